@@ -0,0 +1,144 @@
+//! `#[derive(MysqlRow)]`, for sending structs as the rows of a resultset with
+//! [`msql-srv`](https://docs.rs/msql-srv).
+//!
+//! Use it through the `derive` feature of `msql-srv`, which re-exports it next to the `MysqlRow`
+//! trait:
+//!
+//! ```
+//! use msql_srv::{ColumnFlags, ColumnType, MysqlRow};
+//!
+//! #[derive(MysqlRow)]
+//! #[mysql(table = "users")]
+//! struct User {
+//!     id: u64,
+//!     name: String,
+//!     #[mysql(rename = "email_address")]
+//!     email: Option<String>,
+//! }
+//!
+//! let columns = User::columns();
+//! assert_eq!(columns[0].column, "id");
+//! assert_eq!(columns[0].coltype, ColumnType::MYSQL_TYPE_LONGLONG);
+//! assert_eq!(
+//!     columns[0].colflags,
+//!     ColumnFlags::NOT_NULL_FLAG | ColumnFlags::UNSIGNED_FLAG
+//! );
+//! assert_eq!(columns[2].column, "email_address");
+//! assert_eq!(columns[2].colflags, ColumnFlags::empty());
+//! ```
+#![deny(missing_docs)]
+
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta};
+
+/// Implement `msql_srv::MysqlRow` for a struct with named fields, with one column per field.
+///
+/// The type and flags of each column come from the field's `ToMysqlValue` implementation. Set the
+/// table of the columns with `#[mysql(table = "...")]` on the struct, and give a column a name
+/// other than that of its field with `#[mysql(rename = "...")]` on the field.
+#[proc_macro_derive(MysqlRow, attributes(mysql))]
+pub fn derive_mysql_row(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &data.fields,
+                    "MysqlRow can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "MysqlRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let table = option(&input.attrs, "table")?.unwrap_or_default();
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let column = match option(&field.attrs, "rename")? {
+            Some(column) => column,
+            None => ident.to_string().trim_start_matches("r#").to_owned(),
+        };
+        columns.push(quote! {
+            ::msql_srv::Column {
+                table: ::std::string::String::from(#table),
+                column: ::std::string::String::from(#column),
+                coltype: <#ty as ::msql_srv::ToMysqlValue>::column_type(),
+                colflags: <#ty as ::msql_srv::ToMysqlValue>::column_flags(),
+            }
+        });
+        values.push(quote! {
+            writer.write_col(&self.#ident)?;
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::msql_srv::MysqlRow for #name #ty_generics #where_clause {
+            fn columns() -> ::std::vec::Vec<::msql_srv::Column> {
+                ::std::vec![#(#columns),*]
+            }
+
+            fn write_row<__W: ::std::io::Write>(
+                &self,
+                writer: &mut ::msql_srv::RowWriter<'_, __W>,
+            ) -> ::std::io::Result<()> {
+                #(#values)*
+                writer.end_row()
+            }
+        }
+    })
+}
+
+/// The value of the `#[mysql(key = "...")]` option among `attrs`, if it is given.
+///
+/// Any other option is an error, since each option is only allowed in one place.
+fn option(attrs: &[Attribute], key: &str) -> syn::Result<Option<String>> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("mysql")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(Error::new_spanned(
+                    meta,
+                    format!("expected #[mysql({} = \"...\")]", key),
+                ))
+            }
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(ref option)) if option.path.is_ident(key) => {
+                    match option.lit {
+                        Lit::Str(ref s) => value = Some(s.value()),
+                        ref lit => return Err(Error::new_spanned(lit, "expected a string")),
+                    }
+                }
+                nested => {
+                    return Err(Error::new_spanned(
+                        nested,
+                        format!("expected `{} = \"...\"`", key),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(value)
+}
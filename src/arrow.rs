@@ -0,0 +1,363 @@
+//! Resultsets from Apache Arrow record batches.
+//!
+//! A shim whose backend produces `RecordBatch`es describes the resultset with
+//! [`arrow_columns`](fn.arrow_columns.html), and then sends the batches with
+//! [`RowWriter::write_batch`](struct.RowWriter.html#method.write_batch).
+
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::value::ToMysqlValue;
+use crate::{Column, ColumnDefinition};
+use arrow_array::cast::AsArray;
+use arrow_array::temporal_conversions;
+use arrow_array::types::{
+    Date32Type, Date64Type, Decimal128Type, DecimalType, Float32Type, Float64Type, Int16Type,
+    Int32Type, Int64Type, Int8Type, TimestampMicrosecondType, TimestampMillisecondType,
+    TimestampNanosecondType, TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow_array::{
+    Array, BinaryArray, BooleanArray, Date32Array, Date64Array, Decimal128Array, Float32Array,
+    Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, LargeBinaryArray,
+    LargeStringArray, StringArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+    TimestampNanosecondArray, TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::io::{self, Write};
+
+fn unsupported(field: &str, data_type: &DataType) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "column {} has type {}, which cannot be sent to MySQL clients",
+            field, data_type
+        ),
+    )
+}
+
+/// The columns of a resultset whose rows are those of record batches with the given `schema`,
+/// as columns of table `table`.
+///
+/// Integers, floats, booleans, `Utf8`, `Binary`, `Date32`, `Date64`, `Timestamp` and
+/// `Decimal128` fields can be sent; any other field is an `InvalidData` error. Timestamps become
+/// `DATETIME`s in UTC, with at most microsecond precision. Fields that are not nullable become
+/// `NOT NULL` columns.
+pub fn arrow_columns(schema: &Schema, table: &str) -> io::Result<Vec<ColumnDefinition>> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| column(field, table))
+        .collect()
+}
+
+fn column(field: &Field, table: &str) -> io::Result<ColumnDefinition> {
+    let (coltype, colflags) = match *field.data_type() {
+        DataType::Boolean | DataType::Int8 => (ColumnType::MYSQL_TYPE_TINY, ColumnFlags::empty()),
+        DataType::Int16 => (ColumnType::MYSQL_TYPE_SHORT, ColumnFlags::empty()),
+        DataType::Int32 => (ColumnType::MYSQL_TYPE_LONG, ColumnFlags::empty()),
+        DataType::Int64 => (ColumnType::MYSQL_TYPE_LONGLONG, ColumnFlags::empty()),
+        DataType::UInt8 => (ColumnType::MYSQL_TYPE_TINY, ColumnFlags::UNSIGNED_FLAG),
+        DataType::UInt16 => (ColumnType::MYSQL_TYPE_SHORT, ColumnFlags::UNSIGNED_FLAG),
+        DataType::UInt32 => (ColumnType::MYSQL_TYPE_LONG, ColumnFlags::UNSIGNED_FLAG),
+        DataType::UInt64 => (ColumnType::MYSQL_TYPE_LONGLONG, ColumnFlags::UNSIGNED_FLAG),
+        DataType::Float32 => (ColumnType::MYSQL_TYPE_FLOAT, ColumnFlags::empty()),
+        DataType::Float64 => (ColumnType::MYSQL_TYPE_DOUBLE, ColumnFlags::empty()),
+        DataType::Utf8 | DataType::LargeUtf8 => {
+            (ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::empty())
+        }
+        DataType::Binary | DataType::LargeBinary => (
+            ColumnType::MYSQL_TYPE_BLOB,
+            ColumnFlags::BLOB_FLAG | ColumnFlags::BINARY_FLAG,
+        ),
+        DataType::Date32 | DataType::Date64 => (ColumnType::MYSQL_TYPE_DATE, ColumnFlags::empty()),
+        DataType::Timestamp(..) => (ColumnType::MYSQL_TYPE_DATETIME, ColumnFlags::empty()),
+        DataType::Decimal128(..) => (ColumnType::MYSQL_TYPE_NEWDECIMAL, ColumnFlags::empty()),
+        ref data_type => return Err(unsupported(field.name(), data_type)),
+    };
+    let mut definition = ColumnDefinition::from(Column {
+        table: table.to_owned(),
+        column: field.name().clone(),
+        coltype,
+        colflags,
+    });
+    if !field.is_nullable() {
+        definition.column.colflags |= ColumnFlags::NOT_NULL_FLAG;
+    }
+    match *field.data_type() {
+        DataType::Boolean => definition.column_length = 1,
+        DataType::Binary | DataType::LargeBinary => {
+            definition.character_set = ColumnDefinition::BINARY;
+        }
+        DataType::Timestamp(unit, _) => {
+            definition.decimals = match unit {
+                TimeUnit::Second => 0,
+                TimeUnit::Millisecond => 3,
+                TimeUnit::Microsecond | TimeUnit::Nanosecond => 6,
+            };
+        }
+        DataType::Decimal128(precision, scale) => {
+            let scale = scale.max(0) as u8;
+            // the digits, the sign, and the decimal point if there is one
+            definition.column_length = u32::from(precision) + 1 + u32::from(scale > 0);
+            definition.decimals = scale;
+        }
+        _ => {}
+    }
+    Ok(definition)
+}
+
+/// The values of one column of a record batch, downcast once for all of its rows.
+enum Values<'a> {
+    Boolean(&'a BooleanArray),
+    Int8(&'a Int8Array),
+    Int16(&'a Int16Array),
+    Int32(&'a Int32Array),
+    Int64(&'a Int64Array),
+    UInt8(&'a UInt8Array),
+    UInt16(&'a UInt16Array),
+    UInt32(&'a UInt32Array),
+    UInt64(&'a UInt64Array),
+    Float32(&'a Float32Array),
+    Float64(&'a Float64Array),
+    Utf8(&'a StringArray),
+    LargeUtf8(&'a LargeStringArray),
+    Binary(&'a BinaryArray),
+    LargeBinary(&'a LargeBinaryArray),
+    Date32(&'a Date32Array),
+    Date64(&'a Date64Array),
+    TimestampSecond(&'a TimestampSecondArray),
+    TimestampMillisecond(&'a TimestampMillisecondArray),
+    TimestampMicrosecond(&'a TimestampMicrosecondArray),
+    TimestampNanosecond(&'a TimestampNanosecondArray),
+    Decimal128(&'a Decimal128Array),
+}
+
+/// A column of a record batch that is being sent as part of a resultset.
+pub(crate) struct BatchColumn<'a> {
+    array: &'a dyn Array,
+    values: Values<'a>,
+}
+
+impl<'a> BatchColumn<'a> {
+    pub(crate) fn new(field: &Field, array: &'a dyn Array) -> io::Result<Self> {
+        let values = match *array.data_type() {
+            DataType::Boolean => Values::Boolean(array.as_boolean()),
+            DataType::Int8 => Values::Int8(array.as_primitive::<Int8Type>()),
+            DataType::Int16 => Values::Int16(array.as_primitive::<Int16Type>()),
+            DataType::Int32 => Values::Int32(array.as_primitive::<Int32Type>()),
+            DataType::Int64 => Values::Int64(array.as_primitive::<Int64Type>()),
+            DataType::UInt8 => Values::UInt8(array.as_primitive::<UInt8Type>()),
+            DataType::UInt16 => Values::UInt16(array.as_primitive::<UInt16Type>()),
+            DataType::UInt32 => Values::UInt32(array.as_primitive::<UInt32Type>()),
+            DataType::UInt64 => Values::UInt64(array.as_primitive::<UInt64Type>()),
+            DataType::Float32 => Values::Float32(array.as_primitive::<Float32Type>()),
+            DataType::Float64 => Values::Float64(array.as_primitive::<Float64Type>()),
+            DataType::Utf8 => Values::Utf8(array.as_string::<i32>()),
+            DataType::LargeUtf8 => Values::LargeUtf8(array.as_string::<i64>()),
+            DataType::Binary => Values::Binary(array.as_binary::<i32>()),
+            DataType::LargeBinary => Values::LargeBinary(array.as_binary::<i64>()),
+            DataType::Date32 => Values::Date32(array.as_primitive::<Date32Type>()),
+            DataType::Date64 => Values::Date64(array.as_primitive::<Date64Type>()),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                Values::TimestampSecond(array.as_primitive::<TimestampSecondType>())
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                Values::TimestampMillisecond(array.as_primitive::<TimestampMillisecondType>())
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                Values::TimestampMicrosecond(array.as_primitive::<TimestampMicrosecondType>())
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                Values::TimestampNanosecond(array.as_primitive::<TimestampNanosecondType>())
+            }
+            DataType::Decimal128(..) => Values::Decimal128(array.as_primitive::<Decimal128Type>()),
+            ref data_type => return Err(unsupported(field.name(), data_type)),
+        };
+        Ok(BatchColumn { array, values })
+    }
+
+    /// The value of this column in the given row.
+    pub(crate) fn cell(&self, row: usize) -> io::Result<Cell<'a>> {
+        if self.array.is_null(row) {
+            return Ok(Cell::Null);
+        }
+        Ok(match self.values {
+            Values::Boolean(a) => Cell::Int8(a.value(row) as i8),
+            Values::Int8(a) => Cell::Int8(a.value(row)),
+            Values::Int16(a) => Cell::Int16(a.value(row)),
+            Values::Int32(a) => Cell::Int32(a.value(row)),
+            Values::Int64(a) => Cell::Int64(a.value(row)),
+            Values::UInt8(a) => Cell::UInt8(a.value(row)),
+            Values::UInt16(a) => Cell::UInt16(a.value(row)),
+            Values::UInt32(a) => Cell::UInt32(a.value(row)),
+            Values::UInt64(a) => Cell::UInt64(a.value(row)),
+            Values::Float32(a) => Cell::Float32(a.value(row)),
+            Values::Float64(a) => Cell::Float64(a.value(row)),
+            Values::Utf8(a) => Cell::Bytes(a.value(row).as_bytes()),
+            Values::LargeUtf8(a) => Cell::Bytes(a.value(row).as_bytes()),
+            Values::Binary(a) => Cell::Bytes(a.value(row)),
+            Values::LargeBinary(a) => Cell::Bytes(a.value(row)),
+            Values::Date32(a) => {
+                Cell::Date(in_range(temporal_conversions::date32_to_datetime(a.value(row)))?.date())
+            }
+            Values::Date64(a) => {
+                Cell::Date(in_range(temporal_conversions::date64_to_datetime(a.value(row)))?.date())
+            }
+            Values::TimestampSecond(a) => Cell::DateTime(in_range(
+                temporal_conversions::timestamp_s_to_datetime(a.value(row)),
+            )?),
+            Values::TimestampMillisecond(a) => Cell::DateTime(in_range(
+                temporal_conversions::timestamp_ms_to_datetime(a.value(row)),
+            )?),
+            Values::TimestampMicrosecond(a) => Cell::DateTime(in_range(
+                temporal_conversions::timestamp_us_to_datetime(a.value(row)),
+            )?),
+            Values::TimestampNanosecond(a) => Cell::DateTime(in_range(
+                temporal_conversions::timestamp_ns_to_datetime(a.value(row)),
+            )?),
+            Values::Decimal128(a) => Cell::Decimal(a.value(row), a.precision(), a.scale()),
+        })
+    }
+}
+
+fn in_range(datetime: Option<NaiveDateTime>) -> io::Result<NaiveDateTime> {
+    datetime.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "date or timestamp is out of range",
+        )
+    })
+}
+
+/// A single value of a record batch, with strings and byte strings borrowed from the batch.
+#[derive(Clone, Copy)]
+pub(crate) enum Cell<'a> {
+    Null,
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    UInt8(u8),
+    UInt16(u16),
+    UInt32(u32),
+    UInt64(u64),
+    Float32(f32),
+    Float64(f64),
+    Bytes(&'a [u8]),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Decimal(i128, u8, i8),
+}
+
+/// Evaluate `$e` with `$v` bound to the value of `$cell`, encoded by its own `ToMysqlValue`.
+macro_rules! with_value {
+    ($cell:expr, $v:ident => $e:expr) => {
+        match *$cell {
+            Cell::Null => {
+                let $v = None::<u8>;
+                $e
+            }
+            Cell::Int8($v) => $e,
+            Cell::Int16($v) => $e,
+            Cell::Int32($v) => $e,
+            Cell::Int64($v) => $e,
+            Cell::UInt8($v) => $e,
+            Cell::UInt16($v) => $e,
+            Cell::UInt32($v) => $e,
+            Cell::UInt64($v) => $e,
+            Cell::Float32($v) => $e,
+            Cell::Float64($v) => $e,
+            Cell::Bytes($v) => $e,
+            Cell::Date($v) => $e,
+            Cell::DateTime($v) => $e,
+            Cell::Decimal(value, precision, scale) => {
+                let $v = Decimal128Type::format_decimal(value, precision, scale);
+                $e
+            }
+        }
+    };
+}
+
+impl<'a> ToMysqlValue for Cell<'a> {
+    fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        with_value!(self, v => v.to_mysql_text(w))
+    }
+
+    fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
+        with_value!(self, v => v.to_mysql_bin(w, c))
+    }
+
+    fn is_null(&self) -> bool {
+        matches!(*self, Cell::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Float64Array, RecordBatch};
+    use std::sync::Arc;
+
+    #[test]
+    fn it_maps_schemas() {
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::UInt16, false),
+            Field::new("b", DataType::LargeUtf8, true),
+            Field::new("c", DataType::Decimal128(10, 2), true),
+            Field::new("d", DataType::Decimal128(5, 0), true),
+            Field::new(
+                "e",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+                true,
+            ),
+            Field::new("f", DataType::LargeBinary, true),
+        ]);
+        let columns = arrow_columns(&schema, "t").unwrap();
+
+        assert_eq!(columns[0].column.table, "t");
+        assert_eq!(columns[0].column.column, "a");
+        assert_eq!(columns[0].column.coltype, ColumnType::MYSQL_TYPE_SHORT);
+        assert_eq!(
+            columns[0].column.colflags,
+            ColumnFlags::UNSIGNED_FLAG | ColumnFlags::NOT_NULL_FLAG
+        );
+        assert_eq!(columns[1].column.coltype, ColumnType::MYSQL_TYPE_VAR_STRING);
+        assert_eq!(columns[1].column.colflags, ColumnFlags::empty());
+        assert_eq!(columns[2].column_length, 12);
+        assert_eq!(columns[2].decimals, 2);
+        assert_eq!(columns[3].column_length, 6);
+        assert_eq!(columns[3].decimals, 0);
+        assert_eq!(columns[4].column.coltype, ColumnType::MYSQL_TYPE_DATETIME);
+        assert_eq!(columns[4].decimals, 6);
+        assert_eq!(columns[5].character_set, ColumnDefinition::BINARY);
+    }
+
+    #[test]
+    fn it_rejects_unsupported_types() {
+        let schema = Schema::new(vec![Field::new("a", DataType::Float16, true)]);
+        let e = arrow_columns(&schema, "t").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn it_encodes_cells() {
+        let schema = Schema::new(vec![Field::new("a", DataType::Float64, true)]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(Float64Array::from(vec![Some(0.5), None]))],
+        )
+        .unwrap();
+        let column = BatchColumn::new(batch.schema().field(0), batch.column(0).as_ref()).unwrap();
+
+        let mut text = Vec::new();
+        column.cell(0).unwrap().to_mysql_text(&mut text).unwrap();
+        assert_eq!(text, b"\x030.5");
+        assert!(!column.cell(0).unwrap().is_null());
+        assert!(column.cell(1).unwrap().is_null());
+
+        let decimal = Cell::Decimal(-123, 5, 2);
+        let mut text = Vec::new();
+        decimal.to_mysql_text(&mut text).unwrap();
+        assert_eq!(text, b"\x05-1.23");
+    }
+}
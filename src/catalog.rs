@@ -0,0 +1,797 @@
+//! Answers to the statements that tools send to find out what schemas, tables and columns there
+//! are.
+//!
+//! When a shim describes its database with a [`Catalog`](trait.Catalog.html), `SHOW DATABASES`,
+//! `SHOW TABLES`, `SHOW COLUMNS`, `DESCRIBE`, `SHOW CREATE TABLE` and simple queries against
+//! `information_schema` are answered from it, so that IDEs and BI tools can browse the database.
+
+use crate::compat;
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::sql::{InformationSchemaQuery, Introspection, TableName};
+use crate::{Column, ErrorKind, QueryResultWriter, SystemVariables, VariableScope, VariableValue};
+use std::cmp::Ordering;
+use std::io::{self, Write};
+
+/// Describes the schemas, tables and columns of the database behind a shim.
+///
+/// See [`MysqlShim::catalog`](trait.MysqlShim.html#method.catalog).
+pub trait Catalog {
+    /// The names of all the schemas (databases).
+    fn schemas(&self) -> Vec<String>;
+
+    /// The names of the tables in `schema`, or `None` if there is no such schema.
+    fn tables(&self, schema: &str) -> Option<Vec<String>>;
+
+    /// The columns of table `table` in `schema`, in order, or `None` if there is no such table.
+    ///
+    /// The [`table`](struct.Column.html#structfield.table) of the returned columns is ignored.
+    fn columns(&self, schema: &str, table: &str) -> Option<Vec<Column>>;
+}
+
+/// The columns and rows that answer an introspection statement, or the error it results in.
+pub(crate) type Answer = Result<(Vec<Column>, Vec<Vec<VariableValue>>), (ErrorKind, String)>;
+
+const SCHEMATA: &[&str] = &[
+    "CATALOG_NAME",
+    "SCHEMA_NAME",
+    "DEFAULT_CHARACTER_SET_NAME",
+    "DEFAULT_COLLATION_NAME",
+    "SQL_PATH",
+];
+
+const TABLES: &[&str] = &[
+    "TABLE_CATALOG",
+    "TABLE_SCHEMA",
+    "TABLE_NAME",
+    "TABLE_TYPE",
+    "ENGINE",
+    "TABLE_ROWS",
+    "TABLE_COLLATION",
+    "TABLE_COMMENT",
+];
+
+const COLUMNS: &[&str] = &[
+    "TABLE_CATALOG",
+    "TABLE_SCHEMA",
+    "TABLE_NAME",
+    "COLUMN_NAME",
+    "ORDINAL_POSITION",
+    "COLUMN_DEFAULT",
+    "IS_NULLABLE",
+    "DATA_TYPE",
+    "CHARACTER_MAXIMUM_LENGTH",
+    "NUMERIC_PRECISION",
+    "NUMERIC_SCALE",
+    "CHARACTER_SET_NAME",
+    "COLLATION_NAME",
+    "COLUMN_TYPE",
+    "COLUMN_KEY",
+    "EXTRA",
+    "PRIVILEGES",
+    "COLUMN_COMMENT",
+];
+
+/// The columns of `information_schema` tables that hold numbers rather than strings.
+const NUMERIC: &[&str] = &[
+    "ORDINAL_POSITION",
+    "TABLE_ROWS",
+    "CHARACTER_MAXIMUM_LENGTH",
+    "NUMERIC_PRECISION",
+    "NUMERIC_SCALE",
+];
+
+/// The privileges reported for every column.
+const PRIVILEGES: &str = "select,insert,update,references";
+
+/// Answer `introspection` from `catalog`, where `schema` is the schema the client has switched
+/// to, if any.
+pub(crate) fn answer(
+    introspection: Introspection<'_>,
+    catalog: &dyn Catalog,
+    schema: Option<&str>,
+    vars: &SystemVariables,
+) -> Answer {
+    let charset = Charset::of(vars);
+    match introspection {
+        Introspection::Databases(filter) => Ok((
+            vec![string("Database")],
+            catalog
+                .schemas()
+                .into_iter()
+                .filter(|name| compat::matches(&filter, name))
+                .map(|name| vec![name.into()])
+                .collect(),
+        )),
+        Introspection::Tables {
+            full,
+            schema: from,
+            filter,
+        } => {
+            let schema = from.as_deref().or(schema).ok_or_else(no_database)?;
+            let tables = catalog
+                .tables(schema)
+                .ok_or_else(|| unknown_database(schema))?;
+            let mut cols = vec![string(&format!("Tables_in_{}", schema))];
+            if full {
+                cols.push(string("Table_type"));
+            }
+            let rows = tables
+                .into_iter()
+                .filter(|name| compat::matches(&filter, name))
+                .map(|name| {
+                    let mut row = vec![name.into()];
+                    if full {
+                        row.push("BASE TABLE".into());
+                    }
+                    row
+                })
+                .collect();
+            Ok((cols, rows))
+        }
+        Introspection::Columns {
+            full,
+            table,
+            filter,
+        } => {
+            let (_, columns) = lookup(catalog, &table, schema)?;
+            let mut cols = vec![string("Field"), string("Type")];
+            if full {
+                cols.push(string("Collation"));
+            }
+            cols.extend(
+                ["Null", "Key", "Default", "Extra"]
+                    .iter()
+                    .map(|name| string(name)),
+            );
+            if full {
+                cols.extend(vec![string("Privileges"), string("Comment")]);
+            }
+            let rows = columns
+                .iter()
+                .filter(|c| compat::matches(&filter, &c.column))
+                .map(|c| {
+                    let mut row = vec![c.column.as_str().into(), column_type(c).into()];
+                    if full {
+                        row.push(charset.collation(c));
+                    }
+                    row.extend(vec![
+                        nullable(c).into(),
+                        key(c).into(),
+                        VariableValue::Null,
+                        extra(c).into(),
+                    ]);
+                    if full {
+                        row.extend(vec![PRIVILEGES.into(), "".into()]);
+                    }
+                    row
+                })
+                .collect();
+            Ok((cols, rows))
+        }
+        Introspection::CreateTable(table) => {
+            let (table, columns) = lookup(catalog, &table, schema)?;
+            let create = create_table(table, &columns);
+            Ok((
+                vec![string("Table"), string("Create Table")],
+                vec![vec![table.into(), create.into()]],
+            ))
+        }
+        Introspection::InformationSchema(query) => select(&query, catalog, &charset),
+    }
+}
+
+/// Send the resultset or error that `answer` holds.
+pub(crate) fn write_answer<W: Write>(
+    answer: Answer,
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    match answer {
+        Ok((cols, rows)) => compat::write_rows(&cols, rows, results),
+        Err((kind, msg)) => results.error(kind, msg.as_bytes()),
+    }
+}
+
+/// Look up the columns of `table`, which is in the current schema unless it says otherwise.
+fn lookup<'a>(
+    catalog: &dyn Catalog,
+    table: &'a TableName<'_>,
+    schema: Option<&'a str>,
+) -> Result<(&'a str, Vec<Column>), (ErrorKind, String)> {
+    let schema = table.schema.as_deref().or(schema).ok_or_else(no_database)?;
+    match catalog.columns(schema, &table.table) {
+        Some(columns) => Ok((&table.table, columns)),
+        None => Err((
+            ErrorKind::ER_NO_SUCH_TABLE,
+            format!("Table '{}.{}' doesn't exist", schema, table.table),
+        )),
+    }
+}
+
+/// Answer a `SELECT` from `information_schema`.
+fn select(query: &InformationSchemaQuery<'_>, catalog: &dyn Catalog, charset: &Charset) -> Answer {
+    let names = if query.table.eq_ignore_ascii_case("schemata") {
+        SCHEMATA
+    } else if query.table.eq_ignore_ascii_case("tables") {
+        TABLES
+    } else if query.table.eq_ignore_ascii_case("columns") {
+        COLUMNS
+    } else {
+        return Err((
+            ErrorKind::ER_UNKNOWN_TABLE,
+            format!("Unknown table '{}' in information_schema", query.table),
+        ));
+    };
+    let index = |column: &str, clause: &str| {
+        names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(column))
+            .ok_or_else(|| {
+                (
+                    ErrorKind::ER_BAD_FIELD_ERROR,
+                    format!("Unknown column '{}' in '{}'", column, clause),
+                )
+            })
+    };
+    let selected = match query.columns {
+        Some(ref columns) => columns
+            .iter()
+            .map(|(column, label)| Ok((index(column, "field list")?, label.as_ref())))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => names.iter().copied().enumerate().collect(),
+    };
+    let conditions = query
+        .conditions
+        .iter()
+        .map(|(column, filter)| Ok((index(column, "where clause")?, filter)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let order = query
+        .order
+        .iter()
+        .map(|(column, descending)| {
+            // the columns that rows are ordered by may also be given by their aliases
+            let i = match selected
+                .iter()
+                .find(|(_, label)| label.eq_ignore_ascii_case(column))
+            {
+                Some(&(i, _)) => i,
+                None => index(column, "order clause")?,
+            };
+            Ok((i, *descending))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // skip asking the catalog about schemas and tables that the conditions already rule out
+    let passes = |column: &str, value: &str| {
+        conditions
+            .iter()
+            .all(|&(i, filter)| names[i] != column || compat::matches(filter, value))
+    };
+    let schema_column = if names == SCHEMATA {
+        "SCHEMA_NAME"
+    } else {
+        "TABLE_SCHEMA"
+    };
+    let mut rows = Vec::new();
+    for schema in catalog.schemas() {
+        if !passes(schema_column, &schema) {
+            continue;
+        }
+        if names == SCHEMATA {
+            rows.push(vec![
+                "def".into(),
+                schema.as_str().into(),
+                charset.name.clone(),
+                charset.collation.clone(),
+                VariableValue::Null,
+            ]);
+            continue;
+        }
+        for table in catalog.tables(&schema).unwrap_or_default() {
+            if !passes("TABLE_NAME", &table) {
+                continue;
+            }
+            if names == TABLES {
+                rows.push(vec![
+                    "def".into(),
+                    schema.as_str().into(),
+                    table.as_str().into(),
+                    "BASE TABLE".into(),
+                    VariableValue::Null,
+                    VariableValue::Null,
+                    charset.collation.clone(),
+                    "".into(),
+                ]);
+                continue;
+            }
+            let columns = catalog.columns(&schema, &table).unwrap_or_default();
+            for (position, c) in columns.iter().enumerate() {
+                rows.push(vec![
+                    "def".into(),
+                    schema.as_str().into(),
+                    table.as_str().into(),
+                    c.column.as_str().into(),
+                    (position as u64 + 1).into(),
+                    VariableValue::Null,
+                    nullable(c).into(),
+                    data_type(c).into(),
+                    length(c).into(),
+                    VariableValue::Null,
+                    VariableValue::Null,
+                    charset.name(c),
+                    charset.collation(c),
+                    column_type(c).into(),
+                    key(c).into(),
+                    extra(c).into(),
+                    PRIVILEGES.into(),
+                    "".into(),
+                ]);
+            }
+        }
+    }
+
+    rows.retain(|row| {
+        conditions.iter().all(|&(i, filter)| match text(&row[i]) {
+            Some(value) => compat::matches(filter, &value),
+            None => false,
+        })
+    });
+    rows.sort_by(|a, b| {
+        order
+            .iter()
+            .map(|&(i, descending)| {
+                let ordering = compare(&a[i], &b[i]);
+                if descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|&ordering| ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let cols = selected
+        .iter()
+        .map(|&(i, label)| {
+            if NUMERIC.contains(&names[i]) {
+                number(label)
+            } else {
+                string(label)
+            }
+        })
+        .collect();
+    let rows = rows
+        .into_iter()
+        .map(|row| selected.iter().map(|&(i, _)| row[i].clone()).collect())
+        .collect();
+    Ok((cols, rows))
+}
+
+/// The `CREATE TABLE` statement for a table with the given columns.
+fn create_table(table: &str, columns: &[Column]) -> String {
+    let mut lines: Vec<_> = columns
+        .iter()
+        .map(|c| {
+            let mut line = format!("  {} {}", quote(&c.column), column_type(c));
+            if c.colflags.contains(ColumnFlags::NOT_NULL_FLAG) {
+                line.push_str(" NOT NULL");
+            }
+            if c.colflags.contains(ColumnFlags::AUTO_INCREMENT_FLAG) {
+                line.push_str(" AUTO_INCREMENT");
+            }
+            line
+        })
+        .collect();
+    let keys = |flag| -> Vec<_> {
+        columns
+            .iter()
+            .filter(|c| c.colflags.contains(flag))
+            .map(|c| quote(&c.column))
+            .collect()
+    };
+    let primary = keys(ColumnFlags::PRI_KEY_FLAG);
+    if !primary.is_empty() {
+        lines.push(format!("  PRIMARY KEY ({})", primary.join(",")));
+    }
+    for unique in keys(ColumnFlags::UNIQUE_KEY_FLAG) {
+        lines.push(format!("  UNIQUE KEY {} ({})", unique, unique));
+    }
+    format!("CREATE TABLE {} (\n{}\n)", quote(table), lines.join(",\n"))
+}
+
+/// The character set and collation that text columns are reported to have.
+struct Charset {
+    name: VariableValue,
+    collation: VariableValue,
+}
+
+impl Charset {
+    fn of(vars: &SystemVariables) -> Self {
+        let get = |name| {
+            vars.get(VariableScope::Session, name)
+                .cloned()
+                .unwrap_or(VariableValue::Null)
+        };
+        Charset {
+            name: get("character_set_database"),
+            collation: get("collation_database"),
+        }
+    }
+
+    fn name(&self, column: &Column) -> VariableValue {
+        if is_text(column) {
+            self.name.clone()
+        } else {
+            VariableValue::Null
+        }
+    }
+
+    fn collation(&self, column: &Column) -> VariableValue {
+        if is_text(column) {
+            self.collation.clone()
+        } else {
+            VariableValue::Null
+        }
+    }
+}
+
+/// Whether `column` holds text in some character set, rather than numbers, dates or bytes.
+fn is_text(column: &Column) -> bool {
+    use crate::myc::constants::ColumnType::*;
+    match column.coltype {
+        MYSQL_TYPE_VARCHAR
+        | MYSQL_TYPE_VAR_STRING
+        | MYSQL_TYPE_STRING
+        | MYSQL_TYPE_TINY_BLOB
+        | MYSQL_TYPE_BLOB
+        | MYSQL_TYPE_MEDIUM_BLOB
+        | MYSQL_TYPE_LONG_BLOB
+        | MYSQL_TYPE_ENUM
+        | MYSQL_TYPE_SET => !column.colflags.contains(ColumnFlags::BINARY_FLAG),
+        _ => false,
+    }
+}
+
+/// The name of the type of `column`, as in `information_schema.columns.DATA_TYPE`.
+fn data_type(column: &Column) -> &'static str {
+    use crate::myc::constants::ColumnType::*;
+    let binary = column.colflags.contains(ColumnFlags::BINARY_FLAG);
+    match column.coltype {
+        MYSQL_TYPE_DECIMAL | MYSQL_TYPE_NEWDECIMAL => "decimal",
+        MYSQL_TYPE_TINY => "tinyint",
+        MYSQL_TYPE_SHORT => "smallint",
+        MYSQL_TYPE_INT24 => "mediumint",
+        MYSQL_TYPE_LONG => "int",
+        MYSQL_TYPE_LONGLONG => "bigint",
+        MYSQL_TYPE_FLOAT => "float",
+        MYSQL_TYPE_DOUBLE => "double",
+        MYSQL_TYPE_NULL => "null",
+        MYSQL_TYPE_TIMESTAMP | MYSQL_TYPE_TIMESTAMP2 => "timestamp",
+        MYSQL_TYPE_DATE | MYSQL_TYPE_NEWDATE => "date",
+        MYSQL_TYPE_TIME | MYSQL_TYPE_TIME2 => "time",
+        MYSQL_TYPE_DATETIME | MYSQL_TYPE_DATETIME2 => "datetime",
+        MYSQL_TYPE_YEAR => "year",
+        MYSQL_TYPE_BIT => "bit",
+        MYSQL_TYPE_JSON => "json",
+        MYSQL_TYPE_ENUM => "enum",
+        MYSQL_TYPE_SET => "set",
+        MYSQL_TYPE_GEOMETRY => "geometry",
+        MYSQL_TYPE_VARCHAR | MYSQL_TYPE_VAR_STRING if binary => "varbinary",
+        MYSQL_TYPE_VARCHAR | MYSQL_TYPE_VAR_STRING => "varchar",
+        MYSQL_TYPE_STRING if binary => "binary",
+        MYSQL_TYPE_STRING => "char",
+        MYSQL_TYPE_TINY_BLOB if binary => "tinyblob",
+        MYSQL_TYPE_TINY_BLOB => "tinytext",
+        MYSQL_TYPE_BLOB if binary => "blob",
+        MYSQL_TYPE_BLOB => "text",
+        MYSQL_TYPE_MEDIUM_BLOB if binary => "mediumblob",
+        MYSQL_TYPE_MEDIUM_BLOB => "mediumtext",
+        MYSQL_TYPE_LONG_BLOB if binary => "longblob",
+        MYSQL_TYPE_LONG_BLOB => "longtext",
+    }
+}
+
+/// The maximum length of the values of `column` in characters, if it is a string column that
+/// has one.
+///
+/// [`Column`](struct.Column.html) does not say, so string columns are reported as the longest
+/// `CHAR` there can be.
+fn length(column: &Column) -> Option<u64> {
+    match column.coltype {
+        ColumnType::MYSQL_TYPE_VARCHAR
+        | ColumnType::MYSQL_TYPE_VAR_STRING
+        | ColumnType::MYSQL_TYPE_STRING => Some(255),
+        _ => None,
+    }
+}
+
+/// The full type of `column`, as in `information_schema.columns.COLUMN_TYPE`.
+fn column_type(column: &Column) -> String {
+    let mut column_type = data_type(column).to_owned();
+    if let Some(length) = length(column) {
+        column_type.push_str(&format!("({})", length));
+    }
+    let numeric = match column.coltype {
+        ColumnType::MYSQL_TYPE_DECIMAL
+        | ColumnType::MYSQL_TYPE_NEWDECIMAL
+        | ColumnType::MYSQL_TYPE_FLOAT
+        | ColumnType::MYSQL_TYPE_DOUBLE => true,
+        _ => column.colflags.contains(ColumnFlags::NUM_FLAG) || is_integer(column.coltype),
+    };
+    if numeric && column.colflags.contains(ColumnFlags::UNSIGNED_FLAG) {
+        column_type.push_str(" unsigned");
+    }
+    column_type
+}
+
+fn is_integer(coltype: ColumnType) -> bool {
+    matches!(
+        coltype,
+        ColumnType::MYSQL_TYPE_TINY
+            | ColumnType::MYSQL_TYPE_SHORT
+            | ColumnType::MYSQL_TYPE_INT24
+            | ColumnType::MYSQL_TYPE_LONG
+            | ColumnType::MYSQL_TYPE_LONGLONG
+    )
+}
+
+fn nullable(column: &Column) -> &'static str {
+    if column.colflags.contains(ColumnFlags::NOT_NULL_FLAG) {
+        "NO"
+    } else {
+        "YES"
+    }
+}
+
+fn key(column: &Column) -> &'static str {
+    if column.colflags.contains(ColumnFlags::PRI_KEY_FLAG) {
+        "PRI"
+    } else if column.colflags.contains(ColumnFlags::UNIQUE_KEY_FLAG) {
+        "UNI"
+    } else if column.colflags.contains(ColumnFlags::MULTIPLE_KEY_FLAG) {
+        "MUL"
+    } else {
+        ""
+    }
+}
+
+fn extra(column: &Column) -> &'static str {
+    if column.colflags.contains(ColumnFlags::AUTO_INCREMENT_FLAG) {
+        "auto_increment"
+    } else {
+        ""
+    }
+}
+
+/// Quote `name` with backticks.
+fn quote(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// The value of a column as text, as it is compared in a `WHERE` clause.
+fn text(value: &VariableValue) -> Option<String> {
+    match *value {
+        VariableValue::Null => None,
+        VariableValue::Int(n) => Some(n.to_string()),
+        VariableValue::UInt(n) => Some(n.to_string()),
+        VariableValue::Double(f) => Some(f.to_string()),
+        VariableValue::String(ref s) => Some(s.clone()),
+    }
+}
+
+/// Compare two values of the same column, with `NULL` first and strings ignoring case.
+fn compare(a: &VariableValue, b: &VariableValue) -> Ordering {
+    match (a, b) {
+        (VariableValue::Null, VariableValue::Null) => Ordering::Equal,
+        (VariableValue::Null, _) => Ordering::Less,
+        (_, VariableValue::Null) => Ordering::Greater,
+        (VariableValue::UInt(a), VariableValue::UInt(b)) => a.cmp(b),
+        (VariableValue::String(a), VariableValue::String(b)) => {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        }
+        _ => Ordering::Equal,
+    }
+}
+
+fn string(name: &str) -> Column {
+    Column {
+        table: String::new(),
+        column: name.to_owned(),
+        coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
+        colflags: ColumnFlags::empty(),
+    }
+}
+
+fn number(name: &str) -> Column {
+    Column {
+        table: String::new(),
+        column: name.to_owned(),
+        coltype: ColumnType::MYSQL_TYPE_LONGLONG,
+        colflags: ColumnFlags::UNSIGNED_FLAG,
+    }
+}
+
+fn no_database() -> (ErrorKind, String) {
+    (ErrorKind::ER_NO_DB_ERROR, "No database selected".to_owned())
+}
+
+fn unknown_database(schema: &str) -> (ErrorKind, String) {
+    (
+        ErrorKind::ER_BAD_DB_ERROR,
+        format!("Unknown database '{}'", schema),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse_introspection;
+    use std::collections::BTreeMap;
+
+    struct TestCatalog(BTreeMap<&'static str, BTreeMap<&'static str, Vec<Column>>>);
+
+    impl Catalog for TestCatalog {
+        fn schemas(&self) -> Vec<String> {
+            self.0.keys().map(|s| s.to_string()).collect()
+        }
+
+        fn tables(&self, schema: &str) -> Option<Vec<String>> {
+            Some(self.0.get(schema)?.keys().map(|t| t.to_string()).collect())
+        }
+
+        fn columns(&self, schema: &str, table: &str) -> Option<Vec<Column>> {
+            self.0.get(schema)?.get(table).cloned()
+        }
+    }
+
+    fn column(name: &str, coltype: ColumnType, colflags: ColumnFlags) -> Column {
+        Column {
+            table: String::new(),
+            column: name.to_owned(),
+            coltype,
+            colflags,
+        }
+    }
+
+    fn catalog() -> TestCatalog {
+        let mut shop = BTreeMap::new();
+        shop.insert(
+            "orders",
+            vec![
+                column(
+                    "id",
+                    ColumnType::MYSQL_TYPE_LONGLONG,
+                    ColumnFlags::NOT_NULL_FLAG
+                        | ColumnFlags::PRI_KEY_FLAG
+                        | ColumnFlags::UNSIGNED_FLAG
+                        | ColumnFlags::AUTO_INCREMENT_FLAG,
+                ),
+                column(
+                    "customer",
+                    ColumnType::MYSQL_TYPE_VAR_STRING,
+                    ColumnFlags::empty(),
+                ),
+            ],
+        );
+        shop.insert(
+            "customers",
+            vec![column(
+                "name",
+                ColumnType::MYSQL_TYPE_VAR_STRING,
+                ColumnFlags::NOT_NULL_FLAG | ColumnFlags::UNIQUE_KEY_FLAG,
+            )],
+        );
+        let mut schemas = BTreeMap::new();
+        schemas.insert("shop", shop);
+        schemas.insert("empty", BTreeMap::new());
+        TestCatalog(schemas)
+    }
+
+    fn answer_to(q: &str, schema: Option<&str>) -> Answer {
+        let introspection = parse_introspection(q.as_bytes()).unwrap();
+        answer(
+            introspection,
+            &catalog(),
+            schema,
+            &SystemVariables::default(),
+        )
+    }
+
+    fn rows(q: &str) -> Vec<Vec<VariableValue>> {
+        answer_to(q, Some("shop")).unwrap().1
+    }
+
+    #[test]
+    fn it_shows_schemas_and_tables() {
+        assert_eq!(
+            rows("SHOW DATABASES"),
+            vec![vec!["empty".into()], vec!["shop".into()]]
+        );
+        let (cols, rows) = answer_to("SHOW FULL TABLES LIKE 'o%'", Some("shop")).unwrap();
+        assert_eq!(cols[0].column, "Tables_in_shop");
+        assert_eq!(rows, vec![vec!["orders".into(), "BASE TABLE".into()]]);
+
+        assert_eq!(
+            answer_to("SHOW TABLES", None).unwrap_err().0,
+            ErrorKind::ER_NO_DB_ERROR
+        );
+        assert_eq!(
+            answer_to("SHOW TABLES FROM nope", None).unwrap_err().0,
+            ErrorKind::ER_BAD_DB_ERROR
+        );
+    }
+
+    #[test]
+    fn it_describes_tables() {
+        assert_eq!(
+            rows("DESCRIBE orders"),
+            vec![
+                vec![
+                    "id".into(),
+                    "bigint unsigned".into(),
+                    "NO".into(),
+                    "PRI".into(),
+                    VariableValue::Null,
+                    "auto_increment".into(),
+                ],
+                vec![
+                    "customer".into(),
+                    "varchar(255)".into(),
+                    "YES".into(),
+                    "".into(),
+                    VariableValue::Null,
+                    "".into(),
+                ],
+            ]
+        );
+        assert_eq!(
+            answer_to("SHOW COLUMNS FROM nope", Some("shop"))
+                .unwrap_err()
+                .0,
+            ErrorKind::ER_NO_SUCH_TABLE
+        );
+        assert_eq!(
+            rows("SHOW CREATE TABLE shop.customers")[0][1],
+            "CREATE TABLE `customers` (\n  `name` varchar(255) NOT NULL,\n  UNIQUE KEY `name` (`name`)\n)"
+                .into()
+        );
+    }
+
+    #[test]
+    fn it_selects_from_information_schema() {
+        assert_eq!(
+            rows(
+                "SELECT table_name, COLUMN_NAME, ordinal_position AS pos \
+                 FROM information_schema.columns \
+                 WHERE table_schema = 'shop' ORDER BY table_name DESC, pos"
+            ),
+            vec![
+                vec!["orders".into(), "id".into(), 1u64.into()],
+                vec!["orders".into(), "customer".into(), 2u64.into()],
+                vec!["customers".into(), "name".into(), 1u64.into()],
+            ]
+        );
+        assert_eq!(
+            rows("SELECT * FROM information_schema.schemata WHERE schema_name LIKE 'e%'"),
+            vec![vec![
+                "def".into(),
+                "empty".into(),
+                "utf8".into(),
+                "utf8_general_ci".into(),
+                VariableValue::Null,
+            ]]
+        );
+        assert_eq!(
+            answer_to("SELECT nope FROM information_schema.tables", None)
+                .unwrap_err()
+                .0,
+            ErrorKind::ER_BAD_FIELD_ERROR
+        );
+        assert_eq!(
+            answer_to("SELECT * FROM information_schema.views", None)
+                .unwrap_err()
+                .0,
+            ErrorKind::ER_UNKNOWN_TABLE
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use crate::myc::constants::{CapabilityFlags, Command as CommandByte};
 
+/// MySQL 8's capability flag for query attributes, which `CapabilityFlags` does not know about.
+pub(crate) const CLIENT_QUERY_ATTRIBUTES: u32 = 1 << 27;
+
 #[derive(Debug)]
 pub struct ClientHandshake {
-    capabilities: CapabilityFlags,
+    pub capabilities: CapabilityFlags,
+    pub query_attributes: bool,
     maxps: u32,
     collation: u16,
     pub username: Vec<u8>,
@@ -52,6 +56,7 @@ pub fn client_handshake(i: &[u8]) -> nom::IResult<&[u8], ClientHandshake> {
             i,
             ClientHandshake {
                 capabilities,
+                query_attributes: cap & CLIENT_QUERY_ATTRIBUTES != 0,
                 maxps,
                 collation: u16::from(collation[0]),
                 username,
@@ -80,6 +85,7 @@ pub fn client_handshake(i: &[u8]) -> nom::IResult<&[u8], ClientHandshake> {
             i,
             ClientHandshake {
                 capabilities,
+                query_attributes: false,
                 maxps,
                 collation: 0,
                 username,
@@ -94,12 +100,16 @@ pub fn client_handshake(i: &[u8]) -> nom::IResult<&[u8], ClientHandshake> {
 #[derive(Debug, PartialEq, Eq)]
 pub enum Command<'a> {
     Query(&'a [u8]),
-    ListFields(&'a [u8]),
+    ListFields {
+        table: &'a [u8],
+        wildcard: &'a [u8],
+    },
     Close(u32),
     Prepare(&'a [u8]),
     Init(&'a [u8]),
     Execute {
         stmt: u32,
+        flags: u8,
         params: &'a [u8],
     },
     SendLongData {
@@ -107,15 +117,53 @@ pub enum Command<'a> {
         param: u16,
         data: &'a [u8],
     },
+    BulkExecute {
+        stmt: u32,
+        flags: u16,
+        params: &'a [u8],
+    },
+    SetOption(u16),
+    Statistics,
+    ProcessInfo,
+    ProcessKill(u32),
     Ping,
     Quit,
 }
 
 pub fn execute(i: &[u8]) -> nom::IResult<&[u8], Command<'_>> {
     let (i, stmt) = nom::number::complete::le_u32(i)?;
-    let (i, _flags) = nom::bytes::complete::take(1u8)(i)?;
+    let (i, flags) = nom::number::complete::le_u8(i)?;
     let (i, _iterations) = nom::number::complete::le_u32(i)?;
-    Ok((&[], Command::Execute { stmt, params: i }))
+    Ok((
+        &[],
+        Command::Execute {
+            stmt,
+            flags,
+            params: i,
+        },
+    ))
+}
+
+pub fn list_fields(i: &[u8]) -> nom::IResult<&[u8], Command<'_>> {
+    // the wildcard is optional, and so is the NUL that separates it from the table name
+    let (table, wildcard) = match i.iter().position(|&c| c == 0) {
+        Some(nul) => (&i[..nul], &i[nul + 1..]),
+        None => (i, &[][..]),
+    };
+    Ok((&[], Command::ListFields { table, wildcard }))
+}
+
+pub fn bulk_execute(i: &[u8]) -> nom::IResult<&[u8], Command<'_>> {
+    let (i, stmt) = nom::number::complete::le_u32(i)?;
+    let (i, flags) = nom::number::complete::le_u16(i)?;
+    Ok((
+        &[],
+        Command::BulkExecute {
+            stmt,
+            flags,
+            params: i,
+        },
+    ))
 }
 
 pub fn send_long_data(i: &[u8]) -> nom::IResult<&[u8], Command<'_>> {
@@ -161,10 +209,7 @@ pub fn parse(i: &[u8]) -> nom::IResult<&[u8], Command<'_>> {
             preceded(tag(&[CommandByte::COM_QUERY as u8]), rest),
             Command::Query,
         ),
-        map(
-            preceded(tag(&[CommandByte::COM_FIELD_LIST as u8]), rest),
-            Command::ListFields,
-        ),
+        preceded(tag(&[CommandByte::COM_FIELD_LIST as u8]), list_fields),
         map(
             preceded(tag(&[CommandByte::COM_INIT_DB as u8]), rest),
             Command::Init,
@@ -178,6 +223,8 @@ pub fn parse(i: &[u8]) -> nom::IResult<&[u8], Command<'_>> {
             tag(&[CommandByte::COM_STMT_SEND_LONG_DATA as u8]),
             send_long_data,
         ),
+        // COM_STMT_BULK_EXECUTE, which only MariaDB has
+        preceded(tag(&[0xFA]), bulk_execute),
         map(
             preceded(
                 tag(&[CommandByte::COM_STMT_CLOSE as u8]),
@@ -185,6 +232,26 @@ pub fn parse(i: &[u8]) -> nom::IResult<&[u8], Command<'_>> {
             ),
             Command::Close,
         ),
+        map(
+            preceded(
+                tag(&[CommandByte::COM_SET_OPTION as u8]),
+                nom::number::complete::le_u16,
+            ),
+            Command::SetOption,
+        ),
+        map(tag(&[CommandByte::COM_STATISTICS as u8]), |_| {
+            Command::Statistics
+        }),
+        map(tag(&[CommandByte::COM_PROCESS_INFO as u8]), |_| {
+            Command::ProcessInfo
+        }),
+        map(
+            preceded(
+                tag(&[CommandByte::COM_PROCESS_KILL as u8]),
+                nom::number::complete::le_u32,
+            ),
+            Command::ProcessKill,
+        ),
         map(tag(&[CommandByte::COM_QUIT as u8]), |_| Command::Quit),
         map(tag(&[CommandByte::COM_PING as u8]), |_| Command::Ping),
     ))(i)
@@ -260,7 +327,67 @@ mod tests {
         let (_, cmd) = parse(&p).unwrap();
         assert_eq!(
             cmd,
-            Command::ListFields(&b"select @@version_comment limit 1"[..])
+            Command::ListFields {
+                table: &b"select @@version_comment limit 1"[..],
+                wildcard: &b""[..],
+            }
+        );
+    }
+
+    #[test]
+    fn it_parses_list_fields_wildcard() {
+        let data = &[
+            0x07, 0x00, 0x00, 0x00, 0x04, 0x66, 0x6f, 0x6f, 0x00, 0x62, 0x25,
+        ];
+        let r = Cursor::new(&data[..]);
+        let mut pr = PacketReader::new(r);
+        let (_, p) = pr.next().unwrap().unwrap();
+        let (_, cmd) = parse(&p).unwrap();
+        assert_eq!(
+            cmd,
+            Command::ListFields {
+                table: &b"foo"[..],
+                wildcard: &b"b%"[..],
+            }
+        );
+    }
+
+    #[test]
+    fn it_parses_set_option() {
+        let data = &[0x03, 0x00, 0x00, 0x00, 0x1b, 0x01, 0x00];
+        let r = Cursor::new(&data[..]);
+        let mut pr = PacketReader::new(r);
+        let (_, p) = pr.next().unwrap().unwrap();
+        let (_, cmd) = parse(&p).unwrap();
+        assert_eq!(cmd, Command::SetOption(1));
+    }
+
+    #[test]
+    fn it_parses_process_kill() {
+        let data = &[0x05, 0x00, 0x00, 0x00, 0x0c, 0x2a, 0x00, 0x00, 0x00];
+        let r = Cursor::new(&data[..]);
+        let mut pr = PacketReader::new(r);
+        let (_, p) = pr.next().unwrap().unwrap();
+        let (_, cmd) = parse(&p).unwrap();
+        assert_eq!(cmd, Command::ProcessKill(42));
+    }
+
+    #[test]
+    fn it_parses_bulk_execute() {
+        let data = &[
+            0x0a, 0x00, 0x00, 0x00, 0xfa, 0x01, 0x00, 0x00, 0x00, 0x80, 0x00, 0x08, 0x00, 0x01,
+        ];
+        let r = Cursor::new(&data[..]);
+        let mut pr = PacketReader::new(r);
+        let (_, p) = pr.next().unwrap().unwrap();
+        let (_, cmd) = parse(&p).unwrap();
+        assert_eq!(
+            cmd,
+            Command::BulkExecute {
+                stmt: 1,
+                flags: 128,
+                params: &[0x08, 0x00, 0x01][..],
+            }
         );
     }
 }
@@ -0,0 +1,225 @@
+//! Answers to the statements that client libraries send while they set up a connection.
+//!
+//! Drivers like Connector/J look up server variables, character sets and collations with `SHOW`
+//! statements before the application gets to send its first query. When a shim opts in with
+//! [`MysqlShim::driver_compatibility`](trait.MysqlShim.html#method.driver_compatibility), these
+//! are answered here, consistently with the connection's system variables.
+
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::sql::{Show, ShowFilter};
+use crate::{Column, QueryResultWriter, SystemVariables, VariableValue};
+use std::io::{self, Write};
+
+/// The character sets the server claims to know about, with their description, their default
+/// collation and the maximum length of a character in bytes.
+const CHARACTER_SETS: &[(&str, &str, &str, u64)] = &[
+    ("ascii", "US ASCII", "ascii_general_ci", 1),
+    ("binary", "Binary pseudo charset", "binary", 1),
+    ("latin1", "cp1252 West European", "latin1_swedish_ci", 1),
+    ("utf8", "UTF-8 Unicode", "utf8_general_ci", 3),
+    ("utf8mb4", "UTF-8 Unicode", "utf8mb4_general_ci", 4),
+];
+
+/// The collations the server claims to know about, with their character set and id.
+const COLLATIONS: &[(&str, &str, u64)] = &[
+    ("ascii_bin", "ascii", 65),
+    ("ascii_general_ci", "ascii", 11),
+    ("binary", "binary", 63),
+    ("latin1_bin", "latin1", 47),
+    ("latin1_general_ci", "latin1", 48),
+    ("latin1_swedish_ci", "latin1", 8),
+    ("utf8_bin", "utf8", 83),
+    ("utf8_general_ci", "utf8", 33),
+    ("utf8_unicode_ci", "utf8", 192),
+    ("utf8mb4_bin", "utf8mb4", 46),
+    ("utf8mb4_general_ci", "utf8mb4", 45),
+    ("utf8mb4_unicode_ci", "utf8mb4", 224),
+];
+
+/// The default collation of the character set `charset`, if the server knows about it.
+pub(crate) fn default_collation(charset: &str) -> Option<&'static str> {
+    CHARACTER_SETS
+        .iter()
+        .find(|c| c.0.eq_ignore_ascii_case(charset))
+        .map(|c| c.2)
+}
+
+/// The columns and rows that answer a `SHOW` statement.
+pub(crate) fn show(
+    show: Show,
+    filter: &ShowFilter,
+    vars: &SystemVariables,
+) -> (Vec<Column>, Vec<Vec<VariableValue>>) {
+    let column = |name: &str, coltype, colflags| Column {
+        table: String::new(),
+        column: name.to_owned(),
+        coltype,
+        colflags,
+    };
+    let string = |name| {
+        column(
+            name,
+            ColumnType::MYSQL_TYPE_VAR_STRING,
+            ColumnFlags::empty(),
+        )
+    };
+    let number = |name| {
+        column(
+            name,
+            ColumnType::MYSQL_TYPE_LONGLONG,
+            ColumnFlags::UNSIGNED_FLAG,
+        )
+    };
+
+    match show {
+        Show::Variables(scope) => (
+            vec![string("Variable_name"), string("Value")],
+            vars.iter(scope)
+                .filter(|&(name, _)| matches(filter, name))
+                .map(|(name, value)| {
+                    let value = match *value {
+                        VariableValue::Null => VariableValue::Null,
+                        VariableValue::Int(n) => n.to_string().into(),
+                        VariableValue::UInt(n) => n.to_string().into(),
+                        VariableValue::Double(f) => f.to_string().into(),
+                        VariableValue::String(ref s) => s.as_str().into(),
+                    };
+                    vec![name.into(), value]
+                })
+                .collect(),
+        ),
+        Show::Collation => {
+            let defaults: Vec<_> = CHARACTER_SETS.iter().map(|c| c.2).collect();
+            (
+                vec![
+                    string("Collation"),
+                    string("Charset"),
+                    number("Id"),
+                    string("Default"),
+                    string("Compiled"),
+                    number("Sortlen"),
+                ],
+                COLLATIONS
+                    .iter()
+                    .filter(|&&(name, _, _)| matches(filter, name))
+                    .map(|&(name, charset, id)| {
+                        let default = if defaults.contains(&name) { "Yes" } else { "" };
+                        vec![
+                            name.into(),
+                            charset.into(),
+                            id.into(),
+                            default.into(),
+                            "Yes".into(),
+                            1u64.into(),
+                        ]
+                    })
+                    .collect(),
+            )
+        }
+        Show::CharacterSet => (
+            vec![
+                string("Charset"),
+                string("Description"),
+                string("Default collation"),
+                number("Maxlen"),
+            ],
+            CHARACTER_SETS
+                .iter()
+                .filter(|&&(name, _, _, _)| matches(filter, name))
+                .map(|&(name, description, collation, maxlen)| {
+                    vec![
+                        name.into(),
+                        description.into(),
+                        collation.into(),
+                        maxlen.into(),
+                    ]
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Send a resultset with the given columns and rows.
+pub(crate) fn write_rows<W: Write>(
+    cols: &[Column],
+    rows: Vec<Vec<VariableValue>>,
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    let mut w = results.start(cols)?;
+    for row in rows {
+        w.write_row(row)?;
+    }
+    w.finish()
+}
+
+/// Whether a row with the given name is one that `filter` asks for.
+pub(crate) fn matches(filter: &ShowFilter, name: &str) -> bool {
+    match *filter {
+        ShowFilter::All => true,
+        ShowFilter::Like(ref pattern) => like(pattern.as_bytes(), name.as_bytes()),
+        ShowFilter::Names(ref names) => names.iter().any(|n| n.eq_ignore_ascii_case(name)),
+    }
+}
+
+/// Whether `s` matches the `LIKE` pattern `pattern`, ignoring case.
+///
+/// `%` matches any number of characters, `_` matches exactly one, and a backslash makes the
+/// character after it match only itself.
+fn like(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => s.is_empty(),
+        Some((b'%', rest)) => (0..=s.len()).any(|i| like(rest, &s[i..])),
+        Some((b'_', rest)) => !s.is_empty() && like(rest, &s[1..]),
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            !s.is_empty() && s[0] == rest[0] && like(&rest[1..], &s[1..])
+        }
+        Some((c, rest)) => !s.is_empty() && s[0].eq_ignore_ascii_case(c) && like(rest, &s[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VariableScope;
+
+    #[test]
+    fn it_matches_like_patterns() {
+        assert!(like(b"lower_case%", b"lower_case_table_names"));
+        assert!(like(b"LOWER_CASE%", b"lower_case_table_names"));
+        assert!(like(b"%_names", b"lower_case_table_names"));
+        assert!(like(b"utf8_", b"utf8_"));
+        assert!(like(b"a\\%", b"a%"));
+        assert!(!like(b"a\\%", b"ab"));
+        assert!(!like(b"utf8_", b"utf8"));
+        assert!(!like(b"lower", b"lower_case"));
+    }
+
+    #[test]
+    fn it_shows_variables() {
+        let mut vars = SystemVariables::default();
+        vars.set(VariableScope::Session, "lower_case_table_names", 1u64);
+        let filter = ShowFilter::Like("lower_case%".to_owned());
+
+        let (cols, rows) = show(Show::Variables(VariableScope::Session), &filter, &vars);
+        assert_eq!(cols.len(), 2);
+        assert_eq!(
+            rows,
+            vec![vec!["lower_case_table_names".into(), "1".into()]]
+        );
+        let (_, rows) = show(Show::Variables(VariableScope::Global), &filter, &vars);
+        assert_eq!(
+            rows,
+            vec![vec!["lower_case_table_names".into(), "0".into()]]
+        );
+    }
+
+    #[test]
+    fn it_shows_the_default_collations() {
+        let vars = SystemVariables::default();
+        let filter = ShowFilter::Names(vec!["utf8_general_ci".to_owned()]);
+        let (_, rows) = show(Show::Collation, &filter, &vars);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][2], 33u64.into());
+        assert_eq!(rows[0][3], "Yes".into());
+    }
+}
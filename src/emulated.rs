@@ -0,0 +1,550 @@
+//! Prepared statements for backends that can only execute SQL text.
+
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::{
+    sql, BulkParams, Catalog, Column, DisconnectReason, ErrorKind, InitWriter, MysqlShim,
+    ParamParser, PingWriter, ProcessInfo, QueryResultWriter, SetStatements, StatementMetaWriter,
+    SystemVariables, TransactionOptions, ValueInner, XaHandler,
+};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::str;
+
+/// How the parameters of an emulated statement are described to the client.
+static PARAM: Column = Column {
+    table: String::new(),
+    column: String::new(),
+    coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
+    colflags: ColumnFlags::empty(),
+};
+
+/// Emulates prepared statements for a shim that can only execute SQL text.
+///
+/// Preparing a statement only assigns it an id and notes where its `?` placeholders are (those in
+/// quotes or comments do not count). When the statement is executed, each parameter is rendered
+/// as an escaped SQL literal in place of its placeholder, and the resulting query text can be
+/// given to [`on_query`](trait.MysqlShim.html#tymethod.on_query). A
+/// [`MysqlShim`](trait.MysqlShim.html) can simply be wrapped in an
+/// [`EmulatedShim`](struct.EmulatedShim.html), which does this for it; other shims, such as an
+/// [`AsyncMysqlShim`](trait.AsyncMysqlShim.html), can use `EmulatedStatements` directly:
+///
+/// ```
+/// # use std::io;
+/// use msql_srv::*;
+///
+/// struct Backend {
+///     statements: EmulatedStatements,
+/// }
+///
+/// impl<W: io::Write> MysqlShim<W> for Backend {
+///     type Error = io::Error;
+///
+///     fn on_prepare(&mut self, query: &str, info: StatementMetaWriter<W>) -> io::Result<()> {
+///         self.statements.prepare(query, info)
+///     }
+///     fn on_execute(
+///         &mut self,
+///         id: u32,
+///         params: ParamParser,
+///         results: QueryResultWriter<W>,
+///     ) -> io::Result<()> {
+///         match self.statements.query(id, params) {
+///             Some(query) => self.on_query(&query, results),
+///             None => results.error(ErrorKind::ER_UNKNOWN_STMT_HANDLER, b"unknown statement"),
+///         }
+///     }
+///     fn on_close(&mut self, id: u32) {
+///         self.statements.close(id)
+///     }
+///
+///     fn on_query(&mut self, query: &str, results: QueryResultWriter<W>) -> io::Result<()> {
+///         // hand `query` to the backend here
+///         results.completed(0, 0)
+///     }
+/// }
+/// ```
+///
+/// Quotes in strings are doubled, and backslashes are escaped with a backslash, so the rendered
+/// queries are safe whether or not the backend treats backslashes as escapes. With
+/// `NO_BACKSLASH_ESCAPES`, though, a backslash in a parameter ends up doubled in the value.
+#[derive(Debug, Default)]
+pub struct EmulatedStatements {
+    next_id: u32,
+    statements: HashMap<u32, Vec<String>>,
+}
+
+impl EmulatedStatements {
+    /// Create an empty set of prepared statements.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prepare `query`, replying to the client with a new statement id and a parameter for each of
+    /// the query's placeholders.
+    pub fn prepare<W: Write>(
+        &mut self,
+        query: &str,
+        info: StatementMetaWriter<'_, W>,
+    ) -> io::Result<()> {
+        let fragments: Vec<_> = sql::split_placeholders(query)
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        let params = vec![&PARAM; fragments.len() - 1];
+
+        self.next_id = self.next_id.wrapping_add(1);
+        self.statements.insert(self.next_id, fragments);
+        info.reply(self.next_id, params, &[])
+    }
+
+    /// The query text to execute for statement `id` with the given parameters.
+    ///
+    /// Returns `None` if no statement with that id has been prepared.
+    pub fn query(&self, id: u32, params: ParamParser<'_>) -> Option<String> {
+        let fragments = self.statements.get(&id)?;
+        let mut params = params.into_iter();
+        let mut query = fragments[0].clone();
+        for fragment in &fragments[1..] {
+            match params.next() {
+                Some(param) => write_literal(&mut query, param.value.into_inner()),
+                None => query.push_str("NULL"),
+            }
+            query.push_str(fragment);
+        }
+        Some(query)
+    }
+
+    /// Forget about statement `id`.
+    pub fn close(&mut self, id: u32) {
+        self.statements.remove(&id);
+    }
+}
+
+/// A [`MysqlShim`](trait.MysqlShim.html) that can only execute SQL text, with prepared statements
+/// emulated by [`EmulatedStatements`](struct.EmulatedStatements.html).
+///
+/// The wrapped shim need not implement `on_prepare`, `on_execute` or `on_close`: statements
+/// are prepared by the wrapper, and executing one gives the query text with its parameters
+/// filled in to the wrapped shim's [`on_query`](trait.MysqlShim.html#tymethod.on_query). The
+/// wrapper also takes care of `on_prepare_statement`, and of `on_bulk_execute`, which executes
+/// the statement once for each row. Everything else is passed on to the wrapped shim as-is.
+///
+/// ```no_run
+/// # use std::io;
+/// # use std::net;
+/// use msql_srv::*;
+///
+/// struct Backend;
+///
+/// impl<W: io::Write> MysqlShim<W> for Backend {
+///     type Error = io::Error;
+///
+///     fn on_query(&mut self, query: &str, results: QueryResultWriter<W>) -> io::Result<()> {
+///         // hand `query` to the backend here
+///         results.completed(0, 0)
+///     }
+/// }
+///
+/// let listener = net::TcpListener::bind("127.0.0.1:3306").unwrap();
+/// let (stream, _) = listener.accept().unwrap();
+/// MysqlIntermediary::run_on_tcp(EmulatedShim::new(Backend), stream).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct EmulatedShim<S> {
+    shim: S,
+    statements: EmulatedStatements,
+}
+
+impl<S> EmulatedShim<S> {
+    /// Wrap `shim`, which has no prepared statements of its own.
+    pub fn new(shim: S) -> Self {
+        EmulatedShim {
+            shim,
+            statements: EmulatedStatements::new(),
+        }
+    }
+
+    /// The wrapped shim.
+    pub fn get_ref(&self) -> &S {
+        &self.shim
+    }
+
+    /// The wrapped shim, mutably.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.shim
+    }
+
+    /// Unwrap the shim, forgetting about any statements that are still prepared.
+    pub fn into_inner(self) -> S {
+        self.shim
+    }
+}
+
+// Every hook is implemented here, by emulating it or by passing it on to the wrapped shim, so that
+// none silently falls back to its default; hooks that are added to `MysqlShim` belong here too.
+impl<W: Write, S: MysqlShim<W>> MysqlShim<W> for EmulatedShim<S> {
+    type Error = S::Error;
+
+    fn on_prepare(
+        &mut self,
+        query: &str,
+        info: StatementMetaWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        Ok(self.statements.prepare(query, info)?)
+    }
+
+    fn on_execute(
+        &mut self,
+        id: u32,
+        params: ParamParser<'_>,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        match self.statements.query(id, params) {
+            Some(query) => self.shim.on_query(&query, results),
+            None => Ok(results.error(ErrorKind::ER_UNKNOWN_STMT_HANDLER, b"unknown statement")?),
+        }
+    }
+
+    fn on_bulk_execute(
+        &mut self,
+        id: u32,
+        rows: BulkParams<'_>,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        crate::execute_rows(self, id, rows, results)
+    }
+
+    fn on_close(&mut self, id: u32) {
+        self.statements.close(id)
+    }
+
+    fn on_disconnect(&mut self, reason: DisconnectReason) {
+        self.shim.on_disconnect(reason)
+    }
+
+    fn on_query(
+        &mut self,
+        query: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_query(query, results)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_statement(
+        &mut self,
+        query: &str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_statement(query, statement, results)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_prepare_statement(
+        &mut self,
+        query: &str,
+        _statement: sqlparser::ast::Statement,
+        info: StatementMetaWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_prepare(query, info)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_select(
+        &mut self,
+        query: &str,
+        select: sqlparser::ast::Query,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_select(query, select, results)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_insert(
+        &mut self,
+        query: &str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_insert(query, statement, results)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_update(
+        &mut self,
+        query: &str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_update(query, statement, results)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_delete(
+        &mut self,
+        query: &str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_delete(query, statement, results)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_ddl(
+        &mut self,
+        query: &str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_ddl(query, statement, results)
+    }
+
+    #[cfg(feature = "sqlparser")]
+    fn on_transaction(
+        &mut self,
+        query: &str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_transaction(query, statement, results)
+    }
+
+    fn on_begin(
+        &mut self,
+        query: &str,
+        options: TransactionOptions,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_begin(query, options, results)
+    }
+
+    fn on_commit(
+        &mut self,
+        query: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_commit(query, results)
+    }
+
+    fn on_rollback(
+        &mut self,
+        query: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_rollback(query, results)
+    }
+
+    fn on_savepoint(
+        &mut self,
+        query: &str,
+        name: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_savepoint(query, name, results)
+    }
+
+    fn on_release_savepoint(
+        &mut self,
+        query: &str,
+        name: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_release_savepoint(query, name, results)
+    }
+
+    fn on_rollback_to_savepoint(
+        &mut self,
+        query: &str,
+        name: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.shim.on_rollback_to_savepoint(query, name, results)
+    }
+
+    fn on_init(&mut self, schema: &str, w: InitWriter<'_, W>) -> Result<(), Self::Error> {
+        self.shim.on_init(schema, w)
+    }
+
+    fn on_ping(&mut self, w: PingWriter<'_, W>) -> Result<(), Self::Error> {
+        self.shim.on_ping(w)
+    }
+
+    fn on_list_fields(&mut self, table: &str, wildcard: &str) -> Result<Vec<Column>, Self::Error> {
+        self.shim.on_list_fields(table, wildcard)
+    }
+
+    fn split_multi_statements(&self) -> bool {
+        self.shim.split_multi_statements()
+    }
+
+    fn system_variables(&self) -> SystemVariables {
+        self.shim.system_variables()
+    }
+
+    fn driver_compatibility(&self) -> bool {
+        self.shim.driver_compatibility()
+    }
+
+    fn catalog(&self) -> Option<&dyn Catalog> {
+        self.shim.catalog()
+    }
+
+    fn xa_handler(&mut self) -> Option<&mut dyn XaHandler> {
+        self.shim.xa_handler()
+    }
+
+    fn set_statements(&self) -> SetStatements {
+        self.shim.set_statements()
+    }
+
+    fn on_statistics(&mut self) -> Result<String, Self::Error> {
+        self.shim.on_statistics()
+    }
+
+    fn on_process_info(
+        &mut self,
+        processes: Vec<ProcessInfo>,
+    ) -> Result<Vec<ProcessInfo>, Self::Error> {
+        self.shim.on_process_info(processes)
+    }
+}
+
+/// Append `value` to `query` as an SQL literal.
+fn write_literal(query: &mut String, value: ValueInner<'_>) {
+    match value {
+        ValueInner::NULL => query.push_str("NULL"),
+        ValueInner::Int(n) => write!(query, "{}", n).unwrap(),
+        ValueInner::UInt(n) => write!(query, "{}", n).unwrap(),
+        ValueInner::Double(f) if f.is_finite() => write!(query, "{:?}", f).unwrap(),
+        ValueInner::Double(_) => query.push_str("NULL"),
+        ValueInner::Bytes(b) => match str::from_utf8(b) {
+            Ok(s) => write_string(query, s),
+            Err(_) => {
+                query.push_str("X'");
+                for byte in b {
+                    write!(query, "{:02X}", byte).unwrap();
+                }
+                query.push('\'');
+            }
+        },
+        ValueInner::Date(b) => {
+            let v = padded::<4>(b);
+            let year = u16::from_le_bytes([v[0], v[1]]);
+            write!(query, "'{:04}-{:02}-{:02}'", year, v[2], v[3]).unwrap();
+        }
+        ValueInner::Datetime(b) => {
+            let v = padded::<11>(b);
+            let year = u16::from_le_bytes([v[0], v[1]]);
+            write!(
+                query,
+                "'{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                year, v[2], v[3], v[4], v[5], v[6]
+            )
+            .unwrap();
+            write_micros(query, u32::from_le_bytes([v[7], v[8], v[9], v[10]]));
+        }
+        ValueInner::Time(b) => {
+            let v = padded::<12>(b);
+            let days = u32::from_le_bytes([v[1], v[2], v[3], v[4]]);
+            let sign = if v[0] == 1 { "-" } else { "" };
+            let hours = u64::from(days) * 24 + u64::from(v[5]);
+            write!(query, "'{}{:02}:{:02}:{:02}", sign, hours, v[6], v[7]).unwrap();
+            write_micros(query, u32::from_le_bytes([v[8], v[9], v[10], v[11]]));
+        }
+    }
+}
+
+/// The binary encoding of a temporal value, with the fields it leaves out set to zero.
+fn padded<const N: usize>(b: &[u8]) -> [u8; N] {
+    let mut v = [0; N];
+    let len = b.len().min(N);
+    v[..len].copy_from_slice(&b[..len]);
+    v
+}
+
+/// Append the fractional seconds (if any) and the closing quote of a temporal literal.
+fn write_micros(query: &mut String, micros: u32) {
+    if micros != 0 {
+        write!(query, ".{:06}", micros).unwrap();
+    }
+    query.push('\'');
+}
+
+/// Append `s` to `query` as a quoted string literal.
+fn write_string(query: &mut String, s: &str) {
+    query.push('\'');
+    for c in s.chars() {
+        match c {
+            // a doubled quote ends up as a single one whether or not backslashes are escapes, and
+            // a doubled backslash can then never escape the quote that follows it
+            '\'' => query.push_str("''"),
+            '\\' => query.push_str("\\\\"),
+            c => query.push(c),
+        }
+    }
+    query.push('\'');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(value: ValueInner<'_>) -> String {
+        let mut query = String::new();
+        write_literal(&mut query, value);
+        query
+    }
+
+    #[test]
+    fn it_renders_literals() {
+        assert_eq!(literal(ValueInner::NULL), "NULL");
+        assert_eq!(literal(ValueInner::Int(-42)), "-42");
+        assert_eq!(literal(ValueInner::UInt(u64::MAX)), "18446744073709551615");
+        assert_eq!(literal(ValueInner::Double(1.5)), "1.5");
+        assert_eq!(literal(ValueInner::Double(f64::NAN)), "NULL");
+        assert_eq!(literal(ValueInner::Bytes(b"abc")), "'abc'");
+        assert_eq!(literal(ValueInner::Bytes(&[0xff, 0x00])), "X'FF00'");
+    }
+
+    #[test]
+    fn it_escapes_strings() {
+        assert_eq!(
+            literal(ValueInner::Bytes(b"it's a \"quote\"\\\n")),
+            "'it''s a \"quote\"\\\\\n'"
+        );
+        assert_eq!(
+            literal(ValueInner::Bytes(b"'; DROP TABLE t; --")),
+            "'''; DROP TABLE t; --'"
+        );
+        assert_eq!(
+            literal(ValueInner::Bytes(b"\\'; DROP TABLE t; --")),
+            r"'\\''; DROP TABLE t; --'"
+        );
+    }
+
+    #[test]
+    fn it_renders_temporal_literals() {
+        assert_eq!(
+            literal(ValueInner::Date(&[0xe4, 0x07, 2, 29])),
+            "'2020-02-29'"
+        );
+        assert_eq!(literal(ValueInner::Date(&[])), "'0000-00-00'");
+        assert_eq!(
+            literal(ValueInner::Datetime(&[0xe4, 0x07, 2, 29, 13, 5, 9])),
+            "'2020-02-29 13:05:09'"
+        );
+        assert_eq!(
+            literal(ValueInner::Datetime(&[
+                0xe4, 0x07, 2, 29, 13, 5, 9, 1, 0, 0, 0
+            ])),
+            "'2020-02-29 13:05:09.000001'"
+        );
+        assert_eq!(
+            literal(ValueInner::Time(&[1, 1, 0, 0, 0, 2, 3, 4])),
+            "'-26:03:04'"
+        );
+        assert_eq!(literal(ValueInner::Time(&[])), "'00:00:00'");
+    }
+}
@@ -96,23 +96,43 @@
 extern crate mysql_common as myc;
 
 use std::collections::HashMap;
+use std::future::Future;
 use std::io;
 use std::io::prelude::*;
-use std::iter;
 use std::net;
 use async_trait::async_trait;
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio::io::AsyncRead;
+use tokio::sync::Mutex;
 
+use crate::myc::constants::CapabilityFlags;
+use crate::route::Route;
+use crate::sql::Transaction;
 pub use crate::myc::constants::{ColumnFlags, ColumnType, StatusFlags};
 
+#[cfg(feature = "arrow")]
+mod arrow;
+mod catalog;
 mod commands;
+mod compat;
+mod emulated;
 mod errorcodes;
 mod packet;
 mod params;
+mod registry;
 mod resultset;
+mod route;
+mod row;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sql;
+#[cfg(feature = "sqlparser")]
+mod statement;
 mod value;
+mod variables;
+mod warnings;
 mod writers;
+mod xa;
 
 /// Meta-information abot a single column, used either to describe a prepared statement parameter
 /// or an output column.
@@ -134,10 +154,101 @@ pub struct Column {
     pub colflags: ColumnFlags,
 }
 
+/// A [`Column`](struct.Column.html) along with the rest of the metadata that MySQL sends to
+/// describe it.
+///
+/// A plain `Column` is described with an empty schema and original names, the `utf8` character
+/// set, a display length of 1024 and no decimals. Where that is not good enough, such as for
+/// binary strings, `DECIMAL(10,2)` or `DATETIME(6)`, start from the column and fill in the rest:
+///
+/// ```
+/// # use msql_srv::*;
+/// let price = ColumnDefinition {
+///     schema: "shop".to_owned(),
+///     org_table: "orders".to_owned(),
+///     org_name: "price".to_owned(),
+///     character_set: ColumnDefinition::BINARY,
+///     column_length: 12,
+///     decimals: 2,
+///     ..ColumnDefinition::from(Column {
+///         table: "o".to_owned(),
+///         column: "price".to_owned(),
+///         coltype: ColumnType::MYSQL_TYPE_NEWDECIMAL,
+///         colflags: ColumnFlags::empty(),
+///     })
+/// };
+/// ```
+///
+/// Full definitions are given to the client with
+/// [`QueryResultWriter::start_with_definitions`](struct.QueryResultWriter.html#method.start_with_definitions)
+/// and
+/// [`StatementMetaWriter::reply_with_definitions`](struct.StatementMetaWriter.html#method.reply_with_definitions).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDefinition {
+    /// The column's name, table, type and flags.
+    pub column: Column,
+    /// The schema of the table that the column comes from.
+    pub schema: String,
+    /// The name of the table that the column comes from, rather than its alias.
+    pub org_table: String,
+    /// The name of the column in its table, rather than its alias.
+    pub org_name: String,
+    /// The id of the collation of the column's values, which is
+    /// [`BINARY`](struct.ColumnDefinition.html#associatedconstant.BINARY) for binary strings and
+    /// for columns that do not hold strings.
+    pub character_set: u16,
+    /// The maximum length of the column's values, in bytes.
+    pub column_length: u32,
+    /// The number of digits after the decimal point of `DECIMAL` and floating-point columns, or
+    /// the fractional seconds precision of temporal columns.
+    pub decimals: u8,
+}
+
+impl ColumnDefinition {
+    /// The id of the `binary` collation.
+    pub const BINARY: u16 = 63;
+}
+
+impl From<Column> for ColumnDefinition {
+    fn from(column: Column) -> Self {
+        ColumnDefinition {
+            column,
+            schema: String::new(),
+            org_table: String::new(),
+            org_name: String::new(),
+            character_set: myc::constants::UTF8_GENERAL_CI,
+            column_length: 1024,
+            decimals: 0,
+        }
+    }
+}
+
+#[cfg(feature = "arrow")]
+pub use crate::arrow::arrow_columns;
+pub use crate::catalog::Catalog;
+pub use crate::emulated::{EmulatedShim, EmulatedStatements};
 pub use crate::errorcodes::ErrorKind;
-pub use crate::params::{ParamParser, ParamValue, Params};
-pub use crate::resultset::{InitWriter, QueryResultWriter, RowWriter, StatementMetaWriter};
+pub use crate::params::{BulkParams, ParamParser, ParamValue, Params, QueryAttribute};
+pub use crate::registry::{CancellationToken, ProcessInfo, ServerStatistics};
+pub use crate::resultset::{
+    Completion, InitWriter, LocalFile, PingWriter, QueryResultWriter, RowWriter,
+    StatementMetaWriter,
+};
+pub use crate::row::MysqlRow;
 pub use crate::value::{ToMysqlValue, Value, ValueInner};
+pub use crate::variables::{SetStatements, SystemVariables, VariableScope, VariableValue};
+pub use crate::warnings::WarningLevel;
+pub use crate::xa::{XaHandler, Xid};
+#[cfg(feature = "arrow")]
+pub use arrow_array;
+#[cfg(feature = "arrow")]
+pub use arrow_schema;
+#[cfg(feature = "derive")]
+pub use msql_srv_derive::MysqlRow;
+#[cfg(feature = "sqlparser")]
+pub use sqlparser;
+#[cfg(feature = "sqlparser")]
+use crate::statement::Routed;
 use std::io::Cursor;
 use crate::writers::write_handshake_packet;
 use nom::AsBytes;
@@ -154,28 +265,64 @@ pub trait MysqlShim<W: Write> {
     ///
     /// The provided [`StatementMetaWriter`](struct.StatementMetaWriter.html) should be used to
     /// notify the client of the statement id assigned to the prepared statement, as well as to
-    /// give metadata about the types of parameters and returned columns.
+    /// give metadata about the types of parameters and returned columns. By default, the client is
+    /// told that prepared statements are not supported; see
+    /// [`EmulatedShim`](struct.EmulatedShim.html) for a shim that can only execute SQL text.
     fn on_prepare(
         &mut self,
-        query: &str,
+        _query: &str,
         info: StatementMetaWriter<'_, W>,
-    ) -> Result<(), Self::Error>;
+    ) -> Result<(), Self::Error> {
+        Ok(info.error(
+            ErrorKind::ER_UNSUPPORTED_PS,
+            b"prepared statements are not supported",
+        )?)
+    }
 
     /// Called when the client executes a previously prepared statement.
     ///
     /// Any parameters included with the client's command is given in `params`.
     /// A response to the query should be given using the provided
-    /// [`QueryResultWriter`](struct.QueryResultWriter.html).
+    /// [`QueryResultWriter`](struct.QueryResultWriter.html). By default, the statement is
+    /// reported as unknown.
     fn on_execute(
+        &mut self,
+        _id: u32,
+        _params: ParamParser<'_>,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        Ok(results.error(ErrorKind::ER_UNKNOWN_STMT_HANDLER, b"unknown statement")?)
+    }
+
+    /// Called when the client executes a previously prepared statement once for each of the given
+    /// rows of parameters (MariaDB's `COM_STMT_BULK_EXECUTE`), as MariaDB connectors do for batched
+    /// inserts.
+    ///
+    /// The client expects a single response for all the rows, such as an OK with the total number
+    /// of affected rows, or an error. By default,
+    /// [`on_execute`](trait.MysqlShim.html#method.on_execute) is called once for each row, and
+    /// the rows it reports as affected are added up. Execution stops at the first row that results
+    /// in an error.
+    fn on_bulk_execute(
         &mut self,
         id: u32,
-        params: ParamParser<'_>,
+        rows: BulkParams<'_>,
         results: QueryResultWriter<'_, W>,
-    ) -> Result<(), Self::Error>;
+    ) -> Result<(), Self::Error> {
+        execute_rows(self, id, rows, results)
+    }
 
     /// Called when the client wishes to deallocate resources associated with a previously prepared
-    /// statement.
-    fn on_close(&mut self, stmt: u32);
+    /// statement. By default, nothing happens.
+    fn on_close(&mut self, _stmt: u32) {}
+
+    /// Called once the connection to the client has ended, for whatever `reason`.
+    ///
+    /// This is called whether the connection ended cleanly or not, including when it ends before
+    /// the client has logged in, and right after
+    /// [`on_close`](trait.MysqlShim.html#method.on_close) has been called for every prepared
+    /// statement that the client left open. By default, nothing happens.
+    fn on_disconnect(&mut self, _reason: DisconnectReason) {}
 
     /// Called when the client issues a query for immediate execution.
     ///
@@ -187,10 +334,382 @@ pub trait MysqlShim<W: Write> {
         results: QueryResultWriter<'_, W>,
     ) -> Result<(), Self::Error>;
 
+    /// Called instead of [`on_query`](trait.MysqlShim.html#tymethod.on_query) for queries that
+    /// parse as a single MySQL statement.
+    ///
+    /// `query` is the original query text, and `statement` is what it parsed into. By default,
+    /// the statement is routed to [`on_select`](trait.MysqlShim.html#method.on_select),
+    /// [`on_insert`](trait.MysqlShim.html#method.on_insert),
+    /// [`on_update`](trait.MysqlShim.html#method.on_update),
+    /// [`on_delete`](trait.MysqlShim.html#method.on_delete),
+    /// [`on_ddl`](trait.MysqlShim.html#method.on_ddl) or
+    /// [`on_transaction`](trait.MysqlShim.html#method.on_transaction), depending on its kind,
+    /// and to `on_query` if it is none of those. Queries that do not parse always go to
+    /// `on_query`.
+    #[cfg(feature = "sqlparser")]
+    fn on_statement(
+        &mut self,
+        query: &str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        match statement::route(statement) {
+            Routed::Select(select) => self.on_select(query, select, results),
+            Routed::Insert(statement) => self.on_insert(query, statement, results),
+            Routed::Update(statement) => self.on_update(query, statement, results),
+            Routed::Delete(statement) => self.on_delete(query, statement, results),
+            Routed::Ddl(statement) => self.on_ddl(query, statement, results),
+            Routed::Transaction(statement) => self.on_transaction(query, statement, results),
+            Routed::Other => self.on_query(query, results),
+        }
+    }
+
+    /// Called instead of [`on_prepare`](trait.MysqlShim.html#method.on_prepare) for queries to
+    /// prepare that parse as a single MySQL statement, with `?` placeholders for the parameters.
+    ///
+    /// `query` is the original query text, and `statement` is what it parsed into, so that a shim
+    /// that works with parsed statements does not have to parse queries to prepare itself. By
+    /// default, `query` is given to `on_prepare`. Queries that do not parse always go to
+    /// `on_prepare`.
+    #[cfg(feature = "sqlparser")]
+    fn on_prepare_statement(
+        &mut self,
+        query: &str,
+        _statement: sqlparser::ast::Statement,
+        info: StatementMetaWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_prepare(query, info)
+    }
+
+    /// Called for a query (`SELECT`, but also `WITH`, `VALUES` or a `UNION` of several) that has
+    /// been parsed by [`on_statement`](trait.MysqlShim.html#method.on_statement).
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    #[cfg(feature = "sqlparser")]
+    fn on_select(
+        &mut self,
+        query: &str,
+        _select: sqlparser::ast::Query,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called for an `INSERT` or `REPLACE` statement that has been parsed by
+    /// [`on_statement`](trait.MysqlShim.html#method.on_statement).
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    #[cfg(feature = "sqlparser")]
+    fn on_insert(
+        &mut self,
+        query: &str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called for an `UPDATE` statement that has been parsed by
+    /// [`on_statement`](trait.MysqlShim.html#method.on_statement).
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    #[cfg(feature = "sqlparser")]
+    fn on_update(
+        &mut self,
+        query: &str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called for a `DELETE` statement that has been parsed by
+    /// [`on_statement`](trait.MysqlShim.html#method.on_statement).
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    #[cfg(feature = "sqlparser")]
+    fn on_delete(
+        &mut self,
+        query: &str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called for a statement that changes the schema (`CREATE`, `ALTER`, `DROP` or `TRUNCATE`)
+    /// that has been parsed by [`on_statement`](trait.MysqlShim.html#method.on_statement).
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    #[cfg(feature = "sqlparser")]
+    fn on_ddl(
+        &mut self,
+        query: &str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called for a transaction control statement (such as `SET TRANSACTION`, or `COMMIT AND
+    /// CHAIN`) that has been parsed by
+    /// [`on_statement`](trait.MysqlShim.html#method.on_statement).
+    ///
+    /// The statements that [`on_begin`](trait.MysqlShim.html#method.on_begin) and the other
+    /// transaction hooks are called for never get here. By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    #[cfg(feature = "sqlparser")]
+    fn on_transaction(
+        &mut self,
+        query: &str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called when the client starts a transaction with `BEGIN` or `START TRANSACTION`.
+    ///
+    /// `options` holds the characteristics that `START TRANSACTION` asked for. Once the statement
+    /// has succeeded, the client is told that a transaction is open (`SERVER_STATUS_IN_TRANS`)
+    /// until it is committed or rolled back. By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    fn on_begin(
+        &mut self,
+        query: &str,
+        _options: TransactionOptions,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called when the client commits the open transaction with `COMMIT`.
+    ///
+    /// While `autocommit` is off, any statement that reaches the shim outside of a transaction
+    /// opens one, as in MySQL. Turning `autocommit` back on commits it too: with
+    /// [`SetStatements::Apply`](enum.SetStatements.html), the `SET` statement that does so is
+    /// given to this method, and its assignments are undone if the commit fails. By default, the
+    /// query text is given to [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    fn on_commit(
+        &mut self,
+        query: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called when the client rolls back the open transaction with `ROLLBACK`.
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    fn on_rollback(
+        &mut self,
+        query: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called when the client sets a savepoint called `name` with `SAVEPOINT`.
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    fn on_savepoint(
+        &mut self,
+        query: &str,
+        _name: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called when the client removes the savepoint called `name` with `RELEASE SAVEPOINT`.
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    fn on_release_savepoint(
+        &mut self,
+        query: &str,
+        _name: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
+    /// Called when the client rolls the open transaction back to the savepoint called `name` with
+    /// `ROLLBACK TO SAVEPOINT`. The transaction stays open.
+    ///
+    /// By default, the query text is given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query).
+    fn on_rollback_to_savepoint(
+        &mut self,
+        query: &str,
+        _name: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results)
+    }
+
     /// Called when client switches database.
     fn on_init(&mut self, _: &str, _: InitWriter<'_, W>) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    /// Called when the client pings the server (`COM_PING`), as connection pools do to check that
+    /// a connection is still healthy.
+    ///
+    /// By default, the server replies that it is alive. A shim whose backend is unavailable can
+    /// instead reply with an error such as `ErrorKind::ER_SERVER_SHUTDOWN`, or return an error to
+    /// close the connection.
+    fn on_ping(&mut self, w: PingWriter<'_, W>) -> Result<(), Self::Error> {
+        Ok(w.ok()?)
+    }
+
+    /// Called when the client asks for the columns of `table` (`COM_FIELD_LIST`), as the `mysql`
+    /// command-line client does for tab completion after switching databases.
+    ///
+    /// `wildcard` is a `LIKE` pattern that the returned column names should match, and is empty if
+    /// the client asked for all the columns. By default, no columns are returned.
+    fn on_list_fields(
+        &mut self,
+        _table: &str,
+        _wildcard: &str,
+    ) -> Result<Vec<Column>, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    /// Whether queries that hold several `;`-separated statements should be split up, with
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query) called once per statement.
+    ///
+    /// If this returns `true`, the server advertises `CLIENT_MULTI_STATEMENTS`, and splitting is
+    /// enabled for clients that ask for it. Clients can also turn splitting on and off later
+    /// with `COM_SET_OPTION`; if this returns `false`, turning it on fails instead. The results
+    /// for the individual statements are sent to the client as consecutive resultsets, and
+    /// execution stops at the first statement that results in an error. By default, the whole
+    /// query text is given to `on_query` as-is.
+    fn split_multi_statements(&self) -> bool {
+        false
+    }
+
+    /// The system variables a new connection starts out with.
+    ///
+    /// Queries that select nothing but system variables, like
+    /// `SELECT @@session.auto_increment_increment, @@global.time_zone`, are answered from these
+    /// directly, and never reach [`on_query`](trait.MysqlShim.html#tymethod.on_query). By
+    /// default, a connection gets the common MySQL variables with their usual defaults.
+    fn system_variables(&self) -> SystemVariables {
+        SystemVariables::default()
+    }
+
+    /// Whether the queries that client libraries send while they set up a connection are
+    /// answered by the server.
+    ///
+    /// If this returns `true`, `SHOW VARIABLES`, `SHOW COLLATION` and `SHOW CHARACTER SET`
+    /// (optionally with a `LIKE` pattern or a `WHERE` clause that picks rows by name) are answered
+    /// from the session's [`SystemVariables`](struct.SystemVariables.html) and a built-in list of
+    /// the common character sets, and never reach
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query). Together with the `SELECT @@name`
+    /// queries, which are always answered by the server, and
+    /// [`set_statements`](trait.MysqlShim.html#method.set_statements), this covers what the
+    /// common drivers need before they send the first application query.
+    fn driver_compatibility(&self) -> bool {
+        false
+    }
+
+    /// A description of the schemas, tables and columns of the database behind this shim, if
+    /// there is one.
+    ///
+    /// If a [`Catalog`](trait.Catalog.html) is returned, `SHOW DATABASES`, `SHOW TABLES`,
+    /// `SHOW COLUMNS`, `DESCRIBE`, `SHOW CREATE TABLE` and simple `SELECT`s from
+    /// `information_schema.schemata`, `tables` and `columns` are answered from it, and never reach
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query). By default, there is none.
+    fn catalog(&self) -> Option<&dyn Catalog> {
+        None
+    }
+
+    /// The handler for the `XA` statements of distributed transactions, if this shim takes part in
+    /// them.
+    ///
+    /// If an [`XaHandler`](trait.XaHandler.html) is returned, `XA START`, `XA END`,
+    /// `XA PREPARE`, `XA COMMIT`, `XA ROLLBACK` and `XA RECOVER` are carried out with it, and never
+    /// reach [`on_query`](trait.MysqlShim.html#tymethod.on_query). By default, there is none.
+    fn xa_handler(&mut self) -> Option<&mut dyn XaHandler> {
+        None
+    }
+
+    /// How `SET` statements that assign system variables are dealt with.
+    ///
+    /// Drivers typically send a few of these, like `SET NAMES utf8mb4` or `SET autocommit = 0`,
+    /// right after they connect. With [`SetStatements::Apply`](enum.SetStatements.html), the
+    /// server keeps track of them in the session's variables, so that `SELECT @@name` and the
+    /// status flags reflect them, and `on_query` never sees them. By default, they are given to
+    /// [`on_query`](trait.MysqlShim.html#tymethod.on_query) like any other query.
+    fn set_statements(&self) -> SetStatements {
+        SetStatements::Forward
+    }
+
+    /// Called when the client asks for a human-readable summary of the server's status
+    /// (`COM_STATISTICS`, as sent by `mysqladmin status`).
+    ///
+    /// The returned string is sent to the client as-is. By default, it is built from the
+    /// server-wide [`ServerStatistics`](struct.ServerStatistics.html).
+    fn on_statistics(&mut self) -> Result<String, Self::Error> {
+        Ok(ServerStatistics::current().to_string())
+    }
+
+    /// Called when the client asks for the list of connected clients (`COM_PROCESS_INFO`).
+    ///
+    /// `processes` holds the connections that are currently open in this process for the user
+    /// that the client logged in as, and the returned list is what is sent to the client. By
+    /// default, `processes` is sent unchanged; a shim that lets the client see the connections of
+    /// other users too can return [`ProcessInfo::list`](struct.ProcessInfo.html#method.list)
+    /// instead.
+    ///
+    /// Whatever is listed here, a client can only `KILL` connections of its own user, and gets
+    /// `ER_KILL_DENIED_ERROR` for those of others.
+    fn on_process_info(
+        &mut self,
+        processes: Vec<ProcessInfo>,
+    ) -> Result<Vec<ProcessInfo>, Self::Error> {
+        Ok(processes)
+    }
+}
+
+/// Execute statement `id` with [`MysqlShim::on_execute`](trait.MysqlShim.html#method.on_execute)
+/// once for each of `rows`, and reply with the total number of affected rows, stopping at the
+/// first row that results in an error.
+pub(crate) fn execute_rows<W: Write, S: MysqlShim<W> + ?Sized>(
+    shim: &mut S,
+    id: u32,
+    mut rows: BulkParams<'_>,
+    mut results: QueryResultWriter<'_, W>,
+) -> Result<(), S::Error> {
+    let mut encoded = Vec::new();
+    let mut bound_types = Vec::new();
+    let long_data = HashMap::new();
+    results.state.bulk = Some((0, 0));
+    while rows.next_encoded(&mut encoded) {
+        let params = ParamParser {
+            params: rows.params,
+            bytes: &encoded,
+            long_data: &long_data,
+            bound_types: &mut bound_types,
+        };
+        shim.on_execute(id, params, results.reborrow())?;
+        if results.state.errored {
+            break;
+        }
+    }
+    let (affected, last_insert_id) = results.state.bulk.take().unwrap();
+    if results.state.errored {
+        return Ok(());
+    }
+    Ok(results.completed(affected, last_insert_id)?)
 }
 
 #[async_trait]
@@ -205,46 +724,390 @@ pub trait AsyncMysqlShim<W: Write + Send> {
     ///
     /// The provided [`StatementMetaWriter`](struct.StatementMetaWriter.html) should be used to
     /// notify the client of the statement id assigned to the prepared statement, as well as to
-    /// give metadata about the types of parameters and returned columns.
+    /// give metadata about the types of parameters and returned columns. By default, the client is
+    /// told that prepared statements are not supported; see
+    /// [`EmulatedStatements`](struct.EmulatedStatements.html) for a shim that can only execute SQL
+    /// text.
     async fn on_prepare<'a>(
         &'a mut self,
-        query: &'a str,
+        _query: &'a str,
         info: StatementMetaWriter<'a, W>,
-    ) -> Result<(), Self::Error>;
+    ) -> Result<(), Self::Error> {
+        Ok(info.error(
+            ErrorKind::ER_UNSUPPORTED_PS,
+            b"prepared statements are not supported",
+        )?)
+    }
 
     /// Called when the client executes a previously prepared statement.
     ///
     /// Any parameters included with the client's command is given in `params`.
     /// A response to the query should be given using the provided
-    /// [`QueryResultWriter`](struct.QueryResultWriter.html).
+    /// [`QueryResultWriter`](struct.QueryResultWriter.html). By default, the statement is
+    /// reported as unknown.
+    ///
+    /// If the client cancels the statement (e.g., with `KILL QUERY`), the returned future is
+    /// dropped, and the client is told that the statement was interrupted.
     async fn on_execute<'a>(
         &'a mut self,
-        id: u32,
-        params: ParamParser<'a>,
+        _id: u32,
+        _params: ParamParser<'a>,
         results: QueryResultWriter<'a, W>,
-    ) -> Result<(), Self::Error>;
+    ) -> Result<(), Self::Error> {
+        Ok(results.error(ErrorKind::ER_UNKNOWN_STMT_HANDLER, b"unknown statement")?)
+    }
+
+    /// Called when the client executes a previously prepared statement once for each of the given
+    /// rows of parameters (MariaDB's `COM_STMT_BULK_EXECUTE`).
+    ///
+    /// See [`MysqlShim::on_bulk_execute`](trait.MysqlShim.html#method.on_bulk_execute).
+    async fn on_bulk_execute<'a>(
+        &'a mut self,
+        id: u32,
+        mut rows: BulkParams<'a>,
+        mut results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        let mut encoded = Vec::new();
+        let mut bound_types = Vec::new();
+        let long_data = HashMap::new();
+        results.state.bulk = Some((0, 0));
+        while rows.next_encoded(&mut encoded) {
+            let params = ParamParser {
+                params: rows.params,
+                bytes: &encoded,
+                long_data: &long_data,
+                bound_types: &mut bound_types,
+            };
+            self.on_execute(id, params, results.reborrow()).await?;
+            if results.state.errored {
+                break;
+            }
+        }
+        let (affected, last_insert_id) = results.state.bulk.take().unwrap();
+        if results.state.errored {
+            return Ok(());
+        }
+        Ok(results.completed(affected, last_insert_id)?)
+    }
 
     /// Called when the client wishes to deallocate resources associated with a previously prepared
-    /// statement.
-    async fn on_close<'a>(&'a mut self, stmt: u32)
+    /// statement. By default, nothing happens.
+    async fn on_close<'a>(&'a mut self, _stmt: u32)
         where
-            W: 'async_trait;
+            W: 'async_trait
+    {
+    }
+
+    /// Called once the connection to the client has ended, for whatever `reason`.
+    ///
+    /// See [`MysqlShim::on_disconnect`](trait.MysqlShim.html#method.on_disconnect).
+    async fn on_disconnect<'a>(&'a mut self, _reason: DisconnectReason)
+        where
+            W: 'async_trait
+    {
+    }
 
     /// Called when the client issues a query for immediate execution.
     ///
     /// Results should be returned using the given
     /// [`QueryResultWriter`](struct.QueryResultWriter.html).
+    ///
+    /// If the client cancels the query (e.g., with `KILL QUERY`), the returned future is dropped,
+    /// and the client is told that the query was interrupted.
     async fn on_query<'a>(
         &'a mut self,
         query: &'a str,
         results: QueryResultWriter<'a, W>,
     ) -> Result<(), Self::Error>;
 
+    /// Called instead of [`on_query`](trait.AsyncMysqlShim.html#tymethod.on_query) for queries
+    /// that parse as a single MySQL statement.
+    ///
+    /// See [`MysqlShim::on_statement`](trait.MysqlShim.html#method.on_statement).
+    #[cfg(feature = "sqlparser")]
+    async fn on_statement<'a>(
+        &'a mut self,
+        query: &'a str,
+        statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        match statement::route(statement) {
+            Routed::Select(select) => self.on_select(query, select, results).await,
+            Routed::Insert(statement) => self.on_insert(query, statement, results).await,
+            Routed::Update(statement) => self.on_update(query, statement, results).await,
+            Routed::Delete(statement) => self.on_delete(query, statement, results).await,
+            Routed::Ddl(statement) => self.on_ddl(query, statement, results).await,
+            Routed::Transaction(statement) => self.on_transaction(query, statement, results).await,
+            Routed::Other => self.on_query(query, results).await,
+        }
+    }
+
+    /// Called instead of [`on_prepare`](trait.AsyncMysqlShim.html#method.on_prepare) for
+    /// queries to prepare that parse as a single MySQL statement.
+    ///
+    /// See [`MysqlShim::on_prepare_statement`](trait.MysqlShim.html#method.on_prepare_statement).
+    #[cfg(feature = "sqlparser")]
+    async fn on_prepare_statement<'a>(
+        &'a mut self,
+        query: &'a str,
+        _statement: sqlparser::ast::Statement,
+        info: StatementMetaWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_prepare(query, info).await
+    }
+
+    /// Called for a query that has been parsed by
+    /// [`on_statement`](trait.AsyncMysqlShim.html#method.on_statement).
+    ///
+    /// See [`MysqlShim::on_select`](trait.MysqlShim.html#method.on_select).
+    #[cfg(feature = "sqlparser")]
+    async fn on_select<'a>(
+        &'a mut self,
+        query: &'a str,
+        _select: sqlparser::ast::Query,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called for an `INSERT` or `REPLACE` statement that has been parsed by
+    /// [`on_statement`](trait.AsyncMysqlShim.html#method.on_statement).
+    ///
+    /// See [`MysqlShim::on_insert`](trait.MysqlShim.html#method.on_insert).
+    #[cfg(feature = "sqlparser")]
+    async fn on_insert<'a>(
+        &'a mut self,
+        query: &'a str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called for an `UPDATE` statement that has been parsed by
+    /// [`on_statement`](trait.AsyncMysqlShim.html#method.on_statement).
+    ///
+    /// See [`MysqlShim::on_update`](trait.MysqlShim.html#method.on_update).
+    #[cfg(feature = "sqlparser")]
+    async fn on_update<'a>(
+        &'a mut self,
+        query: &'a str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called for a `DELETE` statement that has been parsed by
+    /// [`on_statement`](trait.AsyncMysqlShim.html#method.on_statement).
+    ///
+    /// See [`MysqlShim::on_delete`](trait.MysqlShim.html#method.on_delete).
+    #[cfg(feature = "sqlparser")]
+    async fn on_delete<'a>(
+        &'a mut self,
+        query: &'a str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called for a statement that changes the schema that has been parsed by
+    /// [`on_statement`](trait.AsyncMysqlShim.html#method.on_statement).
+    ///
+    /// See [`MysqlShim::on_ddl`](trait.MysqlShim.html#method.on_ddl).
+    #[cfg(feature = "sqlparser")]
+    async fn on_ddl<'a>(
+        &'a mut self,
+        query: &'a str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called for a transaction control statement that has been parsed by
+    /// [`on_statement`](trait.AsyncMysqlShim.html#method.on_statement).
+    ///
+    /// See [`MysqlShim::on_transaction`](trait.MysqlShim.html#method.on_transaction).
+    #[cfg(feature = "sqlparser")]
+    async fn on_transaction<'a>(
+        &'a mut self,
+        query: &'a str,
+        _statement: sqlparser::ast::Statement,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called when the client starts a transaction with `BEGIN` or `START TRANSACTION`.
+    ///
+    /// See [`MysqlShim::on_begin`](trait.MysqlShim.html#method.on_begin).
+    async fn on_begin<'a>(
+        &'a mut self,
+        query: &'a str,
+        _options: TransactionOptions,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called when the client commits the open transaction with `COMMIT`.
+    ///
+    /// See [`MysqlShim::on_commit`](trait.MysqlShim.html#method.on_commit).
+    async fn on_commit<'a>(
+        &'a mut self,
+        query: &'a str,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called when the client rolls back the open transaction with `ROLLBACK`.
+    ///
+    /// See [`MysqlShim::on_rollback`](trait.MysqlShim.html#method.on_rollback).
+    async fn on_rollback<'a>(
+        &'a mut self,
+        query: &'a str,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called when the client sets a savepoint called `name` with `SAVEPOINT`.
+    ///
+    /// See [`MysqlShim::on_savepoint`](trait.MysqlShim.html#method.on_savepoint).
+    async fn on_savepoint<'a>(
+        &'a mut self,
+        query: &'a str,
+        _name: &'a str,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called when the client removes the savepoint called `name` with `RELEASE SAVEPOINT`.
+    ///
+    /// See [`MysqlShim::on_release_savepoint`](trait.MysqlShim.html#method.on_release_savepoint).
+    async fn on_release_savepoint<'a>(
+        &'a mut self,
+        query: &'a str,
+        _name: &'a str,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
+    /// Called when the client rolls the open transaction back to the savepoint called `name` with
+    /// `ROLLBACK TO SAVEPOINT`.
+    ///
+    /// See
+    /// [`MysqlShim::on_rollback_to_savepoint`](trait.MysqlShim.html#method.on_rollback_to_savepoint).
+    async fn on_rollback_to_savepoint<'a>(
+        &'a mut self,
+        query: &'a str,
+        _name: &'a str,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.on_query(query, results).await
+    }
+
     /// Called when client switches database.
     async fn on_init<'a>(&'a mut self, _: &'a str, _: InitWriter<'a, W>) -> Result<(), Self::Error> {
         Ok(())
     }
 
+    /// Called when the client pings the server (`COM_PING`).
+    ///
+    /// See [`MysqlShim::on_ping`](trait.MysqlShim.html#method.on_ping).
+    async fn on_ping<'a>(&'a mut self, w: PingWriter<'a, W>) -> Result<(), Self::Error> {
+        Ok(w.ok()?)
+    }
+
+    /// Called when the client asks for the columns of `table` (`COM_FIELD_LIST`).
+    ///
+    /// See [`MysqlShim::on_list_fields`](trait.MysqlShim.html#method.on_list_fields).
+    async fn on_list_fields<'a>(
+        &'a mut self,
+        _table: &'a str,
+        _wildcard: &'a str,
+    ) -> Result<Vec<Column>, Self::Error>
+        where
+            W: 'async_trait
+    {
+        Ok(Vec::new())
+    }
+
+    /// Whether queries that hold several `;`-separated statements should be split up, with
+    /// [`on_query`](trait.AsyncMysqlShim.html#tymethod.on_query) called once per statement.
+    ///
+    /// See [`MysqlShim::split_multi_statements`](trait.MysqlShim.html#method.split_multi_statements).
+    fn split_multi_statements(&self) -> bool {
+        false
+    }
+
+    /// The system variables a new connection starts out with.
+    ///
+    /// See [`MysqlShim::system_variables`](trait.MysqlShim.html#method.system_variables).
+    fn system_variables(&self) -> SystemVariables {
+        SystemVariables::default()
+    }
+
+    /// Whether the queries that client libraries send while they set up a connection are
+    /// answered by the server.
+    ///
+    /// See [`MysqlShim::driver_compatibility`](trait.MysqlShim.html#method.driver_compatibility).
+    fn driver_compatibility(&self) -> bool {
+        false
+    }
+
+    /// A description of the schemas, tables and columns of the database behind this shim, if
+    /// there is one.
+    ///
+    /// See [`MysqlShim::catalog`](trait.MysqlShim.html#method.catalog).
+    fn catalog(&self) -> Option<&dyn Catalog> {
+        None
+    }
+
+    /// The handler for the `XA` statements of distributed transactions, if this shim takes part in
+    /// them.
+    ///
+    /// See [`MysqlShim::xa_handler`](trait.MysqlShim.html#method.xa_handler).
+    fn xa_handler(&mut self) -> Option<&mut dyn XaHandler> {
+        None
+    }
+
+    /// How `SET` statements that assign system variables are dealt with.
+    ///
+    /// See [`MysqlShim::set_statements`](trait.MysqlShim.html#method.set_statements).
+    fn set_statements(&self) -> SetStatements {
+        SetStatements::Forward
+    }
+
+    /// Called when the client asks for a human-readable summary of the server's status
+    /// (`COM_STATISTICS`, as sent by `mysqladmin status`).
+    ///
+    /// See [`MysqlShim::on_statistics`](trait.MysqlShim.html#method.on_statistics).
+    async fn on_statistics<'a>(&'a mut self) -> Result<String, Self::Error>
+        where
+            W: 'async_trait
+    {
+        Ok(ServerStatistics::current().to_string())
+    }
+
+    /// Called when the client asks for the list of connected clients (`COM_PROCESS_INFO`).
+    ///
+    /// See [`MysqlShim::on_process_info`](trait.MysqlShim.html#method.on_process_info).
+    async fn on_process_info<'a>(
+        &'a mut self,
+        processes: Vec<ProcessInfo>,
+    ) -> Result<Vec<ProcessInfo>, Self::Error>
+        where
+            W: 'async_trait
+    {
+        Ok(processes)
+    }
+
     /// Generate salt for native auth plugin
     async fn generate_nonce<'a>(&'a mut self) -> Result<Vec<u8>, Self::Error>
         where
@@ -267,8 +1130,10 @@ pub trait AsyncMysqlShim<W: Write + Send> {
 /// that implements [`MysqlShim`](trait.MysqlShim.html).
 pub struct MysqlIntermediary<B, R: Read, W: Write> {
     shim: B,
-    reader: packet::PacketReader<R>,
+    reader: Mutex<packet::PacketReader<R>>,
     writer: packet::PacketWriter<W>,
+    state: ConnectionState,
+    registration: registry::Registration,
 }
 
 impl<B: MysqlShim<net::TcpStream>> MysqlIntermediary<B, net::TcpStream, net::TcpStream> {
@@ -277,19 +1142,52 @@ impl<B: MysqlShim<net::TcpStream>> MysqlIntermediary<B, net::TcpStream, net::Tcp
     /// [`MysqlIntermediary::run_on`](struct.MysqlIntermediary.html#method.run_on).
     pub fn run_on_tcp(shim: B, stream: net::TcpStream) -> Result<(), B::Error> {
         let w = stream.try_clone()?;
-        MysqlIntermediary::run_on(shim, stream, w)
+        let host = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_default();
+        MysqlIntermediary::run_with_host(shim, stream, w, host)
     }
 }
 
-impl<B: MysqlShim<S>, S: Read + Write + Clone> MysqlIntermediary<B, S, S> {
+impl<B: MysqlShim<S>, S: Read + Write + Clone + Send> MysqlIntermediary<B, S, S> {
     /// Create a new server over a two-way stream and process client commands until the client
-    /// disconnects or an error occurs. See also
-    /// [`MysqlIntermediary::run_on`](struct.MysqlIntermediary.html#method.run_on).
+    /// disconnects or an error occurs. Like the reader of
+    /// [`MysqlIntermediary::run_on`](struct.MysqlIntermediary.html#method.run_on), `stream` must
+    /// be `Send`.
     pub fn run_on_stream(shim: B, stream: S) -> Result<(), B::Error> {
         MysqlIntermediary::run_on(shim, stream.clone(), stream)
     }
 }
 
+/// Why the connection to a client ended, as given to
+/// [`MysqlShim::on_disconnect`](trait.MysqlShim.html#method.on_disconnect).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The client said goodbye (`COM_QUIT`).
+    Quit,
+    /// The client closed the connection without saying goodbye.
+    Closed,
+    /// The connection was killed, with `KILL` or `COM_PROCESS_KILL`.
+    Killed,
+    /// The shim rejected the client's credentials when it logged in (see
+    /// [`AsyncMysqlShim::on_auth`](trait.AsyncMysqlShim.html#method.on_auth)).
+    Rejected,
+    /// An I/O error occurred, or the shim returned an error. The error is returned by the
+    /// intermediary.
+    Error,
+}
+
+/// The characteristics of a transaction started with `START TRANSACTION`, as given to
+/// [`MysqlShim::on_begin`](trait.MysqlShim.html#method.on_begin).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Whether the transaction was started `READ ONLY`.
+    pub read_only: bool,
+    /// Whether the transaction was started `WITH CONSISTENT SNAPSHOT`.
+    pub consistent_snapshot: bool,
+}
+
 #[derive(Default)]
 struct StatementData {
     long_data: HashMap<u16, Vec<u8>>,
@@ -297,18 +1195,107 @@ struct StatementData {
     params: u16,
 }
 
-impl<B: MysqlShim<W>, R: Read, W: Write> MysqlIntermediary<B, R, W> {
+/// Per-connection state that is shared with the writers given to the shim.
+#[derive(Default)]
+pub(crate) struct ConnectionState {
+    /// Whether queries are split into their individual statements before they are dispatched.
+    multi_statements: bool,
+    /// Whether the client prefixes queries and statement parameters with query attributes.
+    query_attributes: bool,
+    /// Whether the client lets the server ask for local files.
+    pub(crate) local_files: bool,
+    /// Set while the client is sending a local file that has not been read to the end.
+    pub(crate) reading_file: bool,
+    /// If buffered output has been sent to the client in the middle of the current command, the
+    /// mark of the output that can no longer be taken back.
+    pub(crate) flushed: Option<(u64, u8)>,
+    /// Set while dispatching a statement that is followed by more statements in the same query.
+    pub(crate) more_statements: bool,
+    /// Set once an error has been sent in response to the current command.
+    pub(crate) errored: bool,
+    /// The database the client has most recently switched to.
+    pub(crate) schema: Option<String>,
+    /// The system variables of this connection.
+    pub(crate) variables: SystemVariables,
+    /// The notes, warnings and errors raised by the last statement.
+    pub(crate) warnings: Vec<warnings::Warning>,
+    /// Whether a transaction is open.
+    pub(crate) in_transaction: bool,
+    /// The XA transaction branch that this connection is working on, and its state.
+    pub(crate) xa: Option<(Xid, xa::XaState)>,
+    /// Cancelled if the client asks for the current command to be interrupted.
+    pub(crate) cancellation: CancellationToken,
+    /// While the rows of a bulk execution are executed one by one, the total number of affected
+    /// rows and the first generated id, which are reported to the client at the end.
+    pub(crate) bulk: Option<(u64, u64)>,
+}
+
+impl ConnectionState {
+    /// The status flags to send to the client at the end of a statement.
+    pub(crate) fn status(&self) -> StatusFlags {
+        let mut status = StatusFlags::empty();
+        status.set(StatusFlags::SERVER_STATUS_AUTOCOMMIT, self.autocommit());
+        status.set(StatusFlags::SERVER_STATUS_IN_TRANS, self.in_transaction);
+        // later statements of a multi-statement query also count as more results
+        status.set(StatusFlags::SERVER_MORE_RESULTS_EXISTS, self.more_statements);
+        status
+    }
+
+    /// Whether `autocommit` is on for this session.
+    fn autocommit(&self) -> bool {
+        self.variables.autocommit()
+    }
+
+    /// Note that a statement is about to reach the shim, which opens a transaction if
+    /// `autocommit` is off.
+    pub(crate) fn implicit_begin(&mut self) {
+        if !self.autocommit() {
+            self.in_transaction = true;
+        }
+    }
+
+    /// Replace the session's system variables with those that a `SET` statement leaves behind.
+    ///
+    /// Turning `autocommit` on ends the open transaction.
+    pub(crate) fn set_variables(&mut self, variables: SystemVariables) {
+        let autocommit = self.autocommit();
+        self.variables = variables;
+        if !autocommit && self.autocommit() {
+            self.in_transaction = false;
+        }
+    }
+
+    /// The number of warnings to report to the client at the end of a statement.
+    pub(crate) fn warning_count(&self) -> u16 {
+        self.warnings.len().min(usize::from(u16::MAX)) as u16
+    }
+}
+
+impl<B: MysqlShim<W>, R: Read + Send, W: Write> MysqlIntermediary<B, R, W> {
     /// Create a new server over two one-way channels and process client commands until the client
     /// disconnects or an error occurs.
+    ///
+    /// `reader` must be `Send`, since the [`QueryResultWriter`](struct.QueryResultWriter.html)s
+    /// given to the shim hold on to it to read local files that the client sends, and those
+    /// writers are `Send` so that async shims can hold them across `.await`.
     pub fn run_on(shim: B, reader: R, writer: W) -> Result<(), B::Error> {
-        let r = packet::PacketReader::new(reader);
+        MysqlIntermediary::run_with_host(shim, reader, writer, String::new())
+    }
+
+    fn run_with_host(shim: B, reader: R, writer: W, host: String) -> Result<(), B::Error> {
+        let r = Mutex::new(packet::PacketReader::new(reader));
         let w = packet::PacketWriter::new(writer);
         let mut mi = MysqlIntermediary {
             shim,
             reader: r,
             writer: w,
+            state: ConnectionState::default(),
+            registration: registry::Registration::new(host),
         };
-        mi.init()?;
+        if let Err(e) = mi.init() {
+            mi.shim.on_disconnect(DisconnectReason::Error);
+            return Err(e);
+        }
         mi.run()
     }
 
@@ -318,26 +1305,34 @@ impl<B: MysqlShim<W>, R: Read, W: Write> MysqlIntermediary<B, R, W> {
         // 5.1.10 because that's what Ruby's ActiveRecord requires
         self.writer.write_all(&b"5.1.10-alpha-msql-proxy\0"[..])?;
 
-        self.writer.write_all(&[0x08, 0x00, 0x00, 0x00])?; // TODO: connection ID
+        self.writer
+            .write_all(&self.registration.id().to_le_bytes())?; // connection ID
         self.writer.write_all(&b";X,po_k}\0"[..])?; // auth seed
-        self.writer.write_all(&[0x00, 0x42])?; // just 4.1 proto
+        self.writer.write_all(&[0x80, 0x42])?; // 4.1 proto, local files
         self.writer.write_all(&[0x21])?; // UTF8_GENERAL_CI
         self.writer.write_all(&[0x00, 0x00])?; // status flags
-        self.writer.write_all(&[0x00, 0x00])?; // extended capabilities
+        let mut capabilities = CapabilityFlags::empty();
+        if self.shim.split_multi_statements() {
+            capabilities |=
+                CapabilityFlags::CLIENT_MULTI_STATEMENTS | CapabilityFlags::CLIENT_MULTI_RESULTS;
+        }
+        let extended = ((capabilities.bits() | commands::CLIENT_QUERY_ATTRIBUTES) >> 16) as u16;
+        self.writer.write_all(&extended.to_le_bytes())?; // extended capabilities
         self.writer.write_all(&[0x00])?; // no plugins
         self.writer.write_all(&[0x00; 6][..])?; // filler
-        self.writer.write_all(&[0x00; 4][..])?; // filler
+        let mariadb_capabilities = writers::MARIADB_CLIENT_STMT_BULK_OPERATIONS;
+        self.writer.write_all(&mariadb_capabilities.to_le_bytes())?; // MariaDB capabilities
         self.writer.write_all(&b">o6^Wz!/kM}N\0"[..])?; // 4.1+ servers must extend salt
         self.writer.flush()?;
 
         {
-            let (seq, handshake) = self.reader.next()?.ok_or_else(|| {
+            let (seq, handshake) = self.reader.get_mut().next()?.ok_or_else(|| {
                 io::Error::new(
                     io::ErrorKind::ConnectionAborted,
                     "peer terminated connection",
                 )
             })?;
-            let _handshake = commands::client_handshake(&handshake)
+            let handshake = commands::client_handshake(&handshake)
                 .map_err(|e| match e {
                     nom::Err::Incomplete(_) => io::Error::new(
                         io::ErrorKind::UnexpectedEof,
@@ -360,85 +1355,234 @@ impl<B: MysqlShim<W>, R: Read, W: Write> MysqlIntermediary<B, R, W> {
                 })?
                 .1;
             self.writer.set_seq(seq + 1);
+            self.registration.set_user(&handshake.username);
+            self.state.query_attributes = handshake.query_attributes;
+            self.state.local_files = handshake
+                .capabilities
+                .contains(CapabilityFlags::CLIENT_LOCAL_FILES);
+            self.state.variables = self.shim.system_variables();
+            self.state.multi_statements = self.shim.split_multi_statements()
+                && handshake
+                    .capabilities
+                    .contains(CapabilityFlags::CLIENT_MULTI_STATEMENTS);
         }
 
-        writers::write_ok_packet(&mut self.writer, 0, 0, StatusFlags::empty())?;
+        writers::write_ok_packet(&mut self.writer, 0, 0, StatusFlags::empty(), 0, b"")?;
         self.writer.flush()?;
 
         Ok(())
     }
 
+    /// If the shim has returned without reading all of a local file, discard the rest of the
+    /// file, which the client sends before it reads anything else.
+    fn skip_local_file(&mut self) -> Result<(), B::Error> {
+        if !self.state.reading_file {
+            return Ok(());
+        }
+        while let Some((seq, packet)) = self.reader.get_mut().next()? {
+            self.writer.set_seq(seq.wrapping_add(1));
+            if packet.is_empty() {
+                break;
+            }
+        }
+        self.state.reading_file = false;
+        Ok(())
+    }
+
     fn run(mut self) -> Result<(), B::Error> {
+        let mut stmts = HashMap::new();
+        let result = self.run_commands(&mut stmts);
+        for &stmt in stmts.keys() {
+            self.shim.on_close(stmt);
+        }
+        let reason = match result {
+            Ok(reason) => reason,
+            Err(_) => DisconnectReason::Error,
+        };
+        self.shim.on_disconnect(reason);
+        result.map(drop)
+    }
+
+    fn run_commands(
+        &mut self,
+        stmts: &mut HashMap<u32, StatementData>,
+    ) -> Result<DisconnectReason, B::Error> {
         use crate::commands::Command;
 
-        let mut stmts: HashMap<u32, _> = HashMap::new();
-        while let Some((seq, packet)) = self.reader.next()? {
+        while let Some((seq, packet)) = self.reader.get_mut().next()? {
+            if self.registration.is_killed() {
+                return Ok(DisconnectReason::Killed);
+            }
             self.writer.set_seq(seq + 1);
             let cmd = commands::parse(&packet).unwrap().1;
+            self.state.cancellation = self.registration.begin(&cmd);
+            self.state.errored = false;
             match cmd {
                 Command::Query(q) => {
-                    if q.starts_with(b"SELECT @@") || q.starts_with(b"select @@") {
-                        let w = QueryResultWriter::new(&mut self.writer, false);
-                        let var = &q[b"SELECT @@".len()..];
-                        match var {
-                            b"max_allowed_packet" => {
-                                let cols = &[Column {
-                                    table: String::new(),
-                                    column: "@@max_allowed_packet".to_owned(),
-                                    coltype: myc::constants::ColumnType::MYSQL_TYPE_LONG,
-                                    colflags: myc::constants::ColumnFlags::UNSIGNED_FLAG,
-                                }];
-                                let mut w = w.start(cols)?;
-                                w.write_row(iter::once(67108864u32))?;
-                                w.finish()?;
+                    // copied, so that the shim can read more packets while it answers the query
+                    let query = q.to_vec();
+                    let (attributes, q) = if self.state.query_attributes {
+                        params::query_attributes(&query)?
+                    } else {
+                        (Vec::new(), &query[..])
+                    };
+                    let mut stmts = if self.state.multi_statements {
+                        sql::split_statements(q)
+                    } else {
+                        Vec::new()
+                    };
+                    if stmts.is_empty() {
+                        stmts.push(q);
+                    }
+
+                    let last = stmts.len() - 1;
+                    for (i, q) in stmts.into_iter().enumerate() {
+                        registry::count_question();
+                        self.state.more_statements = i != last;
+                        let xa = self.shim.xa_handler().is_some();
+                        let handling = route::Handling {
+                            set_statements: self.shim.set_statements(),
+                            driver_compatibility: self.shim.driver_compatibility(),
+                            catalog: self.shim.catalog(),
+                            xa,
+                        };
+                        match route::route(q, handling, &mut self.state)? {
+                            Route::Builtin(builtin) => {
+                                let xa = self.shim.xa_handler();
+                                let w = &mut self.writer;
+                                route::answer(builtin, xa, &self.registration, w, &mut self.state)?;
                             }
-                            _ => {
-                                w.completed(0, 0)?;
+                            Route::Use(schema) => {
+                                let w = InitWriter {
+                                    writer: &mut self.writer,
+                                    state: &mut self.state,
+                                    schema: &schema,
+                                };
+                                self.shim.on_init(&schema, w)?;
+                            }
+                            Route::Transaction(q, transaction, variables) => {
+                                let open = self.state.in_transaction;
+                                self.state.in_transaction = transaction.leaves_open(open);
+                                let w = QueryResultWriter::new(
+                                    &mut self.writer,
+                                    &mut self.state,
+                                    false,
+                                )
+                                .with_attributes(&attributes);
+                                match transaction {
+                                    Transaction::Begin(options) => {
+                                        self.shim.on_begin(q, options, w)?
+                                    }
+                                    Transaction::Commit => self.shim.on_commit(q, w)?,
+                                    Transaction::Rollback => self.shim.on_rollback(q, w)?,
+                                    Transaction::Savepoint(name) => {
+                                        self.shim.on_savepoint(q, &name, w)?
+                                    }
+                                    Transaction::ReleaseSavepoint(name) => {
+                                        self.shim.on_release_savepoint(q, &name, w)?
+                                    }
+                                    Transaction::RollbackToSavepoint(name) => {
+                                        self.shim.on_rollback_to_savepoint(q, &name, w)?
+                                    }
+                                }
+                                if self.state.errored {
+                                    self.state.in_transaction = open;
+                                    if let Some(variables) = variables {
+                                        self.state.variables = variables;
+                                    }
+                                }
+                            }
+                            Route::Query(q) => {
+                                self.state.implicit_begin();
+                                let w = QueryResultWriter::new(
+                                    &mut self.writer,
+                                    &mut self.state,
+                                    false,
+                                )
+                                .with_attributes(&attributes)
+                                .with_client(packet::Client::Blocking(&self.reader));
+                                #[cfg(feature = "sqlparser")]
+                                match statement::parse(q) {
+                                    Some(statement) => self.shim.on_statement(q, statement, w)?,
+                                    None => self.shim.on_query(q, w)?,
+                                }
+                                #[cfg(not(feature = "sqlparser"))]
+                                self.shim.on_query(q, w)?;
+                                self.skip_local_file()?;
                             }
                         }
-                    } else if q.starts_with(b"USE ") || q.starts_with(b"use ") {
-                        let w = InitWriter {
-                            writer: &mut self.writer,
-                        };
-                        let schema = ::std::str::from_utf8(&q[b"USE ".len()..])
-                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                        let schema = schema.trim().trim_end_matches(';').trim_matches('`');
-                        self.shim.on_init(&schema, w)?;
-                    } else {
-                        let w = QueryResultWriter::new(&mut self.writer, false);
-                        self.shim.on_query(
-                            ::std::str::from_utf8(q)
-                                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
-                            w,
-                        )?;
+
+                        if self.state.errored {
+                            // like MySQL, stop executing a multi-statement query at the first error
+                            break;
+                        }
                     }
+                    self.state.more_statements = false;
                 }
                 Command::Prepare(q) => {
                     let w = StatementMetaWriter {
                         writer: &mut self.writer,
-                        stmts: &mut stmts,
+                        stmts: &mut *stmts,
                     };
 
-                    self.shim.on_prepare(
-                        ::std::str::from_utf8(q)
-                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
-                        w,
-                    )?;
+                    let q = ::std::str::from_utf8(q)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    #[cfg(feature = "sqlparser")]
+                    match statement::parse(q) {
+                        Some(statement) => self.shim.on_prepare_statement(q, statement, w)?,
+                        None => self.shim.on_prepare(q, w)?,
+                    }
+                    #[cfg(not(feature = "sqlparser"))]
+                    self.shim.on_prepare(q, w)?;
                 }
-                Command::Execute { stmt, params } => {
+                Command::Execute {
+                    stmt,
+                    flags,
+                    params,
+                } => {
                     let state = stmts.get_mut(&stmt).ok_or_else(|| {
                         io::Error::new(
                             io::ErrorKind::InvalidData,
                             format!("asked to execute unknown statement {}", stmt),
                         )
                     })?;
+                    registry::count_question();
+                    self.state.warnings.clear();
+                    self.state.implicit_begin();
                     {
+                        let (attributes, encoded) = if self.state.query_attributes {
+                            let (attributes, encoded) =
+                                params::execute_attributes(params, flags, state)?;
+                            (attributes, Some(encoded))
+                        } else {
+                            (Vec::new(), None)
+                        };
+                        let params = encoded.as_deref().unwrap_or(params);
                         let params = params::ParamParser::new(params, state);
-                        let w = QueryResultWriter::new(&mut self.writer, true);
+                        let w = QueryResultWriter::new(&mut self.writer, &mut self.state, true)
+                            .with_attributes(&attributes);
                         self.shim.on_execute(stmt, params, w)?;
                     }
                     state.long_data.clear();
                 }
+                Command::BulkExecute {
+                    stmt,
+                    flags,
+                    params,
+                } => {
+                    let state = stmts.get(&stmt).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("asked to bulk execute unknown statement {}", stmt),
+                        )
+                    })?;
+                    registry::count_question();
+                    self.state.warnings.clear();
+                    self.state.implicit_begin();
+                    let rows = params::BulkParams::new(params, flags, state)?;
+                    let w = QueryResultWriter::new(&mut self.writer, &mut self.state, true);
+                    self.shim.on_bulk_execute(stmt, rows, w)?;
+                }
                 Command::SendLongData { stmt, param, data } => {
                     stmts
                         .get_mut(&stmt)
@@ -458,71 +1602,185 @@ impl<B: MysqlShim<W>, R: Read, W: Write> MysqlIntermediary<B, R, W> {
                     stmts.remove(&stmt);
                     // NOTE: spec dictates no response from server
                 }
-                Command::ListFields(_) => {
-                    let cols = &[Column {
-                        table: String::new(),
-                        column: "not implemented".to_owned(),
-                        coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
-                        colflags: myc::constants::ColumnFlags::UNSIGNED_FLAG,
-                    }];
-                    writers::write_column_definitions(cols, &mut self.writer, true)?;
+                Command::ListFields { table, wildcard } => {
+                    let table = ::std::str::from_utf8(table)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    let wildcard = ::std::str::from_utf8(wildcard)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    let cols = self.shim.on_list_fields(table, wildcard)?;
+                    writers::write_field_list(&cols, &mut self.writer)?;
                 }
                 Command::Init(schema) => {
+                    let schema = ::std::str::from_utf8(schema)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
                     let w = InitWriter {
                         writer: &mut self.writer,
+                        state: &mut self.state,
+                        schema,
                     };
-                    self.shim.on_init(
-                        ::std::str::from_utf8(schema)
-                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
-                        w,
-                    )?;
+                    self.shim.on_init(schema, w)?;
+                }
+                Command::SetOption(option) => match option {
+                    // MYSQL_OPTION_MULTI_STATEMENTS_ON, which only a shim that splits them allows
+                    0 if !self.shim.split_multi_statements() => {
+                        writers::write_err(
+                            ErrorKind::ER_NOT_SUPPORTED_YET,
+                            b"multi-statement queries are not supported",
+                            &mut self.writer,
+                        )?;
+                    }
+                    // MYSQL_OPTION_MULTI_STATEMENTS_ON / MYSQL_OPTION_MULTI_STATEMENTS_OFF
+                    0 | 1 => {
+                        self.state.multi_statements = option == 0;
+                        writers::write_eof_packet(&mut self.writer, self.state.status(), 0)?;
+                    }
+                    _ => {
+                        writers::write_err(
+                            ErrorKind::ER_UNKNOWN_COM_ERROR,
+                            format!("unknown option {}", option).as_bytes(),
+                            &mut self.writer,
+                        )?;
+                    }
+                },
+                Command::Statistics => {
+                    let stats = self.shim.on_statistics()?;
+                    self.writer.write_all(stats.as_bytes())?;
+                    self.writer.end_packet()?;
+                }
+                Command::ProcessInfo => {
+                    let processes = self.shim.on_process_info(self.registration.processes())?;
+                    let w = QueryResultWriter::new(&mut self.writer, &mut self.state, false);
+                    registry::write_process_list(&processes, w)?;
+                }
+                Command::ProcessKill(id) => {
+                    let w = QueryResultWriter::new(&mut self.writer, &mut self.state, false);
+                    write_kill_result(self.registration.kill(id, false), id, w)?;
                 }
                 Command::Ping => {
-                    writers::write_ok_packet(&mut self.writer, 0, 0, StatusFlags::empty())?;
+                    let w = PingWriter {
+                        writer: &mut self.writer,
+                        state: &mut self.state,
+                    };
+                    self.shim.on_ping(w)?;
                 }
                 Command::Quit => {
-                    break;
+                    return Ok(DisconnectReason::Quit);
                 }
             }
+            self.registration.end(self.state.schema.as_deref());
             self.writer.flush()?;
+            if self.registration.is_killed() {
+                return Ok(DisconnectReason::Killed);
+            }
         }
-        Ok(())
+        Ok(DisconnectReason::Closed)
     }
 }
 
+/// Reply to a `KILL` of connection `id`.
+fn write_kill_result<W: Write>(
+    kill: registry::Kill,
+    id: u32,
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    match kill {
+        registry::Kill::Killed => results.completed(0, 0),
+        registry::Kill::NoSuchThread => results.error(
+            ErrorKind::ER_NO_SUCH_THREAD,
+            format!("Unknown thread id: {}", id).as_bytes(),
+        ),
+        registry::Kill::NotOwner => results.error(
+            ErrorKind::ER_KILL_DENIED_ERROR,
+            format!("You are not owner of thread {}", id).as_bytes(),
+        ),
+    }
+}
+
+/// Drive `fut` to completion, unless `token` is cancelled first, in which case `fut` is dropped
+/// and `None` is returned.
+async fn unless_cancelled<F: Future>(token: &CancellationToken, fut: F) -> Option<F::Output> {
+    tokio::select! {
+        output = fut => Some(output),
+        _ = token.cancelled() => None,
+    }
+}
+
+/// Throw away the output of a statement that was cancelled while the shim was working on it, and
+/// tell the client that it was interrupted instead.
+fn write_interrupted(
+    writer: &mut packet::PacketWriter<Cursor<Vec<u8>>>,
+    state: &mut ConnectionState,
+    mark: (u64, u8),
+) -> io::Result<()> {
+    // output that has already been sent cannot be taken back
+    writer.rollback(state.flushed.unwrap_or(mark));
+    state.errored = true;
+    writers::write_err(
+        ErrorKind::ER_QUERY_INTERRUPTED,
+        b"Query execution was interrupted",
+        writer,
+    )
+}
+
 /// A server that speaks the MySQL/MariaDB protocol, and can delegate client commands to a backend
 /// that implements [`MysqlShim`](trait.MysqlShim.html).
 pub struct AsyncMysqlIntermediary<B, R: AsyncRead + AsyncWrite + Unpin> {
     shim: B,
-    reader: packet::PacketReader<R>,
-    writer: packet::PacketWriter<Cursor<Vec<u8>>>
+    reader: Mutex<packet::PacketReader<R>>,
+    writer: packet::PacketWriter<Cursor<Vec<u8>>>,
+    state: ConnectionState,
+    registration: registry::Registration,
 }
 
-impl<B: AsyncMysqlShim<Cursor<Vec<u8>>> + Send, R: AsyncRead + AsyncWrite + Unpin> AsyncMysqlIntermediary<B, R> {
+impl<B: AsyncMysqlShim<Cursor<Vec<u8>>> + Send, R: AsyncRead + AsyncWrite + Unpin + Send> AsyncMysqlIntermediary<B, R> {
     /// Create a new server over two one-way channels and process client commands until the client
     /// disconnects or an error occurs.
+    ///
+    /// `stream` must be `Send`, since the [`QueryResultWriter`](struct.QueryResultWriter.html)s
+    /// given to the shim hold on to it to stream rows and read local files, and the shim's futures
+    /// are `Send`.
     pub async fn run_on(shim: B, stream: R) -> Result<(), B::Error> {
-        let r = packet::PacketReader::new(stream);
+        let r = Mutex::new(packet::PacketReader::new(stream));
         let w = packet::PacketWriter::new(Cursor::new(Vec::new()));
         let mut mi = AsyncMysqlIntermediary {
             shim,
             reader: r,
-            writer: w
+            writer: w,
+            state: ConnectionState::default(),
+            registration: registry::Registration::new(String::new()),
         };
-        if !mi.init().await? {
-            return Ok(());
+        match mi.init().await {
+            Ok(true) => mi.run().await,
+            Ok(false) => {
+                mi.shim.on_disconnect(DisconnectReason::Rejected).await;
+                Ok(())
+            }
+            Err(e) => {
+                mi.shim.on_disconnect(DisconnectReason::Error).await;
+                Err(e)
+            }
         }
-        mi.run().await
     }
 
     async fn init(&mut self) -> Result<bool, B::Error> {
         let plugin = b"mysql_native_password";
         let nonce = self.shim.generate_nonce().await?;
-        write_handshake_packet(&mut self.writer, 8, plugin, nonce.as_slice())?;
+        let mut capabilities = CapabilityFlags::empty();
+        if self.shim.split_multi_statements() {
+            capabilities |=
+                CapabilityFlags::CLIENT_MULTI_STATEMENTS | CapabilityFlags::CLIENT_MULTI_RESULTS;
+        }
+        write_handshake_packet(
+            &mut self.writer,
+            self.registration.id(),
+            capabilities,
+            plugin,
+            nonce.as_slice(),
+        )?;
         self.writer_flush().await?;
 
         let handshake = {
-            let (seq, handshake) = self.reader.next_async().await?.ok_or_else(|| {
+            let (seq, handshake) = self.reader.get_mut().next_async().await?.ok_or_else(|| {
                 io::Error::new(
                     io::ErrorKind::ConnectionAborted,
                     "peer terminated connection",
@@ -551,6 +1809,16 @@ impl<B: AsyncMysqlShim<Cursor<Vec<u8>>> + Send, R: AsyncRead + AsyncWrite + Unpi
                 })?
                 .1;
             self.writer.set_seq(seq + 1);
+            self.registration.set_user(&handshake.username);
+            self.state.query_attributes = handshake.query_attributes;
+            self.state.local_files = handshake
+                .capabilities
+                .contains(CapabilityFlags::CLIENT_LOCAL_FILES);
+            self.state.variables = self.shim.system_variables();
+            self.state.multi_statements = self.shim.split_multi_statements()
+                && handshake
+                    .capabilities
+                    .contains(CapabilityFlags::CLIENT_MULTI_STATEMENTS);
             handshake
         };
 
@@ -570,7 +1838,7 @@ impl<B: AsyncMysqlShim<Cursor<Vec<u8>>> + Send, R: AsyncRead + AsyncWrite + Unpi
                     } else {
                         writers::write_auth_switch_packet(&mut self.writer, plugin, nonce.as_slice())?;
                         self.writer_flush().await?;
-                        let (seq, auth) = self.reader.next_async().await?.ok_or_else(|| {
+                        let (seq, auth) = self.reader.get_mut().next_async().await?.ok_or_else(|| {
                             io::Error::new(
                                 io::ErrorKind::ConnectionAborted,
                                 "peer terminated connection",
@@ -588,7 +1856,7 @@ impl<B: AsyncMysqlShim<Cursor<Vec<u8>>> + Send, R: AsyncRead + AsyncWrite + Unpi
             }
         }
 
-        writers::write_ok_packet(&mut self.writer, 0, 0, StatusFlags::empty())?;
+        writers::write_ok_packet(&mut self.writer, 0, 0, StatusFlags::empty(), 0, b"")?;
         self.writer_flush().await?;
 
         Ok(true)
@@ -597,84 +1865,270 @@ impl<B: AsyncMysqlShim<Cursor<Vec<u8>>> + Send, R: AsyncRead + AsyncWrite + Unpi
     async fn writer_flush(&mut self) -> Result<(), B::Error> {
         self.writer.flush()?;
         let buf = self.writer.w.get_mut();
-        self.reader.r.write_all(buf.as_slice()).await?;
-        self.reader.r.flush().await?;
+        self.reader.get_mut().r.write_all(buf.as_slice()).await?;
+        self.reader.get_mut().r.flush().await?;
         buf.truncate(0);
         self.writer.w.set_position(0);
         Ok(())
     }
 
+    /// If the shim has replied without reading all of a local file, discard the rest of the file,
+    /// which the client sends before it reads the reply.
+    async fn skip_local_file(&mut self) -> Result<(), B::Error> {
+        if !self.state.reading_file {
+            return Ok(());
+        }
+        let mut next = self.writer.seq();
+        while let Some((seq, packet)) = self.reader.get_mut().next_async().await? {
+            next = seq.wrapping_add(1);
+            if packet.is_empty() {
+                break;
+            }
+        }
+        // the reply is buffered, and only sent after the end of the file
+        self.writer.resequence(next);
+        self.state.reading_file = false;
+        self.state.flushed = Some((0, next));
+        Ok(())
+    }
+
     async fn run(mut self) -> Result<(), B::Error> {
+        let mut stmts = HashMap::new();
+        let result = self.run_commands(&mut stmts).await;
+        for &stmt in stmts.keys() {
+            self.shim.on_close(stmt).await;
+        }
+        let reason = match result {
+            Ok(reason) => reason,
+            Err(_) => DisconnectReason::Error,
+        };
+        self.shim.on_disconnect(reason).await;
+        result.map(drop)
+    }
+
+    async fn run_commands(
+        &mut self,
+        stmts: &mut HashMap<u32, StatementData>,
+    ) -> Result<DisconnectReason, B::Error> {
         use crate::commands::Command;
 
-        let mut stmts: HashMap<u32, _> = HashMap::new();
-        while let Some((seq, packet)) = self.reader.next_async().await? {
+        let killed = self.registration.killed().clone();
+        loop {
+            let next = tokio::select! {
+                next = self.reader.get_mut().next_async() => next?,
+                _ = killed.cancelled() => return Ok(DisconnectReason::Killed),
+            };
+            let (seq, packet) = match next {
+                Some(next) => next,
+                None => return Ok(DisconnectReason::Closed),
+            };
             self.writer.set_seq(seq + 1);
             let cmd = commands::parse(&packet).unwrap().1;
+            self.state.cancellation = self.registration.begin(&cmd);
+            self.state.errored = false;
+            self.state.flushed = None;
             match cmd {
                 Command::Query(q) => {
-                    if q.starts_with(b"SELECT @@") || q.starts_with(b"select @@") {
-                        let w = QueryResultWriter::new(&mut self.writer, false);
-                        let var = &q[b"SELECT @@".len()..];
-                        match var {
-                            b"max_allowed_packet" => {
-                                let cols = &[Column {
-                                    table: String::new(),
-                                    column: "@@max_allowed_packet".to_owned(),
-                                    coltype: myc::constants::ColumnType::MYSQL_TYPE_LONG,
-                                    colflags: myc::constants::ColumnFlags::UNSIGNED_FLAG,
-                                }];
-                                let mut w = w.start(cols)?;
-                                w.write_row(iter::once(67108864u32))?;
-                                w.finish()?;
+                    // copied, so that the shim can read more packets while it answers the query
+                    let query = q.to_vec();
+                    let (attributes, q) = if self.state.query_attributes {
+                        params::query_attributes(&query)?
+                    } else {
+                        (Vec::new(), &query[..])
+                    };
+                    let mut stmts = if self.state.multi_statements {
+                        sql::split_statements(q)
+                    } else {
+                        Vec::new()
+                    };
+                    if stmts.is_empty() {
+                        stmts.push(q);
+                    }
+
+                    let last = stmts.len() - 1;
+                    for (i, q) in stmts.into_iter().enumerate() {
+                        registry::count_question();
+                        self.state.more_statements = i != last;
+                        // what earlier statements have sent is before this one's mark anyway
+                        self.state.flushed = None;
+                        let xa = self.shim.xa_handler().is_some();
+                        let handling = route::Handling {
+                            set_statements: self.shim.set_statements(),
+                            driver_compatibility: self.shim.driver_compatibility(),
+                            catalog: self.shim.catalog(),
+                            xa,
+                        };
+                        match route::route(q, handling, &mut self.state)? {
+                            Route::Builtin(builtin) => {
+                                let xa = self.shim.xa_handler();
+                                let w = &mut self.writer;
+                                route::answer(builtin, xa, &self.registration, w, &mut self.state)?;
+                            }
+                            Route::Use(schema) => {
+                                let w = InitWriter {
+                                    writer: &mut self.writer,
+                                    state: &mut self.state,
+                                    schema: &schema,
+                                };
+                                self.shim.on_init(&schema, w).await?;
                             }
-                            _ => {
-                                w.completed(0, 0)?;
+                            Route::Transaction(q, transaction, variables) => {
+                                let open = self.state.in_transaction;
+                                self.state.in_transaction = transaction.leaves_open(open);
+                                let cancellation = self.state.cancellation.clone();
+                                let mark = self.writer.mark();
+                                let w = QueryResultWriter::new(
+                                    &mut self.writer,
+                                    &mut self.state,
+                                    false,
+                                )
+                                .with_attributes(&attributes);
+                                let answer = match &transaction {
+                                    Transaction::Begin(options) => {
+                                        self.shim.on_begin(q, *options, w)
+                                    }
+                                    Transaction::Commit => self.shim.on_commit(q, w),
+                                    Transaction::Rollback => self.shim.on_rollback(q, w),
+                                    Transaction::Savepoint(name) => {
+                                        self.shim.on_savepoint(q, name, w)
+                                    }
+                                    Transaction::ReleaseSavepoint(name) => {
+                                        self.shim.on_release_savepoint(q, name, w)
+                                    }
+                                    Transaction::RollbackToSavepoint(name) => {
+                                        self.shim.on_rollback_to_savepoint(q, name, w)
+                                    }
+                                };
+                                match unless_cancelled(&cancellation, answer).await {
+                                    Some(r) => r?,
+                                    None => {
+                                        write_interrupted(&mut self.writer, &mut self.state, mark)?
+                                    }
+                                }
+                                if self.state.errored {
+                                    self.state.in_transaction = open;
+                                    if let Some(variables) = variables {
+                                        self.state.variables = variables;
+                                    }
+                                }
+                            }
+                            Route::Query(q) => {
+                                self.state.implicit_begin();
+                                let cancellation = self.state.cancellation.clone();
+                                let mark = self.writer.mark();
+                                let w = QueryResultWriter::new(
+                                    &mut self.writer,
+                                    &mut self.state,
+                                    false,
+                                )
+                                .with_attributes(&attributes)
+                                .with_client(packet::Client::Async(&self.reader));
+                                #[cfg(feature = "sqlparser")]
+                                let answer = match statement::parse(q) {
+                                    Some(statement) => self.shim.on_statement(q, statement, w),
+                                    None => self.shim.on_query(q, w),
+                                };
+                                #[cfg(not(feature = "sqlparser"))]
+                                let answer = self.shim.on_query(q, w);
+                                let result = unless_cancelled(&cancellation, answer).await;
+                                self.skip_local_file().await?;
+                                match result {
+                                    Some(r) => r?,
+                                    None => {
+                                        write_interrupted(&mut self.writer, &mut self.state, mark)?
+                                    }
+                                }
                             }
                         }
-                    } else if q.starts_with(b"USE ") || q.starts_with(b"use ") {
-                        let w = InitWriter {
-                            writer: &mut self.writer,
-                        };
-                        let schema = ::std::str::from_utf8(&q[b"USE ".len()..])
-                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                        let schema = schema.trim().trim_end_matches(';').trim_matches('`');
-                        self.shim.on_init(&schema, w).await?;
-                    } else {
-                        let w = QueryResultWriter::new(&mut self.writer, false);
-                        self.shim.on_query(
-                            ::std::str::from_utf8(q)
-                                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
-                            w,
-                        ).await?;
+
+                        if self.state.errored {
+                            // like MySQL, stop executing a multi-statement query at the first error
+                            break;
+                        }
                     }
+                    self.state.more_statements = false;
                 }
                 Command::Prepare(q) => {
                     let w = StatementMetaWriter {
                         writer: &mut self.writer,
-                        stmts: &mut stmts,
+                        stmts: &mut *stmts,
                     };
 
-                    self.shim.on_prepare(
-                        ::std::str::from_utf8(q)
-                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
-                        w,
-                    ).await?;
+                    let q = ::std::str::from_utf8(q)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    #[cfg(feature = "sqlparser")]
+                    match statement::parse(q) {
+                        Some(statement) => self.shim.on_prepare_statement(q, statement, w).await?,
+                        None => self.shim.on_prepare(q, w).await?,
+                    }
+                    #[cfg(not(feature = "sqlparser"))]
+                    self.shim.on_prepare(q, w).await?;
                 }
-                Command::Execute { stmt, params } => {
+                Command::Execute {
+                    stmt,
+                    flags,
+                    params,
+                } => {
                     let state = stmts.get_mut(&stmt).ok_or_else(|| {
                         io::Error::new(
                             io::ErrorKind::InvalidData,
                             format!("asked to execute unknown statement {}", stmt),
                         )
                     })?;
+                    registry::count_question();
+                    self.state.warnings.clear();
+                    self.state.implicit_begin();
                     {
+                        // copied, so that the shim can send rows while it answers the statement
+                        let params = params.to_vec();
+                        let (attributes, encoded) = if self.state.query_attributes {
+                            let (attributes, encoded) =
+                                params::execute_attributes(&params, flags, state)?;
+                            (attributes, Some(encoded))
+                        } else {
+                            (Vec::new(), None)
+                        };
+                        let params = encoded.as_deref().unwrap_or(&params);
                         let params = params::ParamParser::new(params, state);
-                        let w = QueryResultWriter::new(&mut self.writer, true);
-                        self.shim.on_execute(stmt, params, w).await?;
+                        let cancellation = self.state.cancellation.clone();
+                        let mark = self.writer.mark();
+                        let w = QueryResultWriter::new(&mut self.writer, &mut self.state, true)
+                            .with_attributes(&attributes)
+                            .with_client(packet::Client::Async(&self.reader));
+                        let execute = self.shim.on_execute(stmt, params, w);
+                        match unless_cancelled(&cancellation, execute).await {
+                            Some(r) => r?,
+                            None => write_interrupted(&mut self.writer, &mut self.state, mark)?,
+                        }
                     }
                     state.long_data.clear();
                 }
+                Command::BulkExecute {
+                    stmt,
+                    flags,
+                    params,
+                } => {
+                    let state = stmts.get(&stmt).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("asked to bulk execute unknown statement {}", stmt),
+                        )
+                    })?;
+                    registry::count_question();
+                    self.state.warnings.clear();
+                    self.state.implicit_begin();
+                    let rows = params::BulkParams::new(params, flags, state)?;
+                    let cancellation = self.state.cancellation.clone();
+                    let mark = self.writer.mark();
+                    let w = QueryResultWriter::new(&mut self.writer, &mut self.state, true);
+                    let execute = self.shim.on_bulk_execute(stmt, rows, w);
+                    let result = unless_cancelled(&cancellation, execute).await;
+                    self.state.bulk = None;
+                    match result {
+                        Some(r) => r?,
+                        None => write_interrupted(&mut self.writer, &mut self.state, mark)?,
+                    }
+                }
                 Command::SendLongData { stmt, param, data } => {
                     stmts
                         .get_mut(&stmt)
@@ -694,34 +2148,77 @@ impl<B: AsyncMysqlShim<Cursor<Vec<u8>>> + Send, R: AsyncRead + AsyncWrite + Unpi
                     stmts.remove(&stmt);
                     // NOTE: spec dictates no response from server
                 }
-                Command::ListFields(_) => {
-                    let cols = &[Column {
-                        table: String::new(),
-                        column: "not implemented".to_owned(),
-                        coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
-                        colflags: myc::constants::ColumnFlags::UNSIGNED_FLAG,
-                    }];
-                    writers::write_column_definitions(cols, &mut self.writer, true)?;
+                Command::ListFields { table, wildcard } => {
+                    let table = ::std::str::from_utf8(table)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    let wildcard = ::std::str::from_utf8(wildcard)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    let cols = self.shim.on_list_fields(table, wildcard).await?;
+                    writers::write_field_list(&cols, &mut self.writer)?;
                 }
                 Command::Init(schema) => {
+                    let schema = ::std::str::from_utf8(schema)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
                     let w = InitWriter {
                         writer: &mut self.writer,
+                        state: &mut self.state,
+                        schema,
                     };
-                    self.shim.on_init(
-                        ::std::str::from_utf8(schema)
-                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
-                        w,
-                    ).await?;
+                    self.shim.on_init(schema, w).await?;
+                }
+                Command::SetOption(option) => match option {
+                    // MYSQL_OPTION_MULTI_STATEMENTS_ON, which only a shim that splits them allows
+                    0 if !self.shim.split_multi_statements() => {
+                        writers::write_err(
+                            ErrorKind::ER_NOT_SUPPORTED_YET,
+                            b"multi-statement queries are not supported",
+                            &mut self.writer,
+                        )?;
+                    }
+                    // MYSQL_OPTION_MULTI_STATEMENTS_ON / MYSQL_OPTION_MULTI_STATEMENTS_OFF
+                    0 | 1 => {
+                        self.state.multi_statements = option == 0;
+                        writers::write_eof_packet(&mut self.writer, self.state.status(), 0)?;
+                    }
+                    _ => {
+                        writers::write_err(
+                            ErrorKind::ER_UNKNOWN_COM_ERROR,
+                            format!("unknown option {}", option).as_bytes(),
+                            &mut self.writer,
+                        )?;
+                    }
+                },
+                Command::Statistics => {
+                    let stats = self.shim.on_statistics().await?;
+                    self.writer.write_all(stats.as_bytes())?;
+                    self.writer.end_packet()?;
+                }
+                Command::ProcessInfo => {
+                    let processes = self.registration.processes();
+                    let processes = self.shim.on_process_info(processes).await?;
+                    let w = QueryResultWriter::new(&mut self.writer, &mut self.state, false);
+                    registry::write_process_list(&processes, w)?;
+                }
+                Command::ProcessKill(id) => {
+                    let w = QueryResultWriter::new(&mut self.writer, &mut self.state, false);
+                    write_kill_result(self.registration.kill(id, false), id, w)?;
                 }
                 Command::Ping => {
-                    writers::write_ok_packet(&mut self.writer, 0, 0, StatusFlags::empty())?;
+                    let w = PingWriter {
+                        writer: &mut self.writer,
+                        state: &mut self.state,
+                    };
+                    self.shim.on_ping(w).await?;
                 }
                 Command::Quit => {
-                    break;
+                    return Ok(DisconnectReason::Quit);
                 }
             }
+            self.registration.end(self.state.schema.as_deref());
             self.writer_flush().await?;
+            if killed.is_cancelled() {
+                return Ok(DisconnectReason::Killed);
+            }
         }
-        Ok(())
     }
 }
\ No newline at end of file
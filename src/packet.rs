@@ -1,8 +1,11 @@
 use byteorder::{ByteOrder, LittleEndian};
 use std::io;
 use std::io::prelude::*;
-use tokio::io::AsyncReadExt;
+use std::io::Cursor;
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::io::AsyncRead;
+use tokio::sync::Mutex;
 
 const U24_MAX: usize = 16_777_215;
 
@@ -58,9 +61,47 @@ impl<W: Write> PacketWriter<W> {
 }
 
 impl<W> PacketWriter<W> {
+    pub fn seq(&self) -> u8 {
+        self.seq
+    }
+
     pub fn set_seq(&mut self, seq: u8) {
         self.seq = seq;
     }
+
+    /// Throw away whatever has been written to the current packet so far.
+    pub fn discard_packet(&mut self) {
+        self.to_write.truncate(4);
+    }
+}
+
+impl PacketWriter<Cursor<Vec<u8>>> {
+    /// Remember the current end of the buffered output, so that everything that is written after
+    /// this point can later be thrown away with `rollback`.
+    pub fn mark(&self) -> (u64, u8) {
+        (self.w.position(), self.seq)
+    }
+
+    pub fn rollback(&mut self, (position, seq): (u64, u8)) {
+        self.discard_packet();
+        self.w.get_mut().truncate(position as usize);
+        self.w.set_position(position);
+        self.seq = seq;
+    }
+
+    /// Renumber the packets that have been buffered so far so that they follow on from `seq`, for
+    /// when the client has sent more packets since they were written.
+    pub fn resequence(&mut self, mut seq: u8) {
+        let buf = self.w.get_mut();
+        let mut at = 0;
+        while at + 4 <= buf.len() {
+            let len = LittleEndian::read_u24(&buf[at..]) as usize;
+            buf[at + 3] = seq;
+            seq = seq.wrapping_add(1);
+            at += 4 + len;
+        }
+        self.seq = seq;
+    }
 }
 
 pub struct PacketReader<R> {
@@ -191,6 +232,80 @@ impl<R: Read> PacketReader<R> {
     }
 }
 
+/// A client connection from which the shim can read packets that the client sends in the middle
+/// of a command, such as the contents of a local file.
+///
+/// The connection's `PacketReader` sits behind a lock so that it can be shared, which keeps the
+/// writers that hold a `Client` covariant over `'a`. Only one command runs at a time, so the lock
+/// is never contended.
+#[derive(Clone, Copy)]
+pub(crate) enum Client<'a> {
+    Blocking(&'a (dyn ReadPacket + 'a)),
+    Async(&'a (dyn AsyncReadPacket + 'a)),
+}
+
+impl<'a> Client<'a> {
+    pub(crate) fn as_blocking(&self) -> Option<&'a (dyn ReadPacket + 'a)> {
+        match *self {
+            Client::Blocking(c) => Some(c),
+            Client::Async(_) => None,
+        }
+    }
+
+    pub(crate) fn as_async(&self) -> Option<&'a (dyn AsyncReadPacket + 'a)> {
+        match *self {
+            Client::Async(c) => Some(c),
+            Client::Blocking(_) => None,
+        }
+    }
+}
+
+pub(crate) trait ReadPacket: Send + Sync {
+    fn read_packet(&self) -> io::Result<Option<(u8, Vec<u8>)>>;
+}
+
+impl<R: Read + Send> ReadPacket for Mutex<PacketReader<R>> {
+    fn read_packet(&self) -> io::Result<Option<(u8, Vec<u8>)>> {
+        let mut reader = self
+            .try_lock()
+            .map_err(|_| io::Error::other("connection is already being read from"))?;
+        Ok(reader.next()?.map(|(seq, p)| (seq, p.to_vec())))
+    }
+}
+
+#[async_trait]
+pub(crate) trait AsyncReadPacket: Send + Sync {
+    /// Send `output` to the client, and then wait for it to send the next packet.
+    async fn exchange(&self, output: &[u8]) -> io::Result<Option<(u8, Vec<u8>)>>;
+
+    /// Send some of `output` to the client, returning how many bytes were sent. Waits while the
+    /// client is not keeping up.
+    async fn send(&self, output: &[u8]) -> io::Result<usize>;
+
+    /// Wait until everything that has been sent has been handed to the connection.
+    async fn flush_output(&self) -> io::Result<()>;
+}
+
+#[async_trait]
+impl<R: AsyncRead + AsyncWrite + Unpin + Send> AsyncReadPacket for Mutex<PacketReader<R>> {
+    async fn exchange(&self, output: &[u8]) -> io::Result<Option<(u8, Vec<u8>)>> {
+        let mut reader = self.lock().await;
+        if !output.is_empty() {
+            reader.r.write_all(output).await?;
+            reader.r.flush().await?;
+        }
+        Ok(reader.next_async().await?.map(|(seq, p)| (seq, p.to_vec())))
+    }
+
+    async fn send(&self, output: &[u8]) -> io::Result<usize> {
+        self.lock().await.r.write(output).await
+    }
+
+    async fn flush_output(&self) -> io::Result<()> {
+        self.lock().await.r.flush().await
+    }
+}
+
 pub fn fullpacket(i: &[u8]) -> nom::IResult<&[u8], (u8, &[u8])> {
     let (i, _) = nom::bytes::complete::tag(&[0xff, 0xff, 0xff])(i)?;
     let (i, seq) = nom::bytes::complete::take(1u8)(i)?;
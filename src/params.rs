@@ -1,6 +1,8 @@
 use crate::myc;
+use crate::myc::io::ReadMysqlExt;
 use crate::{StatementData, Value};
 use std::collections::HashMap;
+use std::io;
 
 /// A `ParamParser` decodes query parameters included in a client's `EXECUTE` command given
 /// type information for the expected parameters.
@@ -116,3 +118,330 @@ impl<'a> Iterator for Params<'a> {
         })
     }
 }
+
+// COM_STMT_BULK_EXECUTE flag that says that parameter types precede the rows
+const SEND_TYPES_TO_SERVER: u16 = 128;
+// COM_STMT_BULK_EXECUTE indicator that says that a value follows
+const INDICATOR_NONE: u8 = 0;
+
+/// The rows of parameters provided by a client in a MariaDB `COM_STMT_BULK_EXECUTE` command, which
+/// executes a prepared statement once for every row.
+///
+/// Each row holds one value for every parameter of the statement. Parameters that the client
+/// sent as `DEFAULT` or `IGNORE` instead of giving a value are given as NULL.
+pub struct BulkParams<'a> {
+    pub(crate) params: u16,
+    input: &'a [u8],
+    types: Vec<(myc::constants::ColumnType, bool)>,
+}
+
+impl<'a> BulkParams<'a> {
+    pub(crate) fn new(input: &'a [u8], flags: u16, stmt: &StatementData) -> io::Result<Self> {
+        let params = stmt.params as usize;
+        let mut bulk = BulkParams {
+            params: stmt.params,
+            input,
+            types: stmt.bound_types.clone(),
+        };
+        if flags & SEND_TYPES_TO_SERVER != 0 {
+            if input.len() < 2 * params {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "bulk execution ended in the middle of the parameter types",
+                ));
+            }
+            let (typmap, rest) = input.split_at(2 * params);
+            bulk.types = typmap
+                .chunks(2)
+                .map(|t| (myc::constants::ColumnType::from(t[0]), (t[1] & 128) != 0))
+                .collect();
+            bulk.input = rest;
+        }
+        if bulk.types.len() != params {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bulk execution without parameter types",
+            ));
+        }
+        if params == 0 && !bulk.input.is_empty() {
+            // a row without parameters takes up no bytes, so there is no telling the rows apart
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bulk execution of a statement without parameters",
+            ));
+        }
+
+        // check all the rows up front, so that iterating over them later cannot fail
+        let mut check = BulkParams {
+            params: bulk.params,
+            input: bulk.input,
+            types: bulk.types.clone(),
+        };
+        while check.read_row(None)?.is_some() {}
+        Ok(bulk)
+    }
+
+    /// Decode the next row. If `encoded` is given, the row is also re-encoded into it in the
+    /// format of the parameters of an `EXECUTE` command.
+    fn read_row(
+        &mut self,
+        encoded: Option<&mut Vec<u8>>,
+    ) -> io::Result<Option<Vec<ParamValue<'a>>>> {
+        if self.input.is_empty() {
+            return Ok(None);
+        }
+
+        let nullmap_len = (self.params as usize).div_ceil(8);
+        let mut values = Vec::new();
+        let mut nullmap = vec![0u8; nullmap_len];
+        let mut row = Vec::with_capacity(self.params as usize);
+        for (i, &(coltype, unsigned)) in self.types.iter().enumerate() {
+            let (&indicator, rest) = self.input.split_first().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "bulk execution ended in the middle of a row",
+                )
+            })?;
+            self.input = rest;
+
+            let value = if indicator == INDICATOR_NONE {
+                let before = self.input;
+                let value = Value::parse_from(&mut self.input, coltype, unsigned)?;
+                values.extend_from_slice(&before[..before.len() - self.input.len()]);
+                value
+            } else {
+                nullmap[i / 8] |= 1u8 << (i % 8);
+                Value::null()
+            };
+            row.push(ParamValue { value, coltype });
+        }
+
+        if let Some(encoded) = encoded {
+            encoded.clear();
+            encoded.extend(nullmap);
+            encoded.push(0x01); // types are bound
+            for &(coltype, unsigned) in &self.types {
+                encoded.push(coltype as u8);
+                encoded.push(if unsigned { 128 } else { 0 });
+            }
+            encoded.extend(values);
+        }
+        Ok(Some(row))
+    }
+
+    /// Re-encode the next row into `encoded` in the format of the parameters of an `EXECUTE`
+    /// command, and return `false` if there are no more rows.
+    pub(crate) fn next_encoded(&mut self, encoded: &mut Vec<u8>) -> bool {
+        // the rows were checked when the command was received
+        self.read_row(Some(encoded)).unwrap().is_some()
+    }
+}
+
+impl<'a> Iterator for BulkParams<'a> {
+    type Item = Vec<ParamValue<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        // the rows were checked when the command was received
+        self.read_row(None).unwrap()
+    }
+}
+
+// COM_STMT_EXECUTE flag that says that a parameter count precedes the parameters
+const PARAMETER_COUNT_AVAILABLE: u8 = 0x08;
+
+/// A named value that the client attached to a query or a statement execution (a MySQL 8 query
+/// attribute), for example to pass along a trace id.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryAttribute<'a> {
+    /// The name of the attribute.
+    pub name: &'a str,
+    /// The value of the attribute.
+    pub value: Value<'a>,
+    /// The column type the client assigned to the value.
+    pub coltype: myc::constants::ColumnType,
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "parameters ended prematurely",
+        ));
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+/// Decode a block of `count` parameters that is prefixed with their names, as sent by clients that
+/// have negotiated `CLIENT_QUERY_ATTRIBUTES`.
+///
+/// The first `params` of them are the parameters of the statement `stmt`, which are re-encoded in
+/// the format that [`Params`](struct.Params.html) expects. The rest are query attributes.
+fn read_named_params<'a>(
+    input: &mut &'a [u8],
+    count: usize,
+    params: usize,
+    stmt: Option<&StatementData>,
+) -> io::Result<(Vec<u8>, Vec<QueryAttribute<'a>>)> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    if count < params {
+        return Err(invalid("too few parameters for statement"));
+    }
+    if count == 0 {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    // the count comes from the client, so make sure that it fits in the packet before using it
+    if count > input.len().saturating_mul(8) {
+        return Err(invalid("more parameters than the packet has room for"));
+    }
+
+    let nullmap = take(input, count.div_ceil(8))?;
+    let mut types = Vec::new();
+    let mut names = Vec::new();
+    if take(input, 1)?[0] != 0x00 {
+        for _ in 0..count {
+            let t = take(input, 2)?;
+            types.push((myc::constants::ColumnType::from(t[0]), (t[1] & 128) != 0));
+            let len = input.read_lenenc_int()?;
+            names.push(take(input, len as usize)?);
+        }
+    } else if count == params {
+        // the client reuses the types it bound in an earlier execution
+        types.extend(stmt.map(|stmt| &stmt.bound_types[..]).unwrap_or(&[]));
+        if types.len() != count {
+            return Err(invalid("parameter types were never bound"));
+        }
+    } else {
+        return Err(invalid("query attributes without types"));
+    }
+
+    let mut encoded = vec![0u8; params.div_ceil(8)];
+    encoded.push(0x01); // types are bound
+    for &(coltype, unsigned) in &types[..params] {
+        encoded.push(coltype as u8);
+        encoded.push(if unsigned { 128 } else { 0 });
+    }
+    let mut attributes = Vec::new();
+    for (i, &(coltype, unsigned)) in types.iter().enumerate() {
+        let null = (nullmap[i / 8] & 1u8 << (i % 8)) != 0;
+        if i < params {
+            if null {
+                encoded[i / 8] |= 1u8 << (i % 8);
+            } else if stmt
+                .and_then(|stmt| stmt.long_data.get(&(i as u16)))
+                .is_none()
+            {
+                // (the values of long data parameters were sent ahead of time instead)
+                let before = *input;
+                Value::parse_from(input, coltype, unsigned)?;
+                encoded.extend_from_slice(&before[..before.len() - input.len()]);
+            }
+        } else {
+            let value = if null {
+                Value::null()
+            } else {
+                Value::parse_from(input, coltype, unsigned)?
+            };
+            attributes.push(QueryAttribute {
+                name: ::std::str::from_utf8(names[i])
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                value,
+                coltype,
+            });
+        }
+    }
+    Ok((encoded, attributes))
+}
+
+/// Split the query attributes off the front of a `COM_QUERY` sent by a client that has negotiated
+/// `CLIENT_QUERY_ATTRIBUTES`, and return them along with the query itself.
+pub(crate) fn query_attributes(mut input: &[u8]) -> io::Result<(Vec<QueryAttribute<'_>>, &[u8])> {
+    let count = input.read_lenenc_int()? as usize;
+    let _parameter_sets = input.read_lenenc_int()?; // always 1
+    let (_, attributes) = read_named_params(&mut input, count, 0, None)?;
+    Ok((attributes, input))
+}
+
+/// Split the query attributes out of the parameters of a `COM_STMT_EXECUTE` sent by a client that
+/// has negotiated `CLIENT_QUERY_ATTRIBUTES`.
+///
+/// Returns the attributes along with the statement's parameters, re-encoded in the format that
+/// [`Params`](struct.Params.html) expects.
+pub(crate) fn execute_attributes<'a>(
+    mut input: &'a [u8],
+    flags: u8,
+    stmt: &StatementData,
+) -> io::Result<(Vec<QueryAttribute<'a>>, Vec<u8>)> {
+    if stmt.params == 0 && flags & PARAMETER_COUNT_AVAILABLE == 0 {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    let count = input.read_lenenc_int()? as usize;
+    let (encoded, attributes) =
+        read_named_params(&mut input, count, stmt.params as usize, Some(stmt))?;
+    Ok((attributes, encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::myc::constants::ColumnType;
+    use crate::ValueInner;
+
+    #[test]
+    fn it_splits_off_query_attributes() {
+        let mut packet = vec![0x01, 0x01, 0x00, 0x01];
+        packet.extend(&[ColumnType::MYSQL_TYPE_VAR_STRING as u8, 0x00]);
+        packet.extend(b"\x05trace\x03abc");
+        packet.extend(b"SELECT 1");
+
+        let (attributes, query) = query_attributes(&packet).unwrap();
+        assert_eq!(query, b"SELECT 1");
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].name, "trace");
+        assert_eq!(attributes[0].value.into_inner(), ValueInner::Bytes(b"abc"));
+
+        let (attributes, query) = query_attributes(b"\x00\x01SELECT 1").unwrap();
+        assert!(attributes.is_empty());
+        assert_eq!(query, b"SELECT 1");
+
+        let mut packet = vec![0xfe];
+        packet.extend(&u64::MAX.to_le_bytes());
+        packet.extend(b"\x01SELECT 1");
+        let e = query_attributes(&packet).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn it_splits_off_execute_attributes() {
+        let mut stmt = StatementData {
+            params: 2,
+            ..Default::default()
+        };
+
+        // two parameters, of which the second is NULL, followed by a NULL attribute
+        let mut packet = vec![0x03, 0b0000_0110, 0x01];
+        packet.extend(&[ColumnType::MYSQL_TYPE_LONGLONG as u8, 0x80, 0x00]);
+        packet.extend(&[ColumnType::MYSQL_TYPE_LONGLONG as u8, 0x00, 0x00]);
+        packet.extend(&[ColumnType::MYSQL_TYPE_NULL as u8, 0x00, 0x01, b'x']);
+        packet.extend(&42u64.to_le_bytes());
+
+        let (attributes, encoded) = execute_attributes(&packet, 0, &stmt).unwrap();
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].name, "x");
+        assert!(attributes[0].value.is_null());
+
+        let params: Vec<_> = ParamParser::new(&encoded, &mut stmt)
+            .into_iter()
+            .map(|p| p.value.into_inner())
+            .collect();
+        assert_eq!(params, vec![ValueInner::UInt(42), ValueInner::NULL]);
+    }
+
+    #[test]
+    fn it_rejects_bulk_rows_without_parameters() {
+        let stmt = StatementData::default();
+        assert_eq!(BulkParams::new(&[], 0, &stmt).unwrap().count(), 0);
+        let e = BulkParams::new(&[0x00], 0, &stmt).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
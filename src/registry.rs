@@ -0,0 +1,449 @@
+//! Server-wide bookkeeping of the client connections handled by this process.
+//!
+//! Every intermediary registers its connection here, which is where connection ids come from,
+//! what `COM_STATISTICS` and `COM_PROCESS_INFO` are answered from by default, and how `KILL`
+//! finds the connection it should interrupt.
+//!
+//! Like a MySQL user without administrative privileges, a client only gets to see, and to kill,
+//! the connections of the user it logged in as.
+
+use crate::commands::Command;
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::{Column, QueryResultWriter};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+use tokio::sync::Notify;
+
+static NEXT_CONNECTION_ID: AtomicU32 = AtomicU32::new(1);
+static QUESTIONS: AtomicU64 = AtomicU64::new(0);
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    started: None,
+    connections: BTreeMap::new(),
+});
+
+struct Registry {
+    started: Option<Instant>,
+    connections: BTreeMap<u32, Process>,
+}
+
+struct Process {
+    user: String,
+    host: String,
+    db: Option<String>,
+    command: &'static str,
+    since: Instant,
+    info: Option<String>,
+    killed: CancellationToken,
+    query: Option<CancellationToken>,
+}
+
+fn registry() -> MutexGuard<'static, Registry> {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry.started.get_or_insert_with(Instant::now);
+    registry
+}
+
+/// Count a statement executed on behalf of a client towards the server's `Questions`.
+pub(crate) fn count_question() {
+    QUESTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// What became of a `KILL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kill {
+    /// The connection, or its statement, was interrupted.
+    Killed,
+    /// There is no connection with the given id.
+    NoSuchThread,
+    /// The connection belongs to another user.
+    NotOwner,
+}
+
+/// Signals that a client has asked for a running statement (or a whole connection) to be
+/// cancelled, typically by issuing `KILL QUERY <id>` from another connection.
+///
+/// Tokens are cheap to clone, and all clones observe the same cancellation.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<CancellationInner>,
+}
+
+#[derive(Debug, Default)]
+struct CancellationInner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    /// Whether cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until cancellation is requested.
+    pub async fn cancelled(&self) {
+        loop {
+            // register interest before checking the flag so that a concurrent cancel isn't missed
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+}
+
+/// A connection's entry in the registry, which is removed again when this is dropped.
+pub(crate) struct Registration {
+    id: u32,
+    killed: CancellationToken,
+}
+
+impl Registration {
+    pub(crate) fn new(host: String) -> Self {
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        let killed = CancellationToken::default();
+        registry().connections.insert(
+            id,
+            Process {
+                user: String::new(),
+                host,
+                db: None,
+                command: "Connect",
+                since: Instant::now(),
+                info: None,
+                killed: killed.clone(),
+                query: None,
+            },
+        );
+        Registration { id, killed }
+    }
+
+    pub(crate) fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn update<F: FnOnce(&mut Process)>(&self, f: F) {
+        if let Some(process) = registry().connections.get_mut(&self.id) {
+            f(process);
+        }
+    }
+
+    pub(crate) fn set_user(&self, user: &[u8]) {
+        self.update(|p| p.user = String::from_utf8_lossy(user).into_owned());
+    }
+
+    /// Interrupt the statement that connection `id` is currently executing, if any, and unless
+    /// `query_only` is set, close the connection as well.
+    ///
+    /// Only connections of the same user as this one can be killed.
+    pub(crate) fn kill(&self, id: u32, query_only: bool) -> Kill {
+        let registry = registry();
+        let process = match registry.connections.get(&id) {
+            Some(process) => process,
+            None => return Kill::NoSuchThread,
+        };
+        match registry.connections.get(&self.id) {
+            Some(own) if own.user == process.user => {}
+            _ => return Kill::NotOwner,
+        }
+        if let Some(ref query) = process.query {
+            query.cancel();
+        }
+        if !query_only {
+            process.killed.cancel();
+        }
+        Kill::Killed
+    }
+
+    /// List the connections of the same user as this one, ordered by id.
+    pub(crate) fn processes(&self) -> Vec<ProcessInfo> {
+        let registry = registry();
+        let user = registry.connections.get(&self.id).map(|p| &p.user);
+        list(&registry, |p| Some(&p.user) == user)
+    }
+
+    /// Whether the connection has been killed, and should be closed.
+    pub(crate) fn is_killed(&self) -> bool {
+        self.killed.is_cancelled()
+    }
+
+    /// A token that is cancelled when the connection is killed.
+    pub(crate) fn killed(&self) -> &CancellationToken {
+        &self.killed
+    }
+
+    /// Record that the connection started executing `cmd`.
+    ///
+    /// Returns the token through which the command can be cancelled.
+    pub(crate) fn begin(&self, cmd: &Command<'_>) -> CancellationToken {
+        let (command, info) = match *cmd {
+            Command::Query(q) => ("Query", Some(q)),
+            Command::Prepare(q) => ("Prepare", Some(q)),
+            Command::Execute { .. } | Command::BulkExecute { .. } => ("Execute", None),
+            Command::SendLongData { .. } => ("Long Data", None),
+            Command::Close(_) => ("Close stmt", None),
+            Command::ListFields { .. } => ("Field List", None),
+            Command::Init(_) => ("Init DB", None),
+            Command::SetOption(_) => ("Set option", None),
+            Command::Statistics => ("Statistics", None),
+            Command::ProcessInfo => ("Processlist", None),
+            Command::ProcessKill(_) => ("Kill", None),
+            Command::Ping => ("Ping", None),
+            Command::Quit => ("Quit", None),
+        };
+        let token = CancellationToken::default();
+        self.update(|p| {
+            p.command = command;
+            p.since = Instant::now();
+            p.info = info.map(|q| String::from_utf8_lossy(q).into_owned());
+            p.query = Some(token.clone());
+        });
+        token
+    }
+
+    /// Record that the connection is idle again, with `db` as its current database.
+    pub(crate) fn end(&self, db: Option<&str>) {
+        self.update(|p| {
+            p.command = "Sleep";
+            p.since = Instant::now();
+            p.info = None;
+            p.query = None;
+            p.db = db.map(String::from);
+        });
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        registry().connections.remove(&self.id);
+    }
+}
+
+/// A snapshot of the server-wide counters, as reported to a client that sends `COM_STATISTICS`
+/// (e.g., `mysqladmin status`).
+///
+/// The counters cover all the connections handled by this process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerStatistics {
+    /// Number of seconds since the first client connected.
+    pub uptime: u64,
+    /// Number of currently connected clients.
+    pub threads: usize,
+    /// Number of statements that clients have issued.
+    pub questions: u64,
+}
+
+impl ServerStatistics {
+    /// Take a snapshot of the current server-wide counters.
+    pub fn current() -> Self {
+        let registry = registry();
+        ServerStatistics {
+            uptime: registry.started.map_or(0, |s| s.elapsed().as_secs()),
+            threads: registry.connections.len(),
+            questions: QUESTIONS.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl fmt::Display for ServerStatistics {
+    /// Formats the statistics the way `mysqladmin status` expects them.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let qps = if self.uptime == 0 {
+            0.0
+        } else {
+            self.questions as f64 / self.uptime as f64
+        };
+        write!(
+            f,
+            "Uptime: {}  Threads: {}  Questions: {}  Slow queries: 0  Opens: 0  \
+             Flush tables: 0  Open tables: 0  Queries per second avg: {:.3}",
+            self.uptime, self.threads, self.questions, qps
+        )
+    }
+}
+
+/// Information about a single client connection, as reported to a client that sends
+/// `COM_PROCESS_INFO`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// The connection's id.
+    pub id: u32,
+    /// The user that the client logged in as.
+    pub user: String,
+    /// The address of the client, if known.
+    pub host: String,
+    /// The client's current database, if any.
+    pub db: Option<String>,
+    /// The kind of command the connection is executing, or `"Sleep"` if it is idle.
+    pub command: String,
+    /// Number of seconds the connection has spent in its current state.
+    pub time: u64,
+    /// The text of the statement being executed, if any.
+    pub info: Option<String>,
+}
+
+impl ProcessInfo {
+    /// List all the client connections that are currently open, ordered by id.
+    pub fn list() -> Vec<ProcessInfo> {
+        list(&registry(), |_| true)
+    }
+}
+
+fn list<F: Fn(&Process) -> bool>(registry: &Registry, filter: F) -> Vec<ProcessInfo> {
+    registry
+        .connections
+        .iter()
+        .filter(|&(_, p)| filter(p))
+        .map(|(&id, p)| ProcessInfo {
+            id,
+            user: p.user.clone(),
+            host: p.host.clone(),
+            db: p.db.clone(),
+            command: p.command.to_owned(),
+            time: p.since.elapsed().as_secs(),
+            info: p.info.clone(),
+        })
+        .collect()
+}
+
+/// Send `processes` to the client as the resultset of a `COM_PROCESS_INFO`.
+pub(crate) fn write_process_list<W: Write>(
+    processes: &[ProcessInfo],
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    let column = |name: &str, coltype, colflags| Column {
+        table: String::new(),
+        column: name.to_owned(),
+        coltype,
+        colflags,
+    };
+    let cols = [
+        column(
+            "Id",
+            ColumnType::MYSQL_TYPE_LONGLONG,
+            ColumnFlags::NOT_NULL_FLAG | ColumnFlags::UNSIGNED_FLAG,
+        ),
+        column("User", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::NOT_NULL_FLAG),
+        column("Host", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::NOT_NULL_FLAG),
+        column("db", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::empty()),
+        column("Command", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::NOT_NULL_FLAG),
+        column("Time", ColumnType::MYSQL_TYPE_LONG, ColumnFlags::NOT_NULL_FLAG),
+        column("State", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::empty()),
+        column("Info", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::empty()),
+    ];
+
+    let mut w = results.start(&cols)?;
+    for p in processes {
+        w.write_col(u64::from(p.id))?;
+        w.write_col(&p.user)?;
+        w.write_col(&p.host)?;
+        w.write_col(&p.db)?;
+        w.write_col(&p.command)?;
+        w.write_col(p.time)?;
+        w.write_col("")?;
+        w.write_col(&p.info)?;
+        w.end_row()?;
+    }
+    w.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_formats_statistics() {
+        let stats = ServerStatistics {
+            uptime: 10,
+            threads: 2,
+            questions: 25,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "Uptime: 10  Threads: 2  Questions: 25  Slow queries: 0  Opens: 0  \
+             Flush tables: 0  Open tables: 0  Queries per second avg: 2.500"
+        );
+    }
+
+    #[test]
+    fn it_registers_connections() {
+        let conn = Registration::new("127.0.0.1:4242".to_owned());
+        conn.set_user(b"jon");
+        conn.begin(&Command::Query(b"SELECT 1"));
+
+        let process = ProcessInfo::list()
+            .into_iter()
+            .find(|p| p.id == conn.id())
+            .unwrap();
+        assert_eq!(process.user, "jon");
+        assert_eq!(process.host, "127.0.0.1:4242");
+        assert_eq!(process.command, "Query");
+        assert_eq!(process.info.as_deref(), Some("SELECT 1"));
+
+        conn.end(Some("test"));
+        let process = ProcessInfo::list()
+            .into_iter()
+            .find(|p| p.id == conn.id())
+            .unwrap();
+        assert_eq!(process.command, "Sleep");
+        assert_eq!(process.db.as_deref(), Some("test"));
+        assert_eq!(process.info, None);
+
+        let id = conn.id();
+        drop(conn);
+        assert!(ProcessInfo::list().into_iter().all(|p| p.id != id));
+    }
+
+    #[test]
+    fn it_kills_queries() {
+        let conn = Registration::new(String::new());
+        let query = conn.begin(&Command::Query(b"SELECT SLEEP(10)"));
+        assert_eq!(conn.kill(conn.id(), true), Kill::Killed);
+        assert!(query.is_cancelled());
+        assert!(!conn.is_killed());
+
+        // a query that starts later is not affected
+        conn.end(None);
+        let query = conn.begin(&Command::Query(b"SELECT 1"));
+        assert!(!query.is_cancelled());
+
+        let other = Registration::new(String::new());
+        assert_eq!(other.kill(conn.id(), false), Kill::Killed);
+        assert!(query.is_cancelled());
+        assert!(conn.is_killed());
+
+        let id = conn.id();
+        drop(conn);
+        assert_eq!(other.kill(id, false), Kill::NoSuchThread);
+    }
+
+    #[test]
+    fn it_keeps_users_to_their_own_connections() {
+        let owner = Registration::new(String::new());
+        owner.set_user(b"owner");
+        let query = owner.begin(&Command::Query(b"SELECT SLEEP(10)"));
+        let intruder = Registration::new(String::new());
+        intruder.set_user(b"intruder");
+
+        assert_eq!(intruder.kill(owner.id(), false), Kill::NotOwner);
+        assert!(!query.is_cancelled());
+        assert!(!owner.is_killed());
+
+        let ids = |conn: &Registration| conn.processes().iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(ids(&intruder), [intruder.id()]);
+        assert_eq!(ids(&owner), [owner.id()]);
+        let all = ProcessInfo::list();
+        assert!(all.iter().any(|p| p.id == owner.id()));
+        assert!(all.iter().any(|p| p.id == intruder.id()));
+    }
+}
@@ -1,22 +1,34 @@
+#[cfg(feature = "arrow")]
+use crate::arrow::BatchColumn;
 use crate::myc::constants::{ColumnFlags, StatusFlags};
-use crate::packet::PacketWriter;
+use crate::packet::{Client, PacketWriter};
 use crate::value::ToMysqlValue;
+use crate::warnings::Warning;
 use crate::writers;
-use crate::{Column, ErrorKind, StatementData};
+use crate::{
+    CancellationToken, Column, ColumnDefinition, ConnectionState, ErrorKind, QueryAttribute,
+    StatementData, SystemVariables, WarningLevel,
+};
 use byteorder::WriteBytesExt;
+use futures_core::Stream;
 use std::borrow::Borrow;
+use std::cmp;
 use std::collections::HashMap;
-use std::io::{self, Write};
+use std::future;
+use std::io::{self, Cursor, Read, Write};
 
 /// Convenience type for responding to a client `USE <db>` command.
 pub struct InitWriter<'a, W: Write> {
     pub(crate) writer: &'a mut PacketWriter<W>,
+    pub(crate) state: &'a mut ConnectionState,
+    pub(crate) schema: &'a str,
 }
 
 impl<'a, W: Write + 'a> InitWriter<'a, W> {
     /// Tell client that database context has been changed
     pub fn ok(self) -> io::Result<()> {
-        writers::write_ok_packet(self.writer, 0, 0, StatusFlags::empty())
+        self.state.schema = Some(self.schema.to_owned());
+        writers::write_ok_packet(self.writer, 0, 0, self.state.status(), 0, b"")
     }
 
     /// Tell client that there was a problem changing the database context.
@@ -27,6 +39,30 @@ impl<'a, W: Write + 'a> InitWriter<'a, W> {
     where
         E: Borrow<[u8]> + ?Sized,
     {
+        self.state.errored = true;
+        writers::write_err(kind, msg.borrow(), self.writer)
+    }
+}
+
+/// Convenience type for responding to a client `PING` command.
+pub struct PingWriter<'a, W: Write> {
+    pub(crate) writer: &'a mut PacketWriter<W>,
+    pub(crate) state: &'a mut ConnectionState,
+}
+
+impl<'a, W: Write + 'a> PingWriter<'a, W> {
+    /// Tell the client that the server is alive.
+    pub fn ok(self) -> io::Result<()> {
+        writers::write_ok_packet(self.writer, 0, 0, self.state.status(), 0, b"")
+    }
+
+    /// Tell the client that the server cannot serve it right now, typically with
+    /// `ErrorKind::ER_SERVER_SHUTDOWN`.
+    pub fn error<E>(self, kind: ErrorKind, msg: &E) -> io::Result<()>
+    where
+        E: Borrow<[u8]> + ?Sized,
+    {
+        self.state.errored = true;
         writers::write_err(kind, msg.borrow(), self.writer)
     }
 }
@@ -68,6 +104,28 @@ impl<'a, W: Write + 'a> StatementMetaWriter<'a, W> {
         writers::write_prepare_ok(id, params, columns, self.writer)
     }
 
+    /// Reply to the client with the given meta-information, describing the parameters and
+    /// columns with their full [`ColumnDefinition`](struct.ColumnDefinition.html)s.
+    ///
+    /// See [`reply`](struct.StatementMetaWriter.html#method.reply).
+    pub fn reply_with_definitions<PI, CI>(self, id: u32, params: PI, columns: CI) -> io::Result<()>
+    where
+        PI: IntoIterator<Item = &'a ColumnDefinition>,
+        CI: IntoIterator<Item = &'a ColumnDefinition>,
+        <PI as IntoIterator>::IntoIter: ExactSizeIterator,
+        <CI as IntoIterator>::IntoIter: ExactSizeIterator,
+    {
+        let params = params.into_iter();
+        self.stmts.insert(
+            id,
+            StatementData {
+                params: params.len() as u16,
+                ..Default::default()
+            },
+        );
+        writers::write_prepare_ok(id, params, columns, self.writer)
+    }
+
     /// Reply to the client's `PREPARE` with an error.
     pub fn error<E>(self, kind: ErrorKind, msg: &E) -> io::Result<()>
     where
@@ -77,8 +135,53 @@ impl<'a, W: Write + 'a> StatementMetaWriter<'a, W> {
     }
 }
 
+/// Everything the client is told about a statement that completed without returning rows.
+///
+/// Pass one to [`completed_with`](struct.QueryResultWriter.html#method.completed_with)
+/// to report more than the affected rows and last insert id:
+///
+/// ```
+/// # use msql_srv::*;
+/// let completion = Completion {
+///     rows: 2,
+///     info: "Rows matched: 3  Changed: 2  Warnings: 0".to_owned(),
+///     status: StatusFlags::SERVER_STATUS_NO_INDEX_USED,
+///     ..Default::default()
+/// };
+/// assert_eq!(completion.last_insert_id, 0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// The number of rows affected by the statement.
+    pub rows: u64,
+    /// An identifier for the client's most recent insertion, or 0.
+    pub last_insert_id: u64,
+    /// A human-readable summary of what the statement did, like the
+    /// `Records: 3  Duplicates: 0  Warnings: 0` that MySQL sends for multi-row inserts.
+    pub info: String,
+    /// Warnings to report on top of those added with
+    /// [`add_warning`](struct.QueryResultWriter.html#method.add_warning), for backends that only
+    /// know how many there were.
+    pub warnings: u16,
+    /// Status flags to set on top of the ones tracked for the connection, like
+    /// `SERVER_STATUS_NO_INDEX_USED`.
+    pub status: StatusFlags,
+}
+
+impl Default for Completion {
+    fn default() -> Self {
+        Completion {
+            rows: 0,
+            last_insert_id: 0,
+            info: String::new(),
+            warnings: 0,
+            status: StatusFlags::empty(),
+        }
+    }
+}
+
 enum Finalizer {
-    Ok { rows: u64, last_insert_id: u64 },
+    Ok(Completion),
     EOF,
 }
 
@@ -99,36 +202,185 @@ enum Finalizer {
 /// `QueryResultWriter` *may* be dropped without calling `no_more_results`, but in this case the
 /// program may panic if an I/O error occurs when sending the end-of-records marker to the client.
 /// To handle such errors, call `no_more_results` explicitly.
+///
+/// If the client cancels the statement (see
+/// [`cancellation_token`](struct.QueryResultWriter.html#method.cancellation_token)), the
+/// statement is ended with an `ER_QUERY_INTERRUPTED` error at the next resultset or row boundary,
+/// and anything written after that is silently discarded.
 #[must_use]
 pub struct QueryResultWriter<'a, W: Write> {
     // XXX: specialization instead?
     pub(crate) is_bin: bool,
     pub(crate) writer: &'a mut PacketWriter<W>,
+    pub(crate) state: &'a mut ConnectionState,
+    attributes: &'a [QueryAttribute<'a>],
+    client: Option<Client<'a>>,
     last_end: Option<Finalizer>,
 }
 
 impl<'a, W: Write> QueryResultWriter<'a, W> {
-    pub(crate) fn new(writer: &'a mut PacketWriter<W>, is_bin: bool) -> Self {
+    pub(crate) fn new(
+        writer: &'a mut PacketWriter<W>,
+        state: &'a mut ConnectionState,
+        is_bin: bool,
+    ) -> Self {
         QueryResultWriter {
             is_bin,
             writer,
+            state,
+            attributes: &[],
+            client: None,
             last_end: None,
         }
     }
 
+    pub(crate) fn with_attributes(mut self, attributes: &'a [QueryAttribute<'a>]) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub(crate) fn with_client(mut self, client: Client<'a>) -> Self {
+        self.client = Some(client);
+        self
+    }
+
     fn finalize(&mut self, more_exists: bool) -> io::Result<()> {
-        let mut status = StatusFlags::empty();
+        if self.state.reading_file {
+            // the client only reads the reply once it has sent all of the file
+            if let Some(client) = self.client.and_then(|c| c.as_blocking()) {
+                while let Some((seq, packet)) = client.read_packet()? {
+                    self.writer.set_seq(seq.wrapping_add(1));
+                    if packet.is_empty() {
+                        break;
+                    }
+                }
+                self.state.reading_file = false;
+            }
+        }
+
+        let mut status = self.state.status();
         if more_exists {
             status.set(StatusFlags::SERVER_MORE_RESULTS_EXISTS, true);
         }
+        let warnings = self.state.warning_count();
         match self.last_end.take() {
             None => Ok(()),
-            Some(Finalizer::Ok {
-                rows,
-                last_insert_id,
-            }) => writers::write_ok_packet(self.writer, rows, last_insert_id, status),
-            Some(Finalizer::EOF) => writers::write_eof_packet(self.writer, status),
+            Some(Finalizer::Ok(completion)) => match self.state.bulk {
+                // the rows of a bulk execution are reported to the client all at once
+                Some((ref mut total, ref mut first_insert_id)) => {
+                    *total += completion.rows;
+                    if *first_insert_id == 0 {
+                        *first_insert_id = completion.last_insert_id;
+                    }
+                    Ok(())
+                }
+                None => writers::write_ok_packet(
+                    self.writer,
+                    completion.rows,
+                    completion.last_insert_id,
+                    status | completion.status,
+                    warnings.saturating_add(completion.warnings),
+                    completion.info.as_bytes(),
+                ),
+            },
+            Some(Finalizer::EOF) => writers::write_eof_packet(self.writer, status, warnings),
+        }
+    }
+
+    /// A writer for the results of another statement, sent as part of the same response.
+    pub(crate) fn reborrow(&mut self) -> QueryResultWriter<'_, W> {
+        QueryResultWriter {
+            is_bin: self.is_bin,
+            writer: self.writer,
+            state: self.state,
+            attributes: self.attributes,
+            client: self.client,
+            last_end: None,
+        }
+    }
+
+    /// If the statement has been cancelled, end it with an `ER_QUERY_INTERRUPTED` error.
+    ///
+    /// Returns whether the statement has been ended by an error. Must only be called between
+    /// packets.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if !self.state.errored && self.state.cancellation.is_cancelled() {
+            self.finalize(true)?;
+            self.state.errored = true;
+            self.state.warnings.push(Warning {
+                level: WarningLevel::Error,
+                kind: ErrorKind::ER_QUERY_INTERRUPTED,
+                message: "Query execution was interrupted".to_owned(),
+            });
+            writers::write_err(
+                ErrorKind::ER_QUERY_INTERRUPTED,
+                b"Query execution was interrupted",
+                self.writer,
+            )?;
+        }
+        Ok(self.state.errored)
+    }
+
+    /// The query attributes that the client attached to this query or statement execution.
+    ///
+    /// Only MySQL 8 clients send query attributes; for all other clients, this is empty.
+    pub fn attributes(&self) -> &[QueryAttribute<'a>] {
+        self.attributes
+    }
+
+    /// The system variables of this connection, including any changes the client has made to
+    /// them with `SET` statements that were applied by the server.
+    pub fn variables(&self) -> &SystemVariables {
+        &self.state.variables
+    }
+
+    /// Attach a note, warning or error with the given code and message to the result of this
+    /// statement.
+    ///
+    /// The number of these is sent to the client along with the result, and the client can list
+    /// them with `SHOW WARNINGS` until it issues another statement.
+    pub fn add_warning(&mut self, level: WarningLevel, kind: ErrorKind, msg: &str) {
+        self.state.warnings.push(Warning {
+            level,
+            kind,
+            message: msg.to_owned(),
+        });
+    }
+
+    /// A token that is cancelled if the client asks for this statement to be cancelled, for
+    /// example by issuing `KILL QUERY` from another connection.
+    ///
+    /// Long-running statements should check the token periodically (or, in async code, wait for
+    /// [`CancellationToken::cancelled`](struct.CancellationToken.html#method.cancelled)) and stop
+    /// early once it is cancelled.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.state.cancellation.clone()
+    }
+
+    /// Ask the client to send the contents of its local file `filename`, as it would for a
+    /// `LOAD DATA LOCAL INFILE` statement.
+    ///
+    /// The returned [`LocalFile`](struct.LocalFile.html) reads the file as the client sends it.
+    /// Once the file has been read, reply to the client as usual, typically with
+    /// [`completed`](struct.QueryResultWriter.html#method.completed) and the number of rows that
+    /// were loaded. If the reply is sent before the whole file has been read, the rest of the file
+    /// is discarded.
+    ///
+    /// Returns `None` if the client does not allow the server to ask for local files, or if this
+    /// is the result of a prepared statement. `ER_NOT_ALLOWED_COMMAND` is the usual reply then.
+    pub fn request_local_file(&mut self, filename: &str) -> io::Result<Option<LocalFile<'_, W>>> {
+        if !self.state.local_files || self.is_bin || self.client.is_none() {
+            return Ok(None);
         }
+        self.finalize(true)?;
+        Ok(Some(LocalFile {
+            writer: self.writer,
+            state: self.state,
+            client: self.client.unwrap(),
+            filename: Some(filename.as_bytes().to_vec()),
+            packet: Vec::new(),
+            at: 0,
+        }))
     }
 
     /// Start a resultset response to the client that conforms to the given `columns`.
@@ -138,18 +390,40 @@ impl<'a, W: Write> QueryResultWriter<'a, W> {
     /// See [`RowWriter`](struct.RowWriter.html).
     pub fn start(mut self, columns: &'a [Column]) -> io::Result<RowWriter<'a, W>> {
         self.finalize(true)?;
-        RowWriter::new(self, columns)
+        RowWriter::new(self, Columns::Plain(columns))
+    }
+
+    /// Start a resultset response to the client that conforms to the given `columns`, which are
+    /// described with their full [`ColumnDefinition`](struct.ColumnDefinition.html)s.
+    ///
+    /// See [`start`](struct.QueryResultWriter.html#method.start).
+    pub fn start_with_definitions(
+        mut self,
+        columns: &'a [ColumnDefinition],
+    ) -> io::Result<RowWriter<'a, W>> {
+        self.finalize(true)?;
+        RowWriter::new(self, Columns::Defined(columns))
     }
 
     /// Send an empty resultset response to the client indicating that `rows` rows were affected by
     /// the query in this resultset. `last_insert_id` may be given to communiate an identifier for
     /// a client's most recent insertion.
-    pub fn complete_one(mut self, rows: u64, last_insert_id: u64) -> io::Result<Self> {
-        self.finalize(true)?;
-        self.last_end = Some(Finalizer::Ok {
+    pub fn complete_one(self, rows: u64, last_insert_id: u64) -> io::Result<Self> {
+        self.complete_one_with(Completion {
             rows,
             last_insert_id,
-        });
+            ..Default::default()
+        })
+    }
+
+    /// Send an empty resultset response to the client, described in full by `completion`.
+    ///
+    /// See [`complete_one`](struct.QueryResultWriter.html#method.complete_one).
+    pub fn complete_one_with(mut self, completion: Completion) -> io::Result<Self> {
+        if !self.interrupted()? {
+            self.finalize(true)?;
+            self.last_end = Some(Finalizer::Ok(completion));
+        }
         Ok(self)
     }
 
@@ -160,12 +434,30 @@ impl<'a, W: Write> QueryResultWriter<'a, W> {
         self.complete_one(rows, last_insert_id)?.no_more_results()
     }
 
+    /// Send an empty resultset response to the client, described in full by `completion`. Use
+    /// this to send an info message, extra warnings or status flags along with the affected rows.
+    ///
+    /// See [`completed`](struct.QueryResultWriter.html#method.completed).
+    pub fn completed_with(self, completion: Completion) -> io::Result<()> {
+        self.complete_one_with(completion)?.no_more_results()
+    }
+
     /// Reply to the client's query with an error.
     pub fn error<E>(mut self, kind: ErrorKind, msg: &E) -> io::Result<()>
     where
         E: Borrow<[u8]> + ?Sized,
     {
+        if self.state.errored {
+            // the statement has already been interrupted
+            return Ok(());
+        }
         self.finalize(true)?;
+        self.state.errored = true;
+        self.state.warnings.push(Warning {
+            level: WarningLevel::Error,
+            kind,
+            message: String::from_utf8_lossy(msg.borrow()).into_owned(),
+        });
         writers::write_err(kind, msg.borrow(), self.writer)
     }
 
@@ -178,10 +470,207 @@ impl<'a, W: Write> QueryResultWriter<'a, W> {
 
 impl<'a, W: Write> Drop for QueryResultWriter<'a, W> {
     fn drop(&mut self) {
+        if self.state.reading_file {
+            // nothing has been sent since the file was requested, and the intermediary discards
+            // the rest of the file once the shim returns
+            return;
+        }
         self.finalize(false).unwrap();
     }
 }
 
+impl<'a> QueryResultWriter<'a, Cursor<Vec<u8>>> {
+    /// Start a resultset response to the client that conforms to the given `columns`, and send
+    /// it each row that `rows` yields as soon as enough of them have been produced, rather than
+    /// once the shim returns.
+    ///
+    /// See [`RowWriter::write_row_async`](struct.RowWriter.html#method.write_row_async).
+    pub async fn stream_rows<S, I, E>(self, columns: &'a [Column], rows: S) -> io::Result<()>
+    where
+        S: Stream<Item = I>,
+        I: IntoIterator<Item = E>,
+        E: ToMysqlValue,
+    {
+        let mut writer = self.start(columns)?;
+        tokio::pin!(rows);
+        while let Some(row) = future::poll_fn(|cx| rows.as_mut().poll_next(cx)).await {
+            writer.write_row_async(row).await?;
+        }
+        writer.finish()
+    }
+}
+
+/// The contents of a local file that the client sends at the request of
+/// [`QueryResultWriter::request_local_file`](struct.QueryResultWriter.html#method.request_local_file).
+///
+/// The file is requested from the client when it is first read. In a
+/// [`MysqlShim`](trait.MysqlShim.html), read it through `std::io::Read`; in an
+/// [`AsyncMysqlShim`](trait.AsyncMysqlShim.html), use
+/// [`read_async`](struct.LocalFile.html#method.read_async) instead.
+pub struct LocalFile<'a, W: Write> {
+    writer: &'a mut PacketWriter<W>,
+    state: &'a mut ConnectionState,
+    client: Client<'a>,
+    // the name of the file, until it has been requested
+    filename: Option<Vec<u8>>,
+    packet: Vec<u8>,
+    at: usize,
+}
+
+impl<'a, W: Write> LocalFile<'a, W> {
+    fn exhausted(&self) -> bool {
+        self.at == self.packet.len() && self.filename.is_none() && !self.state.reading_file
+    }
+
+    fn received(&mut self, packet: Option<(u8, Vec<u8>)>) -> io::Result<()> {
+        let (seq, packet) = packet.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "client disconnected while sending a local file",
+            )
+        })?;
+        self.writer.set_seq(seq.wrapping_add(1));
+        // an empty packet marks the end of the file
+        self.state.reading_file = !packet.is_empty();
+        self.packet = packet;
+        self.at = 0;
+        Ok(())
+    }
+
+    fn copy_to(&mut self, buf: &mut [u8]) -> usize {
+        let n = cmp::min(buf.len(), self.packet.len() - self.at);
+        buf[..n].copy_from_slice(&self.packet[self.at..self.at + n]);
+        self.at += n;
+        n
+    }
+}
+
+/// Send the request for a local file, unless that has already been done.
+fn request_file<W: Write>(
+    filename: &mut Option<Vec<u8>>,
+    writer: &mut PacketWriter<W>,
+    state: &mut ConnectionState,
+) -> io::Result<()> {
+    if let Some(filename) = filename.take() {
+        writer.write_u8(0xFB)?;
+        writer.write_all(&filename)?;
+        writer.end_packet()?;
+        state.reading_file = true;
+    }
+    writer.flush()
+}
+
+impl<'a, W: Write> Read for LocalFile<'a, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.at == self.packet.len() && !self.exhausted() {
+            let packet = match self.client.as_blocking() {
+                Some(client) => {
+                    request_file(&mut self.filename, self.writer, self.state)?;
+                    client.read_packet()?
+                }
+                None => {
+                    return Err(io::Error::other(
+                        "local files must be read with read_async in async shims",
+                    ))
+                }
+            };
+            self.received(packet)?;
+        }
+        Ok(self.copy_to(buf))
+    }
+}
+
+impl<'a> LocalFile<'a, Cursor<Vec<u8>>> {
+    /// Pull some bytes of the file into `buf`, returning how many bytes were read.
+    ///
+    /// Like `std::io::Read::read`, this returns `0` once the whole file has been read.
+    pub async fn read_async(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.at == self.packet.len() && !self.exhausted() {
+            let packet = match self.client.as_async() {
+                Some(client) => {
+                    request_file(&mut self.filename, self.writer, self.state)?;
+                    // the client only sends the file once it has seen the request
+                    let output = self.writer.w.get_mut();
+                    let packet = client.exchange(output).await?;
+                    output.clear();
+                    self.writer.w.set_position(0);
+                    packet
+                }
+                None => return self.read(buf),
+            };
+            self.received(packet)?;
+            self.state.flushed = Some((0, self.writer.seq()));
+        }
+        Ok(self.copy_to(buf))
+    }
+}
+
+/// How much output `RowWriter::write_row_async` buffers before it sends it to the client.
+const STREAM_CHUNK: usize = 16 * 1024;
+
+/// Send the packets that have been written so far to the client, waiting for it to catch up if it
+/// reads them more slowly than they are produced.
+async fn send_buffered(
+    writer: &mut PacketWriter<Cursor<Vec<u8>>>,
+    state: &mut ConnectionState,
+    client: Option<Client<'_>>,
+) -> io::Result<()> {
+    let client = match client.and_then(|c| c.as_async()) {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    if state.reading_file {
+        // the client only reads the reply once it has sent all of the file
+        return Ok(());
+    }
+    let seq = writer.seq();
+    while !writer.w.get_ref().is_empty() {
+        let output = writer.w.get_mut();
+        // if the statement is cancelled while this is sent, the client must still get the rest
+        state.flushed = Some((output.len() as u64, seq));
+        let n = client.send(output).await?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "client stopped accepting output",
+            ));
+        }
+        output.drain(..n);
+        let left = output.len() as u64;
+        writer.w.set_position(left);
+    }
+    client.flush_output().await?;
+    state.flushed = Some((0, seq));
+    Ok(())
+}
+
+/// The columns of a resultset, as plain columns or with their full definitions.
+#[derive(Clone, Copy)]
+enum Columns<'a> {
+    Plain(&'a [Column]),
+    Defined(&'a [ColumnDefinition]),
+}
+
+impl<'a> Columns<'a> {
+    fn len(&self) -> usize {
+        match self {
+            Columns::Plain(columns) => columns.len(),
+            Columns::Defined(columns) => columns.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, i: usize) -> Option<&'a Column> {
+        match self {
+            Columns::Plain(columns) => columns.get(i),
+            Columns::Defined(columns) => columns.get(i).map(|d| &d.column),
+        }
+    }
+}
+
 /// Convenience type for sending rows of a resultset to a client.
 ///
 /// Rows can either be written out one column at a time (using
@@ -194,12 +683,17 @@ impl<'a, W: Write> Drop for QueryResultWriter<'a, W> {
 /// [`finish`](struct.RowWriter.html#method.finish). However, in this case, the program may panic
 /// if an I/O error occurs when sending the end-of-records marker to the client. To avoid this,
 /// call [`finish`](struct.RowWriter.html#method.finish) explicitly.
+///
+/// In an [`AsyncMysqlShim`](trait.AsyncMysqlShim.html), the rows are buffered until the shim
+/// returns. To send a large resultset while it is produced, write its rows with
+/// [`write_row_async`](struct.RowWriter.html#method.write_row_async) instead, or hand a stream of
+/// them to [`QueryResultWriter::stream_rows`](struct.QueryResultWriter.html#method.stream_rows).
 #[must_use]
 pub struct RowWriter<'a, W: Write> {
     result: Option<QueryResultWriter<'a, W>>,
     bitmap_len: usize,
     data: Vec<u8>,
-    columns: &'a [Column],
+    columns: Columns<'a>,
 
     // next column to write for the current row
     // NOTE: (ab)used to track number of *rows* for a zero-column resultset
@@ -212,10 +706,7 @@ impl<'a, W> RowWriter<'a, W>
 where
     W: Write + 'a,
 {
-    fn new(
-        result: QueryResultWriter<'a, W>,
-        columns: &'a [Column],
-    ) -> io::Result<RowWriter<'a, W>> {
+    fn new(result: QueryResultWriter<'a, W>, columns: Columns<'a>) -> io::Result<RowWriter<'a, W>> {
         let bitmap_len = (columns.len() + 7 + 2) / 8;
         let mut rw = RowWriter {
             result: Some(result),
@@ -233,8 +724,12 @@ where
 
     #[inline]
     fn start(&mut self) -> io::Result<()> {
-        if !self.columns.is_empty() {
-            writers::column_definitions(self.columns, self.result.as_mut().unwrap().writer)?;
+        let result = self.result.as_mut().unwrap();
+        if !self.columns.is_empty() && !result.interrupted()? {
+            match self.columns {
+                Columns::Plain(columns) => writers::column_definitions(columns, result.writer)?,
+                Columns::Defined(columns) => writers::column_definitions(columns, result.writer)?,
+            }
         }
         Ok(())
     }
@@ -258,6 +753,11 @@ where
             return Ok(());
         }
 
+        if self.col == 0 && self.result.as_mut().unwrap().interrupted()? {
+            // the statement was cancelled, so the rest of its rows are discarded
+            return Ok(());
+        }
+
         if self.result.as_mut().unwrap().is_bin {
             if self.col == 0 {
                 self.result.as_mut().unwrap().writer.write_u8(0x00)?;
@@ -266,16 +766,12 @@ where
                 self.data.resize(self.bitmap_len, 0);
             }
 
-            let c = self
-                .columns
-                .get(self.col)
-                .ok_or_else(|| {
-                    io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "row has more columns than specification",
-                    )
-                })?
-                .borrow();
+            let c = self.columns.get(self.col).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "row has more columns than specification",
+                )
+            })?;
             if v.is_null() {
                 if c.colflags.contains(ColumnFlags::NOT_NULL_FLAG) {
                     return Err(io::Error::new(
@@ -305,6 +801,10 @@ where
             return Ok(());
         }
 
+        if self.result.as_ref().unwrap().state.errored {
+            return Ok(());
+        }
+
         if self.col != self.columns.len() {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
@@ -344,6 +844,95 @@ where
         }
         self.end_row()
     }
+
+    /// Write a single row, serialized from `row`, as a part of this resultset.
+    ///
+    /// The row must serialize as a struct, whose fields are matched to the columns given to
+    /// [`QueryResultWriter::start`](struct.QueryResultWriter.html#method.start) by name, or as a
+    /// tuple or sequence, whose elements are matched to them by position. Either way, there must be
+    /// exactly one field per column. Each field is written like the scalar it serializes as would
+    /// be by [`write_col`](struct.RowWriter.html#method.write_col): `None` and `()` are `NULL`,
+    /// `bool`s are `0` or `1`, and unit enum variants are their names. If the row cannot be
+    /// written, none of it is sent, and the next row can be written as usual.
+    ///
+    /// ```ignore
+    /// #[derive(Serialize)]
+    /// struct User {
+    ///     id: u64,
+    ///     name: String,
+    ///     email: Option<String>,
+    /// }
+    ///
+    /// let mut rw = results.start(&columns)?;
+    /// for user in &users {
+    ///     rw.write_serialize(user)?;
+    /// }
+    /// rw.finish()
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn write_serialize<T>(&mut self, row: &T) -> io::Result<()>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        if self.columns.is_empty() {
+            return self.end_row();
+        }
+        if self.col != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "current row already has columns written",
+            ));
+        }
+        crate::serialize::write_row(self, row)
+    }
+
+    /// The number of columns of this resultset.
+    #[cfg(feature = "serde")]
+    pub(crate) fn num_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// The `i`th column of this resultset.
+    #[cfg(feature = "serde")]
+    pub(crate) fn column(&self, i: usize) -> Option<&'a Column> {
+        self.columns.get(i)
+    }
+
+    /// Throw away the columns written so far for the current row.
+    pub(crate) fn discard_row(&mut self) {
+        self.result.as_mut().unwrap().writer.discard_packet();
+        self.data.clear();
+        self.col = 0;
+    }
+
+    /// Write all the rows of an Arrow record batch as a part of this resultset.
+    ///
+    /// The batch must have one column for each column of the resultset, usually because the
+    /// resultset was started with the [`arrow_columns`](fn.arrow_columns.html) of the batch's
+    /// schema.
+    #[cfg(feature = "arrow")]
+    pub fn write_batch(&mut self, batch: &arrow_array::RecordBatch) -> io::Result<()> {
+        if batch.num_columns() != self.columns.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "record batch has a different number of columns than specification",
+            ));
+        }
+        let schema = batch.schema();
+        let columns = schema
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .map(|(field, array)| BatchColumn::new(field, array.as_ref()))
+            .collect::<io::Result<Vec<_>>>()?;
+        for row in 0..batch.num_rows() {
+            for column in &columns {
+                self.write_col(column.cell(row)?)?;
+            }
+            self.end_row()?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a, W: Write + 'a> RowWriter<'a, W> {
@@ -354,16 +943,26 @@ impl<'a, W: Write + 'a> RowWriter<'a, W> {
         self.finished = true;
 
         if !self.columns.is_empty() && self.col != 0 {
-            self.end_row()?;
+            let result = self.result.as_mut().unwrap();
+            if result.state.cancellation.is_cancelled() {
+                // no point in completing a row of a cancelled statement
+                self.discard_row();
+            } else {
+                self.end_row()?;
+            }
+        }
+
+        if self.result.as_mut().unwrap().interrupted()? {
+            return Ok(());
         }
 
         if self.columns.is_empty() {
             // response to no column query is always an OK packet
             // we've kept track of the number of rows in col (hacky, I know)
-            self.result.as_mut().unwrap().last_end = Some(Finalizer::Ok {
+            self.result.as_mut().unwrap().last_end = Some(Finalizer::Ok(Completion {
                 rows: self.col as u64,
-                last_insert_id: 0,
-            });
+                ..Default::default()
+            }));
         } else {
             // we wrote out at least one row
             self.result.as_mut().unwrap().last_end = Some(Finalizer::EOF);
@@ -371,6 +970,14 @@ impl<'a, W: Write + 'a> RowWriter<'a, W> {
         Ok(())
     }
 
+    /// Attach a note, warning or error with the given code and message to the result of this
+    /// statement.
+    ///
+    /// See [`QueryResultWriter::add_warning`](struct.QueryResultWriter.html#method.add_warning).
+    pub fn add_warning(&mut self, level: WarningLevel, kind: ErrorKind, msg: &str) {
+        self.result.as_mut().unwrap().add_warning(level, kind, msg);
+    }
+
     /// Indicate to the client that no more rows are coming.
     pub fn finish(self) -> io::Result<()> {
         self.finish_one()?.no_more_results()
@@ -390,3 +997,47 @@ impl<'a, W: Write + 'a> Drop for RowWriter<'a, W> {
         self.finish_inner().unwrap();
     }
 }
+
+impl<'a> RowWriter<'a, Cursor<Vec<u8>>> {
+    /// Send the rows that have been written so far to the client, waiting for it to catch up if it
+    /// reads them more slowly than they are produced.
+    ///
+    /// Rows are otherwise only sent once the shim returns. This does nothing in a synchronous
+    /// [`MysqlShim`](trait.MysqlShim.html), whose rows go straight to the client, or while the
+    /// client is still sending a local file.
+    pub async fn flush_async(&mut self) -> io::Result<()> {
+        let result = self.result.as_mut().unwrap();
+        send_buffered(result.writer, result.state, result.client).await
+    }
+
+    /// Write a single row as a part of this resultset, and send the buffered rows to the client
+    /// once they add up to a few kilobytes.
+    ///
+    /// See [`write_row`](struct.RowWriter.html#method.write_row) and
+    /// [`flush_async`](struct.RowWriter.html#method.flush_async).
+    pub async fn write_row_async<I, E>(&mut self, row: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: ToMysqlValue,
+    {
+        self.write_row(row)?;
+        if self.result.as_ref().unwrap().writer.w.get_ref().len() >= STREAM_CHUNK {
+            self.flush_async().await?;
+        }
+        Ok(())
+    }
+
+    /// Write all the rows of an Arrow record batch as a part of this resultset, and send the
+    /// buffered rows to the client once they add up to a few kilobytes.
+    ///
+    /// See [`write_batch`](struct.RowWriter.html#method.write_batch) and
+    /// [`flush_async`](struct.RowWriter.html#method.flush_async).
+    #[cfg(feature = "arrow")]
+    pub async fn write_batch_async(&mut self, batch: &arrow_array::RecordBatch) -> io::Result<()> {
+        self.write_batch(batch)?;
+        if self.result.as_ref().unwrap().writer.w.get_ref().len() >= STREAM_CHUNK {
+            self.flush_async().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! Deciding what becomes of each statement of a query.
+//!
+//! Statements are routed the same way whether the intermediary is blocking or async: some are
+//! answered by the server itself, and the rest go to the shim through the hook that fits them.
+//! The intermediaries only differ in how they call the shim, so they share the decision, and the
+//! answers that do not involve the shim, through this module.
+
+use crate::catalog::{self, Catalog};
+use crate::packet::PacketWriter;
+use crate::sql::{
+    self, SelectedVariable, Show, ShowFilter, ShowWarnings, Transaction, XaStatement,
+};
+use crate::{
+    compat, registry, variables, warnings, xa, ConnectionState, QueryResultWriter, SetStatements,
+    SystemVariables, XaHandler,
+};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::mem;
+
+/// What the shim has asked the server to take care of.
+pub(crate) struct Handling<'a> {
+    /// See `MysqlShim::set_statements`.
+    pub(crate) set_statements: SetStatements,
+    /// See `MysqlShim::driver_compatibility`.
+    pub(crate) driver_compatibility: bool,
+    /// See `MysqlShim::catalog`.
+    pub(crate) catalog: Option<&'a dyn Catalog>,
+    /// Whether the shim has an `XaHandler`.
+    pub(crate) xa: bool,
+}
+
+/// Where a statement goes.
+pub(crate) enum Route<'q> {
+    /// The server answers it without involving the shim.
+    Builtin(Builtin<'q>),
+    /// `USE`, which goes to `on_init`.
+    Use(Cow<'q, str>),
+    /// A transaction control statement, which goes to the transaction hook for it, or a `SET`
+    /// statement that commits the open transaction by turning `autocommit` on, along with the
+    /// system variables to go back to if the transaction is not committed after all.
+    Transaction(&'q str, Transaction<'q>, Option<SystemVariables>),
+    /// Anything else, which goes to `on_query`, or to `on_statement` if it parses.
+    Query(&'q str),
+}
+
+/// A statement that the server answers itself.
+pub(crate) enum Builtin<'q> {
+    /// `SHOW WARNINGS` or `SHOW ERRORS`.
+    ShowWarnings(ShowWarnings),
+    /// `SELECT @@...`, and whether it selects a row.
+    SelectVariables(Vec<SelectedVariable<'q>>, bool),
+    /// A `SHOW` statement that is answered for driver compatibility.
+    Show(Show, ShowFilter),
+    /// A statement that is answered from the shim's catalog, and its answer.
+    Catalog(catalog::Answer),
+    /// An `XA` statement, for the shim's XA handler.
+    Xa(XaStatement),
+    /// A `SET` statement that has been applied.
+    Set,
+    /// A `SET` statement that assigns to a system variable that does not exist.
+    UnknownVariable(&'q str),
+    /// `KILL [QUERY] id`, and whether only the query is killed.
+    Kill(bool, u32),
+}
+
+/// Decide where the statement `q` goes.
+///
+/// Any statement other than `SHOW WARNINGS` clears the warnings of the one before it, and `SET`
+/// statements that the server deals with are applied to the session here.
+pub(crate) fn route<'q>(
+    q: &'q [u8],
+    handling: Handling<'_>,
+    state: &mut ConnectionState,
+) -> io::Result<Route<'q>> {
+    let builtin = |builtin| Ok(Route::Builtin(builtin));
+
+    match sql::parse_show_warnings(q) {
+        Some(show) => return builtin(Builtin::ShowWarnings(show)),
+        None => state.warnings.clear(),
+    }
+    if let Some((vars, row)) = sql::parse_select_variables(q) {
+        return builtin(Builtin::SelectVariables(vars, row));
+    }
+    if handling.driver_compatibility {
+        if let Some((show, filter)) = sql::parse_show(q) {
+            return builtin(Builtin::Show(show, filter));
+        }
+    }
+    if let Some(catalog) = handling.catalog {
+        if let Some(introspection) = sql::parse_introspection(q) {
+            let schema = state.schema.as_deref();
+            let answer = catalog::answer(introspection, catalog, schema, &state.variables);
+            return builtin(Builtin::Catalog(answer));
+        }
+    }
+    if handling.xa {
+        if let Some(statement) = sql::parse_xa(q) {
+            return builtin(Builtin::Xa(statement));
+        }
+    }
+    if handling.set_statements != SetStatements::Forward {
+        if let Some(assignments) = sql::parse_set(q) {
+            if let Some(name) = state.variables.unknown(&assignments) {
+                return builtin(Builtin::UnknownVariable(name));
+            }
+            let mut variables = state.variables.clone();
+            variables.apply(assignments);
+            if handling.set_statements == SetStatements::Apply {
+                // like in MySQL, turning autocommit on commits the open transaction
+                if state.in_transaction && !state.variables.autocommit() && variables.autocommit() {
+                    let q = text(q)?;
+                    let previous = mem::replace(&mut state.variables, variables);
+                    return Ok(Route::Transaction(q, Transaction::Commit, Some(previous)));
+                }
+                state.set_variables(variables);
+                return builtin(Builtin::Set);
+            }
+            state.set_variables(variables);
+        }
+    }
+    if let Some(schema) = sql::parse_use(q) {
+        return Ok(Route::Use(schema));
+    }
+    if let Some((query_only, id)) = sql::parse_kill(q) {
+        return builtin(Builtin::Kill(query_only, id));
+    }
+
+    Ok(match sql::parse_transaction(q) {
+        Some(transaction) => Route::Transaction(text(q)?, transaction, None),
+        None => Route::Query(text(q)?),
+    })
+}
+
+/// The text of a statement that goes to the shim.
+fn text(q: &[u8]) -> io::Result<&str> {
+    ::std::str::from_utf8(q).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Answer a statement that the server deals with itself.
+pub(crate) fn answer<W: Write>(
+    builtin: Builtin<'_>,
+    xa: Option<&mut dyn XaHandler>,
+    registration: &registry::Registration,
+    writer: &mut PacketWriter<W>,
+    state: &mut ConnectionState,
+) -> io::Result<()> {
+    match builtin {
+        Builtin::ShowWarnings(show) => {
+            let warnings = state.warnings.clone();
+            let w = QueryResultWriter::new(writer, state, false);
+            warnings::write_warnings(show, &warnings, w)
+        }
+        Builtin::SelectVariables(vars, row) => {
+            let selected = state.variables.select(&vars);
+            let w = QueryResultWriter::new(writer, state, false);
+            variables::write_selected(selected, row, w)
+        }
+        Builtin::Show(show, filter) => {
+            let (cols, rows) = compat::show(show, &filter, &state.variables);
+            let w = QueryResultWriter::new(writer, state, false);
+            compat::write_rows(&cols, rows, w)
+        }
+        Builtin::Catalog(answer) => {
+            let w = QueryResultWriter::new(writer, state, false);
+            catalog::write_answer(answer, w)
+        }
+        Builtin::Xa(statement) => {
+            let outcome = match xa {
+                Some(handler) => xa::execute(statement, handler, state),
+                None => unreachable!("XA statements are only routed to a shim's XA handler"),
+            };
+            let w = QueryResultWriter::new(writer, state, false);
+            xa::write_outcome(outcome, w)
+        }
+        Builtin::Set => QueryResultWriter::new(writer, state, false).completed(0, 0),
+        Builtin::UnknownVariable(name) => {
+            variables::write_unknown(name, QueryResultWriter::new(writer, state, false))
+        }
+        Builtin::Kill(query_only, id) => {
+            let w = QueryResultWriter::new(writer, state, false);
+            crate::write_kill_result(registration.kill(id, query_only), id, w)
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Resultsets whose columns are described by a Rust type.
+
+use crate::{Column, RowWriter};
+use std::io::{self, Write};
+
+/// A Rust type whose values can be sent as the rows of a resultset, one column per field.
+///
+/// Writing a resultset by hand means listing its columns and then writing the values of each row
+/// in the same order, which is easy to get wrong when fields are added or moved. With the
+/// `derive` feature, `#[derive(MysqlRow)]` generates both from the fields of a struct instead,
+/// taking the type and flags of each column from the field's
+/// [`ToMysqlValue`](trait.ToMysqlValue.html) implementation: `Option` fields are nullable, and
+/// unsigned integers are `UNSIGNED`. The column table can be set with `#[mysql(table = "...")]` on
+/// the struct, and a column can be named differently from its field with
+/// `#[mysql(rename = "...")]`.
+///
+/// The derived implementation of
+///
+/// ```ignore
+/// #[derive(MysqlRow)]
+/// #[mysql(table = "users")]
+/// struct User {
+///     id: u64,
+///     name: String,
+///     #[mysql(rename = "email_address")]
+///     email: Option<String>,
+/// }
+/// ```
+///
+/// is equivalent to
+///
+/// ```
+/// # use msql_srv::*;
+/// # use std::io;
+/// # struct User {
+/// #     id: u64,
+/// #     name: String,
+/// #     email: Option<String>,
+/// # }
+/// impl MysqlRow for User {
+///     fn columns() -> Vec<Column> {
+///         vec![
+///             Column {
+///                 table: "users".to_owned(),
+///                 column: "id".to_owned(),
+///                 coltype: <u64 as ToMysqlValue>::column_type(),
+///                 colflags: <u64 as ToMysqlValue>::column_flags(),
+///             },
+///             Column {
+///                 table: "users".to_owned(),
+///                 column: "name".to_owned(),
+///                 coltype: <String as ToMysqlValue>::column_type(),
+///                 colflags: <String as ToMysqlValue>::column_flags(),
+///             },
+///             Column {
+///                 table: "users".to_owned(),
+///                 column: "email_address".to_owned(),
+///                 coltype: <Option<String> as ToMysqlValue>::column_type(),
+///                 colflags: <Option<String> as ToMysqlValue>::column_flags(),
+///             },
+///         ]
+///     }
+///
+///     fn write_row<W: io::Write>(&self, writer: &mut RowWriter<'_, W>) -> io::Result<()> {
+///         writer.write_col(&self.id)?;
+///         writer.write_col(&self.name)?;
+///         writer.write_col(&self.email)?;
+///         writer.end_row()
+///     }
+/// }
+///
+/// let columns = User::columns();
+/// assert_eq!(columns[0].colflags, ColumnFlags::NOT_NULL_FLAG | ColumnFlags::UNSIGNED_FLAG);
+/// assert_eq!(columns[2].colflags, ColumnFlags::empty());
+/// ```
+///
+/// A shim then answers a query by starting a resultset with `User::columns()` and calling
+/// `write_row` for each user.
+pub trait MysqlRow {
+    /// The columns of a resultset of these rows, in the order of the fields.
+    fn columns() -> Vec<Column>;
+
+    /// Write this value as a single row of a resultset whose columns are
+    /// [`columns`](trait.MysqlRow.html#tymethod.columns).
+    fn write_row<W: Write>(&self, writer: &mut RowWriter<'_, W>) -> io::Result<()>;
+}
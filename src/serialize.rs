@@ -0,0 +1,422 @@
+//! Rows of a resultset from values that implement `serde::Serialize`.
+//!
+//! A row is a struct, whose fields are matched to the columns of the resultset by name, or a tuple
+//! or sequence, whose elements are matched to them by position. Each field is then written with
+//! the `ToMysqlValue` implementation of the scalar it serializes as.
+
+use crate::RowWriter;
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Write `row` as the next row of `writer`, and end that row.
+///
+/// If the row turns out not to match the resultset part of the way through, the fields that were
+/// already written are thrown away again, so that `writer` is left between rows.
+pub(crate) fn write_row<W, T>(writer: &mut RowWriter<'_, W>, row: &T) -> io::Result<()>
+where
+    W: Write,
+    T: Serialize + ?Sized,
+{
+    let serialized = row.serialize(RowSerializer {
+        writer: &mut *writer,
+        col: 0,
+    });
+    if let Err(Error(e)) = serialized {
+        writer.discard_row();
+        return Err(e);
+    }
+    writer.end_row()
+}
+
+/// The `io::Error` of a failed serialization.
+///
+/// `serde` needs an error type of our own, since it cannot construct an `io::Error` itself.
+#[derive(Debug)]
+struct Error(io::Error);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        invalid(msg.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error(e)
+    }
+}
+
+fn invalid<M: Into<String>>(msg: M) -> Error {
+    Error(io::Error::new(io::ErrorKind::InvalidData, msg.into()))
+}
+
+fn not_a_row() -> Error {
+    invalid("a row must serialize as a struct, a tuple or a sequence")
+}
+
+fn not_a_value() -> Error {
+    invalid("a column value must serialize as a scalar")
+}
+
+/// Serializes a row, writing each of its fields to the next column of the resultset.
+struct RowSerializer<'r, 'a, W: Write> {
+    writer: &'r mut RowWriter<'a, W>,
+    col: usize,
+}
+
+impl<'r, 'a, W: Write + 'a> RowSerializer<'r, 'a, W> {
+    fn expect_len(self, len: usize) -> Result<Self, Error> {
+        let columns = self.writer.num_columns();
+        if len != columns {
+            return Err(invalid(format!(
+                "row has {} fields, but the resultset has {} columns",
+                len, columns
+            )));
+        }
+        Ok(self)
+    }
+
+    fn field<T>(&mut self, name: Option<&str>, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let column = self
+            .writer
+            .column(self.col)
+            .ok_or_else(|| invalid("row has more fields than the resultset has columns"))?;
+        if let Some(name) = name {
+            if name != column.column {
+                return Err(invalid(format!(
+                    "field `{}` does not match column `{}`",
+                    name, column.column
+                )));
+            }
+        }
+        value.serialize(ValueSerializer {
+            writer: &mut *self.writer,
+        })?;
+        self.col += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        if self.col != self.writer.num_columns() {
+            return Err(invalid(
+                "row has fewer fields than the resultset has columns",
+            ));
+        }
+        Ok(())
+    }
+}
+
+macro_rules! not_a_row {
+    ($($method:ident($($arg:ty),*);)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Self::Ok, Self::Error> {
+                Err(not_a_row())
+            }
+        )*
+    };
+}
+
+impl<'r, 'a, W: Write + 'a> ser::Serializer for RowSerializer<'r, 'a, W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    not_a_row! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<(), Error> {
+        Err(not_a_row())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Error> {
+        Err(not_a_row())
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, Error> {
+        match len {
+            Some(len) => self.expect_len(len),
+            None => Ok(self),
+        }
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self, Error> {
+        self.expect_len(len)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<Self, Error> {
+        self.expect_len(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(not_a_row())
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(not_a_row())
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<Self, Error> {
+        self.expect_len(len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(not_a_row())
+    }
+}
+
+impl<'r, 'a, W: Write + 'a> ser::SerializeSeq for RowSerializer<'r, 'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(None, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        RowSerializer::end(self)
+    }
+}
+
+impl<'r, 'a, W: Write + 'a> ser::SerializeTuple for RowSerializer<'r, 'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(None, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        RowSerializer::end(self)
+    }
+}
+
+impl<'r, 'a, W: Write + 'a> ser::SerializeTupleStruct for RowSerializer<'r, 'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(None, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        RowSerializer::end(self)
+    }
+}
+
+impl<'r, 'a, W: Write + 'a> ser::SerializeStruct for RowSerializer<'r, 'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(Some(key), value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        RowSerializer::end(self)
+    }
+}
+
+/// Serializes the value of a single column, and writes it to the resultset.
+struct ValueSerializer<'r, 'a, W: Write> {
+    writer: &'r mut RowWriter<'a, W>,
+}
+
+macro_rules! write_value {
+    ($($method:ident($t:ty);)*) => {
+        $(
+            fn $method(self, v: $t) -> Result<(), Error> {
+                Ok(self.writer.write_col(v)?)
+            }
+        )*
+    };
+}
+
+impl<'r, 'a, W: Write + 'a> ser::Serializer for ValueSerializer<'r, 'a, W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    write_value! {
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        // MySQL has no boolean type of its own; BOOL is a synonym for TINYINT(1)
+        self.serialize_u8(v as u8)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Ok(self.writer.write_col(None::<u8>)?)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.serialize_none()
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Error> {
+        self.serialize_none()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        // like a value of an ENUM column
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Error> {
+        Err(not_a_value())
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(not_a_value())
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(not_a_value())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(not_a_value())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(not_a_value())
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(not_a_value())
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Error> {
+        Err(not_a_value())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(not_a_value())
+    }
+}
@@ -0,0 +1,1865 @@
+//! Lightweight lexical helpers for the SQL text that clients send.
+//!
+//! None of this is a real SQL parser; it only knows enough about quoting and comments to avoid
+//! being fooled by a `;` inside a string literal or a comment.
+
+use crate::variables::VariableScope;
+use crate::{TransactionOptions, Xid};
+use std::borrow::Cow;
+
+/// Returns the index just past the quoted section that starts at `i`.
+///
+/// `'` and `"` strings may contain backslash escapes, and all three quote styles may contain the
+/// quote character itself by doubling it. An unterminated quote extends to the end of the input.
+pub(crate) fn skip_quoted(q: &[u8], i: usize) -> usize {
+    let quote = q[i];
+    let mut i = i + 1;
+    while i < q.len() {
+        if q[i] == b'\\' && quote != b'`' {
+            i += 2;
+        } else if q[i] == quote {
+            if q.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    q.len()
+}
+
+/// If a comment starts at `i`, returns the index just past it.
+///
+/// Recognizes `# ...` and `-- ...` line comments, as well as `/* ... */` block comments. Note
+/// that MySQL requires whitespace (or the end of the query) after `--` for it to start a comment.
+pub(crate) fn skip_comment(q: &[u8], i: usize) -> Option<usize> {
+    let line = match (q[i], q.get(i + 1), q.get(i + 2)) {
+        (b'#', _, _) => true,
+        (b'-', Some(b'-'), None) => true,
+        (b'-', Some(b'-'), Some(c)) if c.is_ascii_whitespace() => true,
+        (b'/', Some(b'*'), _) => false,
+        _ => return None,
+    };
+
+    if line {
+        Some(
+            q[i..]
+                .iter()
+                .position(|&c| c == b'\n')
+                .map(|p| i + p + 1)
+                .unwrap_or(q.len()),
+        )
+    } else {
+        Some(
+            q[i + 2..]
+                .windows(2)
+                .position(|w| w == b"*/")
+                .map(|p| i + 2 + p + 2)
+                .unwrap_or(q.len()),
+        )
+    }
+}
+
+/// Returns the index of the first byte at or after `i` that is neither whitespace nor part of a
+/// comment.
+pub(crate) fn skip_whitespace_and_comments(q: &[u8], mut i: usize) -> usize {
+    while i < q.len() {
+        if q[i].is_ascii_whitespace() {
+            i += 1;
+        } else if let Some(end) = skip_comment(q, i) {
+            i = end;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// Splits a multi-statement query into its individual statements.
+///
+/// Statements are separated by `;`, except where the `;` appears inside a quoted string, a quoted
+/// identifier, or a comment. Surrounding whitespace is trimmed from each statement, and
+/// statements that consist only of whitespace and comments are left out.
+pub(crate) fn split_statements(q: &[u8]) -> Vec<&[u8]> {
+    fn push<'a>(stmts: &mut Vec<&'a [u8]>, stmt: &'a [u8]) {
+        if skip_whitespace_and_comments(stmt, 0) != stmt.len() {
+            stmts.push(trim(stmt));
+        }
+    }
+
+    let mut stmts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < q.len() {
+        match q[i] {
+            b'\'' | b'"' | b'`' => i = skip_quoted(q, i),
+            b';' => {
+                push(&mut stmts, &q[start..i]);
+                i += 1;
+                start = i;
+            }
+            _ => i = skip_comment(q, i).unwrap_or(i + 1),
+        }
+    }
+    push(&mut stmts, &q[start..]);
+    stmts
+}
+
+/// Splits a query around its `?` parameter placeholders.
+///
+/// A `?` inside a quoted string, a quoted identifier, or a comment is not a placeholder. The
+/// returned fragments are one more than the number of placeholders.
+pub(crate) fn split_placeholders(q: &str) -> Vec<&str> {
+    let b = q.as_bytes();
+    let mut fragments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'\'' | b'"' | b'`' => i = skip_quoted(b, i),
+            b'?' => {
+                fragments.push(&q[start..i]);
+                i += 1;
+                start = i;
+            }
+            _ => i = skip_comment(b, i).unwrap_or(i + 1),
+        }
+    }
+    fragments.push(&q[start..]);
+    fragments
+}
+
+/// If `q` is a `KILL [CONNECTION | QUERY] <id>` statement, returns whether only the query should
+/// be killed, along with the id of the target connection.
+pub(crate) fn parse_kill(q: &[u8]) -> Option<(bool, u32)> {
+    fn word(q: &[u8], i: usize) -> (&[u8], usize) {
+        let i = skip_whitespace_and_comments(q, i);
+        let end = q[i..]
+            .iter()
+            .position(|c| !c.is_ascii_alphanumeric())
+            .map(|p| i + p)
+            .unwrap_or(q.len());
+        (&q[i..end], end)
+    }
+
+    let (kill, i) = word(q, 0);
+    if !kill.eq_ignore_ascii_case(b"KILL") {
+        return None;
+    }
+    let (mut id, mut i) = word(q, i);
+    let mut query_only = false;
+    if id.eq_ignore_ascii_case(b"QUERY") || id.eq_ignore_ascii_case(b"CONNECTION") {
+        query_only = id.eq_ignore_ascii_case(b"QUERY");
+        let next = word(q, i);
+        id = next.0;
+        i = next.1;
+    }
+    let id = ::std::str::from_utf8(id).ok()?.parse().ok()?;
+
+    let i = skip_whitespace_and_comments(q, i);
+    match &q[i..] {
+        b"" | b";" => Some((query_only, id)),
+        _ => None,
+    }
+}
+
+/// If `q` is a `USE <schema>` statement, returns the name of the schema, with any quoting removed.
+///
+/// The schema name may be quoted with backticks, in which case a doubled backtick stands for a
+/// single one.
+pub(crate) fn parse_use(q: &[u8]) -> Option<Cow<'_, str>> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let (schema, end) = name(q, keyword(q, 0, "USE")?)?;
+    if at_end(b, skip_whitespace_and_comments(b, end)) {
+        Some(schema)
+    } else {
+        None
+    }
+}
+
+/// A transaction control statement.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Transaction<'a> {
+    /// `BEGIN [WORK]`, or `START TRANSACTION` with any of its characteristics.
+    Begin(TransactionOptions),
+    /// `COMMIT [WORK]`.
+    Commit,
+    /// `ROLLBACK [WORK]`.
+    Rollback,
+    /// `SAVEPOINT name`.
+    Savepoint(Cow<'a, str>),
+    /// `RELEASE SAVEPOINT name`.
+    ReleaseSavepoint(Cow<'a, str>),
+    /// `ROLLBACK [WORK] TO [SAVEPOINT] name`.
+    RollbackToSavepoint(Cow<'a, str>),
+}
+
+impl Transaction<'_> {
+    /// Whether a transaction is open once this statement has succeeded, given whether one was
+    /// open before it.
+    pub(crate) fn leaves_open(&self, open: bool) -> bool {
+        match self {
+            Transaction::Begin(_) => true,
+            Transaction::Commit | Transaction::Rollback => false,
+            _ => open,
+        }
+    }
+}
+
+/// If `q` is a transaction control statement, returns what it asks for.
+///
+/// `COMMIT` and `ROLLBACK` with `AND CHAIN` or `RELEASE`, and `SET TRANSACTION`, are not
+/// recognized.
+pub(crate) fn parse_transaction(q: &[u8]) -> Option<Transaction<'_>> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let work = |i| keyword(q, i, "WORK").unwrap_or(i);
+
+    let (transaction, end) = if let Some(i) = keyword(q, 0, "BEGIN") {
+        (Transaction::Begin(TransactionOptions::default()), work(i))
+    } else if let Some(i) = keyword(q, 0, "START") {
+        let mut i = keyword(q, i, "TRANSACTION")?;
+        let mut options = TransactionOptions::default();
+        // where the next characteristic may start
+        let mut next = Some(i);
+        while let Some(start) = next {
+            if let Some(end) = keyword(q, start, "WITH") {
+                let end = keyword(q, end, "CONSISTENT")?;
+                i = keyword(q, end, "SNAPSHOT")?;
+                options.consistent_snapshot = true;
+            } else if let Some(end) = keyword(q, start, "READ") {
+                options.read_only = keyword(q, end, "ONLY").is_some();
+                i = match keyword(q, end, "ONLY") {
+                    Some(end) => end,
+                    None => keyword(q, end, "WRITE")?,
+                };
+            } else if start == i {
+                break;
+            } else {
+                return None;
+            }
+            let comma = skip_whitespace_and_comments(b, i);
+            next = if b.get(comma) == Some(&b',') {
+                Some(comma + 1)
+            } else {
+                None
+            };
+        }
+        (Transaction::Begin(options), i)
+    } else if let Some(i) = keyword(q, 0, "COMMIT") {
+        (Transaction::Commit, work(i))
+    } else if let Some(i) = keyword(q, 0, "ROLLBACK") {
+        let i = work(i);
+        match keyword(q, i, "TO") {
+            Some(i) => {
+                let i = keyword(q, i, "SAVEPOINT").unwrap_or(i);
+                let (name, end) = name(q, i)?;
+                (Transaction::RollbackToSavepoint(name), end)
+            }
+            None => (Transaction::Rollback, i),
+        }
+    } else if let Some(i) = keyword(q, 0, "SAVEPOINT") {
+        let (name, end) = name(q, i)?;
+        (Transaction::Savepoint(name), end)
+    } else if let Some(i) = keyword(q, 0, "RELEASE") {
+        let (name, end) = name(q, keyword(q, i, "SAVEPOINT")?)?;
+        (Transaction::ReleaseSavepoint(name), end)
+    } else {
+        return None;
+    };
+
+    if at_end(b, skip_whitespace_and_comments(b, end)) {
+        Some(transaction)
+    } else {
+        None
+    }
+}
+
+/// An `XA` statement.
+#[derive(Debug, PartialEq)]
+pub(crate) enum XaStatement {
+    /// `XA {START | BEGIN} xid`.
+    Start(Xid),
+    /// `XA END xid`.
+    End(Xid),
+    /// `XA PREPARE xid`.
+    Prepare(Xid),
+    /// `XA COMMIT xid [ONE PHASE]`, and whether `ONE PHASE` was given.
+    Commit(Xid, bool),
+    /// `XA ROLLBACK xid`.
+    Rollback(Xid),
+    /// `XA RECOVER [CONVERT XID]`, and whether `CONVERT XID` was given.
+    Recover(bool),
+}
+
+/// If `q` is an `XA` statement, returns what it asks for.
+///
+/// `XA START` with `JOIN` or `RESUME`, and `XA END` with `SUSPEND`, are not recognized.
+pub(crate) fn parse_xa(q: &[u8]) -> Option<XaStatement> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let i = keyword(q, 0, "XA")?;
+
+    let (statement, end) = if let Some(i) = keyword(q, i, "START").or(keyword(q, i, "BEGIN")) {
+        let (xid, end) = xid(q, i)?;
+        (XaStatement::Start(xid), end)
+    } else if let Some(i) = keyword(q, i, "END") {
+        let (xid, end) = xid(q, i)?;
+        (XaStatement::End(xid), end)
+    } else if let Some(i) = keyword(q, i, "PREPARE") {
+        let (xid, end) = xid(q, i)?;
+        (XaStatement::Prepare(xid), end)
+    } else if let Some(i) = keyword(q, i, "COMMIT") {
+        let (xid, end) = xid(q, i)?;
+        match keyword(q, end, "ONE") {
+            Some(end) => (XaStatement::Commit(xid, true), keyword(q, end, "PHASE")?),
+            None => (XaStatement::Commit(xid, false), end),
+        }
+    } else if let Some(i) = keyword(q, i, "ROLLBACK") {
+        let (xid, end) = xid(q, i)?;
+        (XaStatement::Rollback(xid), end)
+    } else if let Some(i) = keyword(q, i, "RECOVER") {
+        match keyword(q, i, "CONVERT") {
+            Some(end) => (XaStatement::Recover(true), keyword(q, end, "XID")?),
+            None => (XaStatement::Recover(false), i),
+        }
+    } else {
+        return None;
+    };
+
+    if at_end(b, skip_whitespace_and_comments(b, end)) {
+        Some(statement)
+    } else {
+        None
+    }
+}
+
+/// A system variable in a `SELECT @@...` list.
+#[derive(Debug, PartialEq)]
+pub(crate) struct SelectedVariable<'a> {
+    /// The scope given with `@@global.` or `@@session.`, if any.
+    pub(crate) scope: Option<VariableScope>,
+    /// The name of the variable.
+    pub(crate) name: &'a str,
+    /// The name of the column the value is returned in: either the alias, or the expression
+    /// itself, as written by the client.
+    pub(crate) column: &'a str,
+}
+
+/// If `q` is a `SELECT` statement that selects nothing but system variables, like
+/// `SELECT @@a, @@session.b AS x, @@global.c`, returns the variables in the order they were
+/// selected, along with whether the row of values should be sent at all, which only a `LIMIT`
+/// clause can prevent.
+pub(crate) fn parse_select_variables(q: &[u8]) -> Option<(Vec<SelectedVariable<'_>>, bool)> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let mut i = skip_whitespace_and_comments(b, 0);
+    let end = identifier(b, i);
+    if !q[i..end].eq_ignore_ascii_case("SELECT") {
+        return None;
+    }
+    i = end;
+
+    let mut vars = Vec::new();
+    loop {
+        let start = skip_whitespace_and_comments(b, i);
+        if !q[start..].starts_with("@@") {
+            return None;
+        }
+        let mut end = identifier(b, start + 2);
+        let mut name = &q[start + 2..end];
+        let mut scope = None;
+        if b.get(end) == Some(&b'.') {
+            scope = Some(scope_modifier(name)?);
+            let from = end + 1;
+            end = identifier(b, from);
+            name = &q[from..end];
+        }
+        if name.is_empty() {
+            return None;
+        }
+        let mut column = &q[start..end];
+
+        // an optional alias, with or without AS
+        i = skip_whitespace_and_comments(b, end);
+        let word = identifier(b, i);
+        let explicit = q[i..word].eq_ignore_ascii_case("AS");
+        if explicit {
+            i = skip_whitespace_and_comments(b, word);
+        }
+        match b.get(i) {
+            Some(b'`') | Some(b'\'') | Some(b'"') => {
+                let end = skip_quoted(b, i);
+                if end - i < 2 || b[end - 1] != b[i] {
+                    return None;
+                }
+                column = &q[i + 1..end - 1];
+                i = end;
+            }
+            Some(_) if identifier(b, i) != i && (explicit || keyword(q, i, "LIMIT").is_none()) => {
+                let end = identifier(b, i);
+                column = &q[i..end];
+                i = end;
+            }
+            _ if explicit => return None,
+            _ => {}
+        }
+
+        vars.push(SelectedVariable {
+            scope,
+            name,
+            column,
+        });
+
+        i = skip_whitespace_and_comments(b, i);
+        if b.get(i) != Some(&b',') {
+            break;
+        }
+        i += 1;
+    }
+
+    let mut row = true;
+    if let Some(end) = keyword(q, i, "LIMIT") {
+        let (offset, count, end) = limit(q, end)?;
+        row = count > 0 && offset == 0;
+        i = end;
+    }
+
+    if at_end(b, skip_whitespace_and_comments(b, i)) {
+        Some((vars, row))
+    } else {
+        None
+    }
+}
+
+/// Parses the `[offset,] count` or `count OFFSET offset` that follows `LIMIT` at `i`, and returns
+/// the offset and the count along with the index just past them.
+fn limit(q: &str, i: usize) -> Option<(u64, u64, usize)> {
+    let b = q.as_bytes();
+    let number = |i| match set_value(q, skip_whitespace_and_comments(b, i))? {
+        (SetValue::Number(n), end) => Some((n.parse::<u64>().ok()?, end)),
+        _ => None,
+    };
+    let (count, end) = number(i)?;
+    let i = skip_whitespace_and_comments(b, end);
+    if b.get(i) == Some(&b',') {
+        let (n, end) = number(i + 1)?;
+        Some((count, n, end))
+    } else if let Some(end) = keyword(q, i, "OFFSET") {
+        let (offset, end) = number(end)?;
+        Some((offset, count, end))
+    } else {
+        Some((0, count, end))
+    }
+}
+
+/// What a `SHOW WARNINGS` or `SHOW ERRORS` statement asks for.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ShowWarnings {
+    /// Whether only errors are asked for, rather than notes, warnings and errors.
+    pub(crate) errors: bool,
+    /// Whether only their number is asked for, as in `SHOW COUNT(*) WARNINGS`.
+    pub(crate) count: bool,
+    /// How many to skip, and how many to show at most.
+    pub(crate) limit: (u64, u64),
+}
+
+/// If `q` is a `SHOW WARNINGS [LIMIT ...]`, `SHOW ERRORS [LIMIT ...]`, `SHOW COUNT(*) WARNINGS`
+/// or `SHOW COUNT(*) ERRORS` statement, returns what it asks for.
+pub(crate) fn parse_show_warnings(q: &[u8]) -> Option<ShowWarnings> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let mut i = keyword(q, 0, "SHOW")?;
+
+    let count = keyword(q, i, "COUNT").is_some();
+    if count {
+        i = keyword(q, i, "COUNT")?;
+        for &c in b"(*)" {
+            i = skip_whitespace_and_comments(b, i);
+            if b.get(i) != Some(&c) {
+                return None;
+            }
+            i += 1;
+        }
+    }
+    let errors = keyword(q, i, "ERRORS").is_some();
+    i = match keyword(q, i, "WARNINGS") {
+        Some(end) => end,
+        None => keyword(q, i, "ERRORS")?,
+    };
+
+    let mut limit = (0, u64::MAX);
+    if !count {
+        if let Some(end) = keyword(q, i, "LIMIT") {
+            let (offset, n, end) = self::limit(q, end)?;
+            limit = (offset, n);
+            i = end;
+        }
+    }
+
+    if at_end(b, skip_whitespace_and_comments(b, i)) {
+        Some(ShowWarnings {
+            errors,
+            count,
+            limit,
+        })
+    } else {
+        None
+    }
+}
+
+/// A single assignment in a `SET` statement.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Assignment<'a> {
+    /// `[GLOBAL | SESSION] name = value`, or `@@[global. | session.]name = value`.
+    Variable {
+        scope: VariableScope,
+        name: &'a str,
+        value: SetValue<'a>,
+    },
+    /// `NAMES charset [COLLATE collation]`, where `None` stands for `NAMES DEFAULT`.
+    Names(Option<(&'a str, Option<&'a str>)>),
+    /// `CHARACTER SET charset`, where `None` stands for `CHARACTER SET DEFAULT`.
+    CharacterSet(Option<&'a str>),
+}
+
+/// The literal that is assigned to a system variable.
+#[derive(Debug, PartialEq)]
+pub(crate) enum SetValue<'a> {
+    /// `DEFAULT`.
+    Default,
+    /// `NULL`.
+    Null,
+    /// A numeric literal, as written.
+    Number(&'a str),
+    /// A quoted string, with its escapes resolved.
+    String(String),
+    /// A bare word, like `ON` or `utf8mb4`.
+    Word(&'a str),
+}
+
+/// If `q` is a `SET` statement that only assigns literals to system variables, returns its
+/// assignments in order.
+///
+/// Statements that assign user variables, or values that need to be evaluated, are left alone,
+/// as are `SET TRANSACTION`, `SET PASSWORD` and friends.
+pub(crate) fn parse_set(q: &[u8]) -> Option<Vec<Assignment<'_>>> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let mut i = skip_whitespace_and_comments(b, 0);
+    let end = identifier(b, i);
+    if !q[i..end].eq_ignore_ascii_case("SET") {
+        return None;
+    }
+    i = end;
+
+    // like in MySQL, a scope keyword applies to all the assignments that follow it
+    let mut scope = VariableScope::Session;
+    let mut assignments = Vec::new();
+    loop {
+        i = skip_whitespace_and_comments(b, i);
+        let mut end = identifier(b, i);
+        let mut word = &q[i..end];
+        if let Some(modifier) = scope_modifier(word) {
+            scope = modifier;
+            i = skip_whitespace_and_comments(b, end);
+            end = identifier(b, i);
+            word = &q[i..end];
+        }
+
+        if word.eq_ignore_ascii_case("NAMES") {
+            let (charset, end) = charset_name(q, skip_whitespace_and_comments(b, end))?;
+            i = end;
+            let charset = match charset {
+                Some(charset) => {
+                    let from = skip_whitespace_and_comments(b, i);
+                    let end = identifier(b, from);
+                    if q[from..end].eq_ignore_ascii_case("COLLATE") {
+                        let (collation, end) =
+                            charset_name(q, skip_whitespace_and_comments(b, end))?;
+                        i = end;
+                        Some((charset, Some(collation?)))
+                    } else {
+                        Some((charset, None))
+                    }
+                }
+                None => None,
+            };
+            assignments.push(Assignment::Names(charset));
+        } else if word.eq_ignore_ascii_case("CHARSET") || word.eq_ignore_ascii_case("CHARACTER") {
+            if word.eq_ignore_ascii_case("CHARACTER") {
+                let from = skip_whitespace_and_comments(b, end);
+                end = identifier(b, from);
+                if !q[from..end].eq_ignore_ascii_case("SET") {
+                    return None;
+                }
+            }
+            let (charset, end) = charset_name(q, skip_whitespace_and_comments(b, end))?;
+            i = end;
+            assignments.push(Assignment::CharacterSet(charset));
+        } else {
+            let mut scope = scope;
+            if word.is_empty() && q[i..].starts_with("@@") {
+                end = identifier(b, i + 2);
+                word = &q[i + 2..end];
+                if b.get(end) == Some(&b'.') {
+                    scope = scope_modifier(word)?;
+                    let from = end + 1;
+                    end = identifier(b, from);
+                    word = &q[from..end];
+                } else {
+                    scope = VariableScope::Session;
+                }
+            }
+            if word.is_empty()
+                || word.eq_ignore_ascii_case("TRANSACTION")
+                || word.eq_ignore_ascii_case("PASSWORD")
+            {
+                return None;
+            }
+
+            i = skip_whitespace_and_comments(b, end);
+            if q[i..].starts_with(":=") {
+                i += 2;
+            } else if q[i..].starts_with('=') {
+                i += 1;
+            } else {
+                return None;
+            }
+            let (value, end) = set_value(q, skip_whitespace_and_comments(b, i))?;
+            i = end;
+            assignments.push(Assignment::Variable {
+                scope,
+                name: word,
+                value,
+            });
+        }
+
+        i = skip_whitespace_and_comments(b, i);
+        match b.get(i) {
+            Some(b',') => i += 1,
+            _ if at_end(b, i) => return Some(assignments),
+            _ => return None,
+        }
+    }
+}
+
+/// What a `SHOW` statement asks for.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Show {
+    /// `SHOW [GLOBAL | SESSION] VARIABLES`.
+    Variables(VariableScope),
+    /// `SHOW COLLATION`.
+    Collation,
+    /// `SHOW CHARACTER SET`.
+    CharacterSet,
+}
+
+/// Which rows of a `SHOW` statement the client is interested in.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ShowFilter {
+    /// All of them.
+    All,
+    /// Those whose name matches a `LIKE` pattern.
+    Like(String),
+    /// Those with one of the given names, as in `WHERE Variable_name = 'a' OR ...`.
+    Names(Vec<String>),
+}
+
+/// If `q` is a `SHOW VARIABLES`, `SHOW COLLATION` or `SHOW CHARACTER SET` statement, with an
+/// optional `LIKE` pattern or a `WHERE` clause that only compares the name column to strings,
+/// returns what it asks for.
+pub(crate) fn parse_show(q: &[u8]) -> Option<(Show, ShowFilter)> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let mut i = keyword(q, 0, "SHOW")?;
+
+    let start = skip_whitespace_and_comments(b, i);
+    let end = identifier(b, start);
+    let scope = scope_modifier(&q[start..end]);
+    if scope.is_some() {
+        i = end;
+    }
+    let (show, column) = if let Some(end) = keyword(q, i, "VARIABLES") {
+        i = end;
+        (
+            Show::Variables(scope.unwrap_or(VariableScope::Session)),
+            "Variable_name",
+        )
+    } else if scope.is_some() {
+        return None;
+    } else if let Some(end) = keyword(q, i, "COLLATION") {
+        i = end;
+        (Show::Collation, "Collation")
+    } else if let Some(end) = keyword(q, i, "CHARSET") {
+        i = end;
+        (Show::CharacterSet, "Charset")
+    } else {
+        i = keyword(q, keyword(q, i, "CHARACTER")?, "SET")?;
+        (Show::CharacterSet, "Charset")
+    };
+
+    let (filter, i) = show_filter(q, i, |c| c.eq_ignore_ascii_case(column))?;
+    if at_end(b, skip_whitespace_and_comments(b, i)) {
+        Some((show, filter))
+    } else {
+        None
+    }
+}
+
+/// Parses the optional `LIKE` or `WHERE` clause of a `SHOW` statement at `i`, and returns the
+/// filter it stands for along with the index just past it.
+///
+/// The `WHERE` clause may only compare the name column, which `is_name` recognizes, to strings.
+fn show_filter(q: &str, i: usize, is_name: impl Fn(&str) -> bool) -> Option<(ShowFilter, usize)> {
+    let b = q.as_bytes();
+    if let Some(end) = keyword(q, i, "LIKE") {
+        let (pattern, end) = string(q, end)?;
+        Some((ShowFilter::Like(pattern), end))
+    } else if let Some(mut i) = keyword(q, i, "WHERE") {
+        let mut names = Vec::new();
+        loop {
+            let start = skip_whitespace_and_comments(b, i);
+            let end = identifier(b, start);
+            if !is_name(&q[start..end]) {
+                return None;
+            }
+            i = skip_whitespace_and_comments(b, end);
+            if b.get(i) != Some(&b'=') {
+                return None;
+            }
+            let (name, end) = string(q, i + 1)?;
+            names.push(name);
+            i = end;
+            match keyword(q, i, "OR") {
+                Some(end) => i = end,
+                None => break,
+            }
+        }
+        Some((ShowFilter::Names(names), i))
+    } else {
+        Some((ShowFilter::All, i))
+    }
+}
+
+/// A table name, optionally qualified with the schema that holds the table.
+#[derive(Debug, PartialEq)]
+pub(crate) struct TableName<'a> {
+    pub(crate) schema: Option<Cow<'a, str>>,
+    pub(crate) table: Cow<'a, str>,
+}
+
+/// A statement that looks into the schemas, tables and columns of the database.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Introspection<'a> {
+    /// `SHOW {DATABASES | SCHEMAS}`.
+    Databases(ShowFilter),
+    /// `SHOW [FULL] TABLES [{FROM | IN} schema]`.
+    Tables {
+        full: bool,
+        schema: Option<Cow<'a, str>>,
+        filter: ShowFilter,
+    },
+    /// `SHOW [FULL] {COLUMNS | FIELDS} {FROM | IN} table [{FROM | IN} schema]`, or
+    /// `{DESCRIBE | DESC} table [column]`.
+    Columns {
+        full: bool,
+        table: TableName<'a>,
+        filter: ShowFilter,
+    },
+    /// `SHOW CREATE TABLE table`.
+    CreateTable(TableName<'a>),
+    /// `SELECT ... FROM information_schema.table ...`.
+    InformationSchema(InformationSchemaQuery<'a>),
+}
+
+/// A `SELECT` from one of the tables in `information_schema`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct InformationSchemaQuery<'a> {
+    /// The table selected from.
+    pub(crate) table: Cow<'a, str>,
+    /// The selected columns, each with the name of the column it is returned in, or `None` for
+    /// `*`.
+    pub(crate) columns: Option<Vec<(Cow<'a, str>, Cow<'a, str>)>>,
+    /// The `WHERE` clause, as filters on the values of columns that must all be met.
+    pub(crate) conditions: Vec<(Cow<'a, str>, ShowFilter)>,
+    /// The `ORDER BY` clause, as columns along with whether they are sorted in descending order.
+    pub(crate) order: Vec<(Cow<'a, str>, bool)>,
+}
+
+/// If `q` is a statement that looks into the schemas, tables or columns of the database, returns
+/// what it asks for.
+///
+/// Apart from the `SHOW` statements for schemas, tables and columns, this recognizes a simple
+/// `SELECT` from `information_schema.schemata`, `tables` or `columns`: one that selects columns
+/// (possibly with aliases) or `*`, and whose `WHERE` clause, if any, only combines comparisons of
+/// columns to strings with `=`, `LIKE` or `IN` using `AND`. It may be followed by `ORDER BY`.
+pub(crate) fn parse_introspection(q: &[u8]) -> Option<Introspection<'_>> {
+    let q = ::std::str::from_utf8(q).ok()?;
+    let b = q.as_bytes();
+    let (introspection, i) = if let Some(i) = keyword(q, 0, "SHOW") {
+        show_introspection(q, i)?
+    } else if let Some(i) = keyword(q, 0, "DESCRIBE").or_else(|| keyword(q, 0, "DESC")) {
+        describe(q, i)?
+    } else {
+        select_information_schema(q, keyword(q, 0, "SELECT")?)?
+    };
+
+    if at_end(b, skip_whitespace_and_comments(b, i)) {
+        Some(introspection)
+    } else {
+        None
+    }
+}
+
+fn show_introspection(q: &str, i: usize) -> Option<(Introspection<'_>, usize)> {
+    if let Some(i) = keyword(q, i, "DATABASES").or_else(|| keyword(q, i, "SCHEMAS")) {
+        let (filter, i) = show_filter(q, i, |c| c.eq_ignore_ascii_case("Database"))?;
+        return Some((Introspection::Databases(filter), i));
+    }
+    if let Some(i) = keyword(q, i, "CREATE") {
+        let (table, i) = table_name(q, keyword(q, i, "TABLE")?)?;
+        return Some((Introspection::CreateTable(table), i));
+    }
+
+    let (full, i) = match keyword(q, i, "FULL") {
+        Some(end) => (true, end),
+        None => (false, i),
+    };
+    let from = |i| keyword(q, i, "FROM").or_else(|| keyword(q, i, "IN"));
+    if let Some(mut i) = keyword(q, i, "TABLES") {
+        let mut schema = None;
+        if let Some(end) = from(i) {
+            let (name, end) = name(q, end)?;
+            schema = Some(name);
+            i = end;
+        }
+        let (filter, i) = show_filter(q, i, |c| {
+            c.len() > 10 && c[..10].eq_ignore_ascii_case("Tables_in_")
+        })?;
+        return Some((
+            Introspection::Tables {
+                full,
+                schema,
+                filter,
+            },
+            i,
+        ));
+    }
+
+    let i = keyword(q, i, "COLUMNS").or_else(|| keyword(q, i, "FIELDS"))?;
+    let (mut table, mut i) = table_name(q, from(i)?)?;
+    if let Some(end) = from(i) {
+        let (name, end) = name(q, end)?;
+        table.schema = Some(name);
+        i = end;
+    }
+    let (filter, i) = show_filter(q, i, |c| c.eq_ignore_ascii_case("Field"))?;
+    Some((
+        Introspection::Columns {
+            full,
+            table,
+            filter,
+        },
+        i,
+    ))
+}
+
+fn describe(q: &str, i: usize) -> Option<(Introspection<'_>, usize)> {
+    // these make it an EXPLAIN instead
+    const EXPLAINED: &[&str] = &[
+        "ANALYZE",
+        "DELETE",
+        "EXTENDED",
+        "FOR",
+        "FORMAT",
+        "INSERT",
+        "PARTITIONS",
+        "REPLACE",
+        "SELECT",
+        "TABLE",
+        "UPDATE",
+        "WITH",
+    ];
+    if EXPLAINED.iter().any(|kw| keyword(q, i, kw).is_some()) {
+        return None;
+    }
+
+    let (table, i) = table_name(q, i)?;
+    let pattern = string(q, i).or_else(|| name(q, i).map(|(name, end)| (name.into_owned(), end)));
+    let (filter, i) = match pattern {
+        Some((pattern, end)) => (ShowFilter::Like(pattern), end),
+        None => (ShowFilter::All, i),
+    };
+    Some((
+        Introspection::Columns {
+            full: false,
+            table,
+            filter,
+        },
+        i,
+    ))
+}
+
+fn select_information_schema(q: &str, i: usize) -> Option<(Introspection<'_>, usize)> {
+    let b = q.as_bytes();
+    let mut i = skip_whitespace_and_comments(b, i);
+    let columns = if b.get(i) == Some(&b'*') {
+        i += 1;
+        None
+    } else {
+        let mut columns = Vec::new();
+        loop {
+            let (column, end) = column_name(q, i)?;
+            i = end;
+            let alias = if let Some(end) = keyword(q, i, "AS") {
+                Some(name(q, end)?)
+            } else if keyword(q, i, "FROM").is_none() {
+                name(q, i)
+            } else {
+                None
+            };
+            let label = match alias {
+                Some((alias, end)) => {
+                    i = end;
+                    alias
+                }
+                None => column.clone(),
+            };
+            columns.push((column, label));
+
+            i = skip_whitespace_and_comments(b, i);
+            if b.get(i) != Some(&b',') {
+                break;
+            }
+            i += 1;
+        }
+        Some(columns)
+    };
+
+    let (schema, end) = name(q, keyword(q, i, "FROM")?)?;
+    i = skip_whitespace_and_comments(b, end);
+    if !schema.eq_ignore_ascii_case("information_schema") || b.get(i) != Some(&b'.') {
+        return None;
+    }
+    let (table, end) = name(q, i + 1)?;
+    i = end;
+    // an alias for the table does not matter, since columns are only matched by name
+    if let Some(end) = keyword(q, i, "AS") {
+        i = name(q, end)?.1;
+    } else if keyword(q, i, "WHERE").is_none() && keyword(q, i, "ORDER").is_none() {
+        if let Some((_, end)) = name(q, i) {
+            i = end;
+        }
+    }
+
+    let mut conditions = Vec::new();
+    if let Some(end) = keyword(q, i, "WHERE") {
+        i = end;
+        loop {
+            let (column, end) = column_name(q, i)?;
+            i = skip_whitespace_and_comments(b, end);
+            let filter = if b.get(i) == Some(&b'=') {
+                let (value, end) = string(q, i + 1)?;
+                i = end;
+                ShowFilter::Names(vec![value])
+            } else if let Some(end) = keyword(q, i, "LIKE") {
+                let (pattern, end) = string(q, end)?;
+                i = end;
+                ShowFilter::Like(pattern)
+            } else {
+                i = skip_whitespace_and_comments(b, keyword(q, i, "IN")?);
+                if b.get(i) != Some(&b'(') {
+                    return None;
+                }
+                let mut values = Vec::new();
+                loop {
+                    let (value, end) = string(q, i + 1)?;
+                    values.push(value);
+                    i = skip_whitespace_and_comments(b, end);
+                    match b.get(i) {
+                        Some(b',') => {}
+                        Some(b')') => break,
+                        _ => return None,
+                    }
+                }
+                i += 1;
+                ShowFilter::Names(values)
+            };
+            conditions.push((column, filter));
+            match keyword(q, i, "AND") {
+                Some(end) => i = end,
+                None => break,
+            }
+        }
+    }
+
+    let mut order = Vec::new();
+    if let Some(end) = keyword(q, i, "ORDER") {
+        i = keyword(q, end, "BY")?;
+        loop {
+            let (column, end) = column_name(q, i)?;
+            i = end;
+            let mut descending = false;
+            if let Some(end) = keyword(q, i, "ASC") {
+                i = end;
+            } else if let Some(end) = keyword(q, i, "DESC") {
+                descending = true;
+                i = end;
+            }
+            order.push((column, descending));
+
+            i = skip_whitespace_and_comments(b, i);
+            if b.get(i) != Some(&b',') {
+                break;
+            }
+            i += 1;
+        }
+    }
+
+    Some((
+        Introspection::InformationSchema(InformationSchemaQuery {
+            table,
+            columns,
+            conditions,
+            order,
+        }),
+        i,
+    ))
+}
+
+/// Parses the literal that starts at `i`, and returns it along with the index just past it.
+fn set_value(q: &str, i: usize) -> Option<(SetValue<'_>, usize)> {
+    let b = q.as_bytes();
+    match b.get(i) {
+        Some(&quote) if quote == b'\'' || quote == b'"' => {
+            let end = skip_quoted(b, i);
+            if end - i < 2 || b[end - 1] != quote {
+                return None;
+            }
+            let mut value = String::with_capacity(end - i);
+            let mut chars = q[i + 1..end - 1].chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.push(match chars.next()? {
+                        '0' => '\0',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        c => c,
+                    }),
+                    c if c == char::from(quote) => {
+                        // a doubled quote
+                        chars.next();
+                        value.push(c);
+                    }
+                    c => value.push(c),
+                }
+            }
+            Some((SetValue::String(value), end))
+        }
+        Some(b'-') | Some(b'+') | Some(b'0'..=b'9') | Some(b'.') => {
+            let end = b[i + 1..]
+                .iter()
+                .position(|&c| !(c.is_ascii_digit() || c == b'.'))
+                .map(|p| i + 1 + p)
+                .unwrap_or(b.len());
+            if !b[i..end].iter().any(u8::is_ascii_digit) {
+                return None;
+            }
+            Some((SetValue::Number(&q[i..end]), end))
+        }
+        _ => {
+            let end = identifier(b, i);
+            let word = &q[i..end];
+            if word.is_empty() || b.get(end) == Some(&b'(') {
+                None
+            } else if word.eq_ignore_ascii_case("DEFAULT") {
+                Some((SetValue::Default, end))
+            } else if word.eq_ignore_ascii_case("NULL") {
+                Some((SetValue::Null, end))
+            } else {
+                Some((SetValue::Word(word), end))
+            }
+        }
+    }
+}
+
+/// Parses the character set or collation name that starts at `i`, which may be quoted, and
+/// returns it along with the index just past it. `DEFAULT` is returned as `None`.
+fn charset_name(q: &str, i: usize) -> Option<(Option<&str>, usize)> {
+    let b = q.as_bytes();
+    match b.get(i) {
+        Some(&quote) if quote == b'\'' || quote == b'"' => {
+            let end = skip_quoted(b, i);
+            if end - i < 2 || b[end - 1] != quote {
+                return None;
+            }
+            Some((Some(&q[i + 1..end - 1]), end))
+        }
+        _ => match set_value(q, i)? {
+            (SetValue::Default, end) => Some((None, end)),
+            (SetValue::Word(name), end) => Some((Some(name), end)),
+            _ => None,
+        },
+    }
+}
+
+/// Parses the string literal at or after `i`, and returns its value along with the index just
+/// past it.
+fn string(q: &str, i: usize) -> Option<(String, usize)> {
+    match set_value(q, skip_whitespace_and_comments(q.as_bytes(), i))? {
+        (SetValue::String(s), end) => Some((s, end)),
+        _ => None,
+    }
+}
+
+/// Parses the `gtrid [, bqual [, formatID]]` of an XA transaction branch at or after `i`, and
+/// returns it along with the index just past it.
+fn xid(q: &str, i: usize) -> Option<(Xid, usize)> {
+    let b = q.as_bytes();
+    let (gtrid, mut end) = xid_part(q, i)?;
+    let mut xid = Xid {
+        gtrid,
+        bqual: Vec::new(),
+        format_id: 1,
+    };
+    let comma = skip_whitespace_and_comments(b, end);
+    if b.get(comma) == Some(&b',') {
+        let (bqual, e) = xid_part(q, comma + 1)?;
+        xid.bqual = bqual;
+        end = e;
+        let comma = skip_whitespace_and_comments(b, end);
+        if b.get(comma) == Some(&b',') {
+            match set_value(q, skip_whitespace_and_comments(b, comma + 1))? {
+                (SetValue::Number(n), e) => {
+                    xid.format_id = n.parse().ok()?;
+                    end = e;
+                }
+                _ => return None,
+            }
+        }
+    }
+    if xid.gtrid.is_empty() || xid.gtrid.len() > 64 || xid.bqual.len() > 64 {
+        return None;
+    }
+    Some((xid, end))
+}
+
+/// Parses the `gtrid` or `bqual` at or after `i`, which is either a quoted string or a
+/// hexadecimal literal (`X'...'` or `0x...`), and returns its bytes along with the index just
+/// past it.
+fn xid_part(q: &str, i: usize) -> Option<(Vec<u8>, usize)> {
+    let b = q.as_bytes();
+    let i = skip_whitespace_and_comments(b, i);
+    let (hex, end) = if matches!(b.get(i..i + 2), Some(b"0x") | Some(b"0X")) {
+        let end = identifier(b, i);
+        (&q[i + 2..end], end)
+    } else if matches!(b.get(i), Some(b'x') | Some(b'X')) && b.get(i + 1) == Some(&b'\'') {
+        let end = skip_quoted(b, i + 1);
+        if end - i < 3 || b[end - 1] != b'\'' {
+            return None;
+        }
+        (&q[i + 2..end - 1], end)
+    } else {
+        let (s, end) = string(q, i)?;
+        return Some((s.into_bytes(), end));
+    };
+
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|j| u8::from_str_radix(hex.get(j..j + 2)?, 16).ok())
+        .collect::<Option<_>>()?;
+    Some((bytes, end))
+}
+
+/// Parses the (possibly quoted) identifier at or after `i`, and returns it without quotes along
+/// with the index just past it.
+///
+/// In backticks, a doubled backtick stands for a single one.
+fn name(q: &str, i: usize) -> Option<(Cow<'_, str>, usize)> {
+    let b = q.as_bytes();
+    let i = skip_whitespace_and_comments(b, i);
+    if b.get(i) == Some(&b'`') {
+        let end = skip_quoted(b, i);
+        if end - i < 2 || b[end - 1] != b'`' {
+            return None;
+        }
+        let name = &q[i + 1..end - 1];
+        if name.contains("``") {
+            Some((Cow::Owned(name.replace("``", "`")), end))
+        } else {
+            Some((Cow::Borrowed(name), end))
+        }
+    } else {
+        // unquoted identifiers may also hold any non-ASCII character
+        let end = b[i..]
+            .iter()
+            .position(|&c| !(c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80))
+            .map(|p| i + p)
+            .unwrap_or(b.len());
+        if end == i {
+            return None;
+        }
+        Some((Cow::Borrowed(&q[i..end]), end))
+    }
+}
+
+/// Parses the table name at or after `i`, which may be qualified with a schema, and returns it
+/// along with the index just past it.
+fn table_name(q: &str, i: usize) -> Option<(TableName<'_>, usize)> {
+    let (name, end) = name(q, i)?;
+    let dot = skip_whitespace_and_comments(q.as_bytes(), end);
+    if q.as_bytes().get(dot) == Some(&b'.') {
+        let (table, end) = self::name(q, dot + 1)?;
+        Some((
+            TableName {
+                schema: Some(name),
+                table,
+            },
+            end,
+        ))
+    } else {
+        Some((
+            TableName {
+                schema: None,
+                table: name,
+            },
+            end,
+        ))
+    }
+}
+
+/// Parses the column name at or after `i`, and returns it without the table that may qualify it
+/// along with the index just past it.
+fn column_name(q: &str, i: usize) -> Option<(Cow<'_, str>, usize)> {
+    let (name, end) = name(q, i)?;
+    let dot = skip_whitespace_and_comments(q.as_bytes(), end);
+    if q.as_bytes().get(dot) == Some(&b'.') {
+        self::name(q, dot + 1)
+    } else {
+        Some((name, end))
+    }
+}
+
+/// The scope named by a `GLOBAL`, `SESSION` or `LOCAL` modifier.
+fn scope_modifier(word: &str) -> Option<VariableScope> {
+    if word.eq_ignore_ascii_case("GLOBAL") {
+        Some(VariableScope::Global)
+    } else if word.eq_ignore_ascii_case("SESSION") || word.eq_ignore_ascii_case("LOCAL") {
+        Some(VariableScope::Session)
+    } else {
+        None
+    }
+}
+
+/// If the next word at or after `i` is `keyword`, returns the index just past it.
+fn keyword(q: &str, i: usize, keyword: &str) -> Option<usize> {
+    let b = q.as_bytes();
+    let i = skip_whitespace_and_comments(b, i);
+    let end = identifier(b, i);
+    if q[i..end].eq_ignore_ascii_case(keyword) {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+/// Returns the index just past the identifier that starts at `i`.
+fn identifier(q: &[u8], i: usize) -> usize {
+    q[i..]
+        .iter()
+        .position(|&c| !(c.is_ascii_alphanumeric() || c == b'_' || c == b'$'))
+        .map(|p| i + p)
+        .unwrap_or(q.len())
+}
+
+/// Whether nothing but an optional `;` and whitespace or comments follows `i`.
+fn at_end(q: &[u8], i: usize) -> bool {
+    match q.get(i) {
+        None => true,
+        Some(b';') => skip_whitespace_and_comments(q, i + 1) == q.len(),
+        _ => false,
+    }
+}
+
+/// Trims leading and trailing ASCII whitespace.
+pub(crate) fn trim(q: &[u8]) -> &[u8] {
+    let start = q
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(q.len());
+    let end = q
+        .iter()
+        .rposition(|c| !c.is_ascii_whitespace())
+        .map(|p| p + 1)
+        .unwrap_or(start);
+    &q[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(q: &str) -> Vec<&str> {
+        split_statements(q.as_bytes())
+            .into_iter()
+            .map(|s| ::std::str::from_utf8(s).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_splits_statements() {
+        assert_eq!(split("SELECT 1"), vec!["SELECT 1"]);
+        assert_eq!(split("SELECT 1;"), vec!["SELECT 1"]);
+        assert_eq!(
+            split("INSERT INTO t VALUES (1); SELECT * FROM t"),
+            vec!["INSERT INTO t VALUES (1)", "SELECT * FROM t"]
+        );
+        assert_eq!(split(" ; ;; "), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn it_respects_quotes() {
+        assert_eq!(
+            split(r#"SELECT 'a;b', "c;d", `e;f`; SELECT 2"#),
+            vec![r#"SELECT 'a;b', "c;d", `e;f`"#, "SELECT 2"]
+        );
+        assert_eq!(
+            split(r"SELECT 'it''s;', 'back\';slash'; SELECT 2"),
+            vec![r"SELECT 'it''s;', 'back\';slash'", "SELECT 2"]
+        );
+        assert_eq!(
+            split("SELECT 'unterminated;"),
+            vec!["SELECT 'unterminated;"]
+        );
+    }
+
+    #[test]
+    fn it_respects_comments() {
+        assert_eq!(
+            split("SELECT 1 -- one; two\n; SELECT /* ; */ 2 # three;"),
+            vec!["SELECT 1 -- one; two", "SELECT /* ; */ 2 # three;"]
+        );
+        assert_eq!(
+            split("SELECT 1--2; SELECT 3"),
+            vec!["SELECT 1--2", "SELECT 3"]
+        );
+        assert_eq!(
+            split("SELECT 1; /* nothing */ ; -- at all"),
+            vec!["SELECT 1"]
+        );
+    }
+
+    #[test]
+    fn it_splits_placeholders() {
+        assert_eq!(split_placeholders("SELECT 1"), vec!["SELECT 1"]);
+        assert_eq!(
+            split_placeholders("SELECT a FROM t WHERE b = ? AND c IN (?, ?)"),
+            vec!["SELECT a FROM t WHERE b = ", " AND c IN (", ", ", ")"]
+        );
+        assert_eq!(
+            split_placeholders("SELECT '?', \"?\", `?`, 'it''s?' -- ?\n, ? /* ? */ # ?"),
+            vec!["SELECT '?', \"?\", `?`, 'it''s?' -- ?\n, ", " /* ? */ # ?"]
+        );
+    }
+
+    #[test]
+    fn it_parses_kill() {
+        assert_eq!(parse_kill(b"KILL 12"), Some((false, 12)));
+        assert_eq!(parse_kill(b"kill query 12;"), Some((true, 12)));
+        assert_eq!(parse_kill(b" KILL CONNECTION /* x */ 7 "), Some((false, 7)));
+        assert_eq!(parse_kill(b"KILL"), None);
+        assert_eq!(parse_kill(b"KILL QUERY"), None);
+        assert_eq!(parse_kill(b"KILL QUERY 1 2"), None);
+        assert_eq!(parse_kill(b"KILL 99999999999"), None);
+        assert_eq!(parse_kill(b"KILLED 1"), None);
+        assert_eq!(parse_kill(b"SELECT 'KILL 1'"), None);
+    }
+
+    fn select(q: &str) -> Option<Vec<(Option<VariableScope>, &str, &str)>> {
+        parse_select_variables(q.as_bytes()).map(|(vars, row)| {
+            assert!(row);
+            vars.into_iter()
+                .map(|v| (v.scope, v.name, v.column))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn it_parses_select_variables() {
+        use crate::variables::VariableScope::{Global, Session};
+
+        assert_eq!(
+            select("SELECT @@max_allowed_packet"),
+            Some(vec![(None, "max_allowed_packet", "@@max_allowed_packet")])
+        );
+        assert_eq!(
+            select("select @@a, @@SESSION.b,@@global.c ;"),
+            Some(vec![
+                (None, "a", "@@a"),
+                (Some(Session), "b", "@@SESSION.b"),
+                (Some(Global), "c", "@@global.c"),
+            ])
+        );
+        assert_eq!(
+            select("SELECT @@local.a AS x, @@b `y z`, @@c AS 'w' /* done */"),
+            Some(vec![
+                (Some(Session), "a", "x"),
+                (None, "b", "y z"),
+                (None, "c", "w"),
+            ])
+        );
+        assert_eq!(select("SELECT @@a FROM dual"), None);
+        assert_eq!(
+            select("select @@version_comment limit 1"),
+            Some(vec![(None, "version_comment", "@@version_comment")])
+        );
+        assert_eq!(
+            select("SELECT @@a AS `limit` LIMIT 0, 1"),
+            Some(vec![(None, "a", "limit")])
+        );
+        let limited = |q: &str| parse_select_variables(q.as_bytes()).map(|(_, row)| row);
+        assert_eq!(limited("SELECT @@a LIMIT 0"), Some(false));
+        assert_eq!(limited("SELECT @@a LIMIT 1 OFFSET 1"), Some(false));
+        assert_eq!(limited("SELECT @@a LIMIT 1, 1"), Some(false));
+        assert_eq!(select("SELECT @@a LIMIT"), None);
+        assert_eq!(select("SELECT @@a LIMIT -1"), None);
+        assert_eq!(select("SELECT @@a + 1"), None);
+        assert_eq!(select("SELECT @@a AS"), None);
+        assert_eq!(select("SELECT @@a, 1"), None);
+        assert_eq!(select("SELECT @@other.a"), None);
+        assert_eq!(select("SELECT @@"), None);
+        assert_eq!(select("SELECT @a"), None);
+        assert_eq!(select("SELECTED @@a"), None);
+    }
+
+    #[test]
+    fn it_parses_set() {
+        use crate::variables::VariableScope::{Global, Session};
+
+        fn var<'a>(scope: VariableScope, name: &'a str, value: SetValue<'a>) -> Assignment<'a> {
+            Assignment::Variable { scope, name, value }
+        }
+
+        assert_eq!(
+            parse_set(b"SET autocommit=0"),
+            Some(vec![var(Session, "autocommit", SetValue::Number("0"))])
+        );
+        assert_eq!(
+            parse_set(b"set GLOBAL a = ON, b := -1.5, SESSION c='it''s', d = \"x\\ty\";"),
+            Some(vec![
+                var(Global, "a", SetValue::Word("ON")),
+                var(Global, "b", SetValue::Number("-1.5")),
+                var(Session, "c", SetValue::String("it's".to_owned())),
+                var(Session, "d", SetValue::String("x\ty".to_owned())),
+            ])
+        );
+        assert_eq!(
+            parse_set(b"SET @@session.time_zone='+00:00', @@GLOBAL.x = NULL, @@y = DEFAULT"),
+            Some(vec![
+                var(Session, "time_zone", SetValue::String("+00:00".to_owned())),
+                var(Global, "x", SetValue::Null),
+                var(Session, "y", SetValue::Default),
+            ])
+        );
+        assert_eq!(
+            parse_set(b"SET NAMES utf8mb4 COLLATE utf8mb4_bin, character_set_results = NULL"),
+            Some(vec![
+                Assignment::Names(Some(("utf8mb4", Some("utf8mb4_bin")))),
+                var(Session, "character_set_results", SetValue::Null),
+            ])
+        );
+        assert_eq!(
+            parse_set(b"SET NAMES 'latin1' COLLATE 'latin1_bin'"),
+            Some(vec![Assignment::Names(Some((
+                "latin1",
+                Some("latin1_bin")
+            )))])
+        );
+        assert_eq!(
+            parse_set(b"SET NAMES DEFAULT; "),
+            Some(vec![Assignment::Names(None)])
+        );
+        assert_eq!(
+            parse_set(b"SET CHARACTER SET utf8, CHARSET DEFAULT"),
+            Some(vec![
+                Assignment::CharacterSet(Some("utf8")),
+                Assignment::CharacterSet(None),
+            ])
+        );
+        assert_eq!(parse_set(b"SET @x = 1"), None);
+        assert_eq!(parse_set(b"SET a = @@b"), None);
+        assert_eq!(parse_set(b"SET a = 1 + 1"), None);
+        assert_eq!(parse_set(b"SET a = NOW()"), None);
+        assert_eq!(parse_set(b"SET a"), None);
+        assert_eq!(parse_set(b"SET NAMES utf8 COLLATE DEFAULT"), None);
+        assert_eq!(
+            parse_set(b"SET TRANSACTION ISOLATION LEVEL READ COMMITTED"),
+            None
+        );
+        assert_eq!(parse_set(b"SET @@other.a = 1"), None);
+        assert_eq!(parse_set(b"SETTLE a = 1"), None);
+    }
+
+    #[test]
+    fn it_parses_show() {
+        use crate::variables::VariableScope::{Global, Session};
+
+        assert_eq!(
+            parse_show(b"SHOW VARIABLES"),
+            Some((Show::Variables(Session), ShowFilter::All))
+        );
+        assert_eq!(
+            parse_show(b"show global variables like 'lower_case%';"),
+            Some((
+                Show::Variables(Global),
+                ShowFilter::Like("lower_case%".to_owned())
+            ))
+        );
+        assert_eq!(
+            parse_show(
+                b"SHOW VARIABLES WHERE Variable_name ='language' OR variable_name = 'net_write_timeout'"
+            ),
+            Some((
+                Show::Variables(Session),
+                ShowFilter::Names(vec!["language".to_owned(), "net_write_timeout".to_owned()])
+            ))
+        );
+        assert_eq!(
+            parse_show(b"SHOW COLLATION"),
+            Some((Show::Collation, ShowFilter::All))
+        );
+        assert_eq!(
+            parse_show(b"SHOW CHARACTER SET LIKE 'utf8%'"),
+            Some((Show::CharacterSet, ShowFilter::Like("utf8%".to_owned())))
+        );
+        assert_eq!(
+            parse_show(b"SHOW CHARSET WHERE Charset = 'latin1'"),
+            Some((
+                Show::CharacterSet,
+                ShowFilter::Names(vec!["latin1".to_owned()])
+            ))
+        );
+        assert_eq!(parse_show(b"SHOW GLOBAL COLLATION"), None);
+        assert_eq!(parse_show(b"SHOW VARIABLES WHERE Value = '1'"), None);
+        assert_eq!(
+            parse_show(b"SHOW VARIABLES WHERE Variable_name LIKE 'a'"),
+            None
+        );
+        assert_eq!(parse_show(b"SHOW VARIABLES LIKE lower_case"), None);
+        assert_eq!(parse_show(b"SHOW TABLES"), None);
+        assert_eq!(parse_show(b"SHOW STATUS"), None);
+    }
+
+    #[test]
+    fn it_parses_use() {
+        let schema = |q: &str| parse_use(q.as_bytes()).map(Cow::into_owned);
+
+        assert_eq!(schema("USE test"), Some("test".to_owned()));
+        assert_eq!(schema("Use test;"), Some("test".to_owned()));
+        assert_eq!(schema("  use\ttest ; "), Some("test".to_owned()));
+        assert_eq!(
+            schema("/* switch */ USE -- now\n test # done"),
+            Some("test".to_owned())
+        );
+        assert_eq!(schema("USE `my db`"), Some("my db".to_owned()));
+        assert_eq!(schema("USE `a``b`;"), Some("a`b".to_owned()));
+        assert_eq!(schema("USE`test`"), Some("test".to_owned()));
+        assert_eq!(schema("USE données"), Some("données".to_owned()));
+        assert_eq!(schema("USE"), None);
+        assert_eq!(schema("USE `test"), None);
+        assert_eq!(schema("USE a b"), None);
+        assert_eq!(schema("USE a; USE b"), None);
+        assert_eq!(schema("USER test"), None);
+        assert_eq!(schema("SELECT 'USE test'"), None);
+    }
+
+    #[test]
+    fn it_parses_show_introspection() {
+        let parse = |q: &'static str| parse_introspection(q.as_bytes());
+        let table = |schema: Option<&'static str>, table: &'static str| TableName {
+            schema: schema.map(Cow::Borrowed),
+            table: Cow::Borrowed(table),
+        };
+
+        assert_eq!(
+            parse("SHOW DATABASES"),
+            Some(Introspection::Databases(ShowFilter::All))
+        );
+        assert_eq!(
+            parse("show schemas like 'a%';"),
+            Some(Introspection::Databases(ShowFilter::Like("a%".to_owned())))
+        );
+        assert_eq!(
+            parse("SHOW FULL TABLES FROM `my db` WHERE Tables_in_my_db = 'x'"),
+            Some(Introspection::Tables {
+                full: true,
+                schema: Some(Cow::Borrowed("my db")),
+                filter: ShowFilter::Names(vec!["x".to_owned()]),
+            })
+        );
+        assert_eq!(
+            parse("SHOW TABLES"),
+            Some(Introspection::Tables {
+                full: false,
+                schema: None,
+                filter: ShowFilter::All,
+            })
+        );
+        assert_eq!(
+            parse("SHOW COLUMNS FROM t IN db LIKE 'a%'"),
+            Some(Introspection::Columns {
+                full: false,
+                table: table(Some("db"), "t"),
+                filter: ShowFilter::Like("a%".to_owned()),
+            })
+        );
+        assert_eq!(
+            parse("SHOW FULL FIELDS FROM db.`t`"),
+            Some(Introspection::Columns {
+                full: true,
+                table: table(Some("db"), "t"),
+                filter: ShowFilter::All,
+            })
+        );
+        assert_eq!(
+            parse("DESCRIBE t"),
+            Some(Introspection::Columns {
+                full: false,
+                table: table(None, "t"),
+                filter: ShowFilter::All,
+            })
+        );
+        assert_eq!(
+            parse("desc db.t a"),
+            Some(Introspection::Columns {
+                full: false,
+                table: table(Some("db"), "t"),
+                filter: ShowFilter::Like("a".to_owned()),
+            })
+        );
+        assert_eq!(
+            parse("SHOW CREATE TABLE `db`.t"),
+            Some(Introspection::CreateTable(table(Some("db"), "t")))
+        );
+
+        assert_eq!(parse("SHOW TABLES WHERE Table_type = 'VIEW'"), None);
+        assert_eq!(parse("SHOW COLUMNS t"), None);
+        assert_eq!(parse("SHOW CREATE VIEW v"), None);
+        assert_eq!(parse("DESCRIBE SELECT * FROM t"), None);
+        assert_eq!(parse("SHOW VARIABLES"), None);
+    }
+
+    #[test]
+    fn it_parses_information_schema_queries() {
+        let parse = |q: &'static str| match parse_introspection(q.as_bytes()) {
+            Some(Introspection::InformationSchema(query)) => Some(query),
+            _ => None,
+        };
+
+        let query = parse(
+            "SELECT c.table_name, column_name AS name, `DATA_TYPE` type \
+             FROM information_schema.COLUMNS c \
+             WHERE table_schema = 'db' AND c.table_name IN ('a', 'b') AND column_name LIKE 'x%' \
+             ORDER BY table_name, ORDINAL_POSITION DESC",
+        )
+        .unwrap();
+        assert_eq!(query.table, "COLUMNS");
+        assert_eq!(
+            query.columns.unwrap(),
+            vec![
+                (Cow::Borrowed("table_name"), Cow::Borrowed("table_name")),
+                (Cow::Borrowed("column_name"), Cow::Borrowed("name")),
+                (Cow::Borrowed("DATA_TYPE"), Cow::Borrowed("type")),
+            ]
+        );
+        assert_eq!(
+            query.conditions,
+            vec![
+                (
+                    Cow::Borrowed("table_schema"),
+                    ShowFilter::Names(vec!["db".to_owned()])
+                ),
+                (
+                    Cow::Borrowed("table_name"),
+                    ShowFilter::Names(vec!["a".to_owned(), "b".to_owned()])
+                ),
+                (
+                    Cow::Borrowed("column_name"),
+                    ShowFilter::Like("x%".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(
+            query.order,
+            vec![
+                (Cow::Borrowed("table_name"), false),
+                (Cow::Borrowed("ORDINAL_POSITION"), true),
+            ]
+        );
+
+        let query = parse("select * from INFORMATION_SCHEMA.schemata;").unwrap();
+        assert_eq!(query.table, "schemata");
+        assert_eq!(query.columns, None);
+        assert!(query.conditions.is_empty());
+
+        assert_eq!(parse("SELECT * FROM t"), None);
+        assert_eq!(
+            parse("SELECT COUNT(*) FROM information_schema.tables"),
+            None
+        );
+        assert_eq!(
+            parse("SELECT * FROM information_schema.tables WHERE table_rows > 0"),
+            None
+        );
+        assert_eq!(
+            parse("SELECT * FROM information_schema.tables WHERE a = 'x' OR b = 'y'"),
+            None
+        );
+        assert_eq!(
+            parse("SELECT * FROM information_schema.tables LIMIT 1"),
+            None
+        );
+    }
+
+    #[test]
+    fn it_parses_show_warnings() {
+        let parse = |q: &str| parse_show_warnings(q.as_bytes());
+        let all = (0, u64::MAX);
+
+        assert_eq!(
+            parse("SHOW WARNINGS"),
+            Some(ShowWarnings {
+                errors: false,
+                count: false,
+                limit: all,
+            })
+        );
+        assert_eq!(
+            parse("show errors limit 2, 5;"),
+            Some(ShowWarnings {
+                errors: true,
+                count: false,
+                limit: (2, 5),
+            })
+        );
+        assert_eq!(
+            parse("SHOW COUNT ( * ) WARNINGS"),
+            Some(ShowWarnings {
+                errors: false,
+                count: true,
+                limit: all,
+            })
+        );
+        assert_eq!(parse("SHOW COUNT(*) ERRORS LIMIT 1"), None);
+        assert_eq!(parse("SHOW COUNT(a) WARNINGS"), None);
+        assert_eq!(parse("SHOW WARNINGS LIMIT"), None);
+        assert_eq!(parse("SHOW VARIABLES"), None);
+    }
+
+    #[test]
+    fn it_parses_transaction_statements() {
+        let parse = |q: &'static str| parse_transaction(q.as_bytes());
+        let options = |read_only, consistent_snapshot| {
+            Some(Transaction::Begin(TransactionOptions {
+                read_only,
+                consistent_snapshot,
+            }))
+        };
+
+        assert_eq!(parse("BEGIN"), options(false, false));
+        assert_eq!(parse("begin work;"), options(false, false));
+        assert_eq!(parse("START TRANSACTION"), options(false, false));
+        assert_eq!(parse("START TRANSACTION READ ONLY"), options(true, false));
+        assert_eq!(
+            parse("START TRANSACTION WITH CONSISTENT SNAPSHOT, READ WRITE"),
+            options(false, true)
+        );
+        assert_eq!(parse("COMMIT WORK"), Some(Transaction::Commit));
+        assert_eq!(parse("/* x */ ROLLBACK"), Some(Transaction::Rollback));
+        assert_eq!(
+            parse("SAVEPOINT `s 1`"),
+            Some(Transaction::Savepoint("s 1".into()))
+        );
+        assert_eq!(
+            parse("RELEASE SAVEPOINT s1"),
+            Some(Transaction::ReleaseSavepoint("s1".into()))
+        );
+        assert_eq!(
+            parse("ROLLBACK WORK TO SAVEPOINT s1"),
+            Some(Transaction::RollbackToSavepoint("s1".into()))
+        );
+        assert_eq!(
+            parse("ROLLBACK TO s1"),
+            Some(Transaction::RollbackToSavepoint("s1".into()))
+        );
+
+        assert_eq!(parse("START TRANSACTION READ ONLY,"), None);
+        assert_eq!(parse("START TRANSACTION WITH SNAPSHOT"), None);
+        assert_eq!(parse("COMMIT AND CHAIN"), None);
+        assert_eq!(parse("ROLLBACK RELEASE"), None);
+        assert_eq!(parse("RELEASE s1"), None);
+        assert_eq!(parse("SET TRANSACTION READ ONLY"), None);
+        assert_eq!(parse("BEGINNING"), None);
+    }
+
+    #[test]
+    fn it_parses_xa_statements() {
+        let xid = |gtrid: &[u8], bqual: &[u8], format_id| Xid {
+            gtrid: gtrid.to_vec(),
+            bqual: bqual.to_vec(),
+            format_id,
+        };
+        let parse = |q: &str| parse_xa(q.as_bytes());
+
+        assert_eq!(
+            parse("XA START 'a'"),
+            Some(XaStatement::Start(xid(b"a", b"", 1)))
+        );
+        assert_eq!(
+            parse("xa begin 'a', 'b';"),
+            Some(XaStatement::Start(xid(b"a", b"b", 1)))
+        );
+        assert_eq!(
+            parse("XA END 0x0aff, X'62', 131077"),
+            Some(XaStatement::End(xid(&[0x0a, 0xff], b"b", 131077)))
+        );
+        assert_eq!(
+            parse("XA PREPARE 'a'"),
+            Some(XaStatement::Prepare(xid(b"a", b"", 1)))
+        );
+        assert_eq!(
+            parse("XA COMMIT 'a' ONE PHASE"),
+            Some(XaStatement::Commit(xid(b"a", b"", 1), true))
+        );
+        assert_eq!(
+            parse("XA COMMIT 'a'"),
+            Some(XaStatement::Commit(xid(b"a", b"", 1), false))
+        );
+        assert_eq!(
+            parse("XA ROLLBACK 'a', '', -1"),
+            Some(XaStatement::Rollback(xid(b"a", b"", -1)))
+        );
+        assert_eq!(parse("XA RECOVER"), Some(XaStatement::Recover(false)));
+        assert_eq!(
+            parse("XA RECOVER CONVERT XID"),
+            Some(XaStatement::Recover(true))
+        );
+
+        assert_eq!(parse("XA START 'a' JOIN"), None);
+        assert_eq!(parse("XA END 'a' SUSPEND"), None);
+        assert_eq!(parse("XA START ''"), None);
+        assert_eq!(parse("XA START 0xabc"), None);
+        assert_eq!(parse("XA START 0xzz"), None);
+        assert_eq!(parse("XA COMMIT 'a' ONE"), None);
+        assert_eq!(parse("XA START a"), None);
+        assert_eq!(parse("XA"), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Parsing of queries into statements, for shims that would rather not parse SQL themselves.
+//!
+//! Only compiled with the `sqlparser` feature, which makes the intermediary give queries that
+//! parse as a single statement to
+//! [`MysqlShim::on_statement`](trait.MysqlShim.html#method.on_statement), and queries to prepare
+//! that do to
+//! [`MysqlShim::on_prepare_statement`](trait.MysqlShim.html#method.on_prepare_statement).
+
+use sqlparser::ast::{Query, Statement};
+use sqlparser::dialect::MySqlDialect;
+use sqlparser::parser::Parser;
+
+/// Parse `query` with the MySQL dialect, if it holds exactly one statement.
+pub(crate) fn parse(query: &str) -> Option<Statement> {
+    let mut statements = Parser::parse_sql(&MySqlDialect {}, query).ok()?;
+    if statements.len() == 1 {
+        statements.pop()
+    } else {
+        None
+    }
+}
+
+/// The callback a parsed statement is routed to by default.
+pub(crate) enum Routed {
+    Select(Query),
+    Insert(Statement),
+    Update(Statement),
+    Delete(Statement),
+    Ddl(Statement),
+    Transaction(Statement),
+    Other,
+}
+
+/// Decide which callback `statement` should be given to.
+pub(crate) fn route(statement: Statement) -> Routed {
+    match statement {
+        Statement::Query(query) => Routed::Select(*query),
+        Statement::Insert { .. } => Routed::Insert(statement),
+        Statement::Update { .. } => Routed::Update(statement),
+        Statement::Delete { .. } => Routed::Delete(statement),
+        Statement::CreateTable { .. }
+        | Statement::CreateView { .. }
+        | Statement::CreateIndex { .. }
+        | Statement::CreateSchema { .. }
+        | Statement::CreateDatabase { .. }
+        | Statement::AlterTable { .. }
+        | Statement::AlterIndex { .. }
+        | Statement::AlterView { .. }
+        | Statement::Drop { .. }
+        | Statement::Truncate { .. } => Routed::Ddl(statement),
+        Statement::StartTransaction { .. }
+        | Statement::SetTransaction { .. }
+        | Statement::Commit { .. }
+        | Statement::Rollback { .. }
+        | Statement::Savepoint { .. } => Routed::Transaction(statement),
+        _ => Routed::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routed(query: &str) -> &'static str {
+        match route(parse(query).unwrap()) {
+            Routed::Select(_) => "select",
+            Routed::Insert(_) => "insert",
+            Routed::Update(_) => "update",
+            Routed::Delete(_) => "delete",
+            Routed::Ddl(_) => "ddl",
+            Routed::Transaction(_) => "transaction",
+            Routed::Other => "other",
+        }
+    }
+
+    #[test]
+    fn it_routes_statements() {
+        assert_eq!(routed("SELECT a FROM t WHERE b = 1"), "select");
+        assert_eq!(routed("INSERT INTO t (a) VALUES (1)"), "insert");
+        assert_eq!(routed("UPDATE t SET a = 2"), "update");
+        assert_eq!(routed("DELETE FROM t"), "delete");
+        assert_eq!(routed("CREATE TABLE t (a INT)"), "ddl");
+        assert_eq!(routed("DROP TABLE t"), "ddl");
+        assert_eq!(routed("START TRANSACTION"), "transaction");
+        assert_eq!(routed("COMMIT"), "transaction");
+        assert_eq!(routed("SHOW TABLES"), "other");
+    }
+
+    #[test]
+    fn it_only_parses_single_statements() {
+        assert!(parse("SELECT 1; SELECT 2").is_none());
+        assert!(parse("SELEKT 1").is_none());
+        assert!(parse("").is_none());
+    }
+
+    #[test]
+    fn it_parses_placeholders() {
+        assert_eq!(routed("SELECT a FROM t WHERE b = ? AND c = ?"), "select");
+        assert_eq!(routed("INSERT INTO t (a, b) VALUES (?, ?)"), "insert");
+    }
+}
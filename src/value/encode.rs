@@ -17,6 +17,32 @@ pub trait ToMysqlValue {
     fn is_null(&self) -> bool {
         false
     }
+
+    /// The type of the columns that hold values of this type, for resultsets whose columns are
+    /// derived from Rust types (see [`MysqlRow`](trait.MysqlRow.html)).
+    fn column_type() -> ColumnType {
+        ColumnType::MYSQL_TYPE_VAR_STRING
+    }
+
+    /// The flags of the columns that hold values of this type. Such columns are `NOT NULL` unless
+    /// the values are `Option`s.
+    fn column_flags() -> ColumnFlags {
+        ColumnFlags::NOT_NULL_FLAG
+    }
+}
+
+macro_rules! column_of {
+    ($ct:ident) => {
+        fn column_type() -> ColumnType {
+            ColumnType::$ct
+        }
+    };
+    ($ct:ident, $flags:expr) => {
+        column_of!($ct);
+        fn column_flags() -> ColumnFlags {
+            ColumnFlags::NOT_NULL_FLAG | $flags
+        }
+    };
 }
 
 macro_rules! mysql_text_trivial {
@@ -40,6 +66,14 @@ impl<T> ToMysqlValue for Option<T>
 where
     T: ToMysqlValue,
 {
+    fn column_type() -> ColumnType {
+        T::column_type()
+    }
+
+    fn column_flags() -> ColumnFlags {
+        T::column_flags() - ColumnFlags::NOT_NULL_FLAG
+    }
+
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         if let Some(ref v) = *self {
             v.to_mysql_text(w)
@@ -87,9 +121,10 @@ macro_rules! like_try_into {
 }
 
 macro_rules! forgiving_numeric {
-    ($t:ty) => {
+    ($t:ty, $flags:expr) => {
         impl ToMysqlValue for $t {
             mysql_text_trivial!();
+            column_of!(MYSQL_TYPE_LONGLONG, $flags);
             fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
                 let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
                 match c.coltype {
@@ -128,10 +163,11 @@ macro_rules! forgiving_numeric {
     };
 }
 
-forgiving_numeric!(usize);
-forgiving_numeric!(isize);
+forgiving_numeric!(usize, ColumnFlags::UNSIGNED_FLAG);
+forgiving_numeric!(isize, ColumnFlags::empty());
 
 impl ToMysqlValue for u8 {
+    column_of!(MYSQL_TYPE_TINY, ColumnFlags::UNSIGNED_FLAG);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -167,6 +203,7 @@ impl ToMysqlValue for u8 {
 }
 
 impl ToMysqlValue for i8 {
+    column_of!(MYSQL_TYPE_TINY);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -202,6 +239,7 @@ impl ToMysqlValue for i8 {
 }
 
 impl ToMysqlValue for u16 {
+    column_of!(MYSQL_TYPE_SHORT, ColumnFlags::UNSIGNED_FLAG);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -230,6 +268,7 @@ impl ToMysqlValue for u16 {
 }
 
 impl ToMysqlValue for i16 {
+    column_of!(MYSQL_TYPE_SHORT);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -258,6 +297,7 @@ impl ToMysqlValue for i16 {
 }
 
 impl ToMysqlValue for u32 {
+    column_of!(MYSQL_TYPE_LONG, ColumnFlags::UNSIGNED_FLAG);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -279,6 +319,7 @@ impl ToMysqlValue for u32 {
 }
 
 impl ToMysqlValue for i32 {
+    column_of!(MYSQL_TYPE_LONG);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -300,6 +341,7 @@ impl ToMysqlValue for i32 {
 }
 
 impl ToMysqlValue for u64 {
+    column_of!(MYSQL_TYPE_LONGLONG, ColumnFlags::UNSIGNED_FLAG);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -314,6 +356,7 @@ impl ToMysqlValue for u64 {
 }
 
 impl ToMysqlValue for i64 {
+    column_of!(MYSQL_TYPE_LONGLONG);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         let signed = !c.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
@@ -328,6 +371,7 @@ impl ToMysqlValue for i64 {
 }
 
 impl ToMysqlValue for f32 {
+    column_of!(MYSQL_TYPE_FLOAT);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         match c.coltype {
@@ -339,6 +383,7 @@ impl ToMysqlValue for f32 {
 }
 
 impl ToMysqlValue for f64 {
+    column_of!(MYSQL_TYPE_DOUBLE);
     mysql_text_trivial!();
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         match c.coltype {
@@ -367,6 +412,11 @@ impl ToMysqlValue for str {
 }
 
 impl ToMysqlValue for [u8] {
+    column_of!(
+        MYSQL_TYPE_BLOB,
+        ColumnFlags::BLOB_FLAG | ColumnFlags::BINARY_FLAG
+    );
+
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         w.write_lenenc_str(self).map(|_| ())
     }
@@ -392,6 +442,11 @@ impl ToMysqlValue for [u8] {
 }
 
 impl ToMysqlValue for Vec<u8> {
+    column_of!(
+        MYSQL_TYPE_BLOB,
+        ColumnFlags::BLOB_FLAG | ColumnFlags::BINARY_FLAG
+    );
+
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         (&self[..]).to_mysql_text(w)
     }
@@ -404,16 +459,29 @@ impl<'a, T> ToMysqlValue for &'a T
 where
     T: ToMysqlValue + ?Sized,
 {
+    fn column_type() -> ColumnType {
+        T::column_type()
+    }
+
+    fn column_flags() -> ColumnFlags {
+        T::column_flags()
+    }
+
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         (*self).to_mysql_text(w)
     }
     fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
         (*self).to_mysql_bin(w, c)
     }
+    fn is_null(&self) -> bool {
+        (*self).is_null()
+    }
 }
 
 use chrono::{self, Datelike, NaiveDate, NaiveDateTime, Timelike};
 impl ToMysqlValue for NaiveDate {
+    column_of!(MYSQL_TYPE_DATE);
+
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         w.write_lenenc_str(
             format!("{:04}-{:02}-{:02}", self.year(), self.month(), self.day()).as_bytes(),
@@ -434,6 +502,8 @@ impl ToMysqlValue for NaiveDate {
 }
 
 impl ToMysqlValue for NaiveDateTime {
+    column_of!(MYSQL_TYPE_DATETIME);
+
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         let us = self.nanosecond() / 1_000;
 
@@ -497,6 +567,8 @@ impl ToMysqlValue for NaiveDateTime {
 
 use std::time::Duration;
 impl ToMysqlValue for Duration {
+    column_of!(MYSQL_TYPE_TIME);
+
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         let s = self.as_secs();
         //let d = s / (24 * 3600);
@@ -550,6 +622,10 @@ impl ToMysqlValue for Duration {
 }
 
 impl ToMysqlValue for myc::value::Value {
+    fn column_flags() -> ColumnFlags {
+        ColumnFlags::empty()
+    }
+
     #[allow(clippy::many_single_char_names)]
     fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
         match *self {
@@ -907,4 +983,71 @@ mod tests {
             ColumnType::MYSQL_TYPE_STRING
         );
     }
+
+    mod derived_columns {
+        use super::*;
+
+        macro_rules! rt {
+            ($name:ident, $t:ty, $v:expr) => {
+                #[test]
+                fn $name() {
+                    let mut data = Vec::new();
+                    let col = Column {
+                        table: String::new(),
+                        column: String::new(),
+                        coltype: <$t>::column_type(),
+                        colflags: <$t>::column_flags(),
+                    };
+                    let unsigned = col.colflags.contains(ColumnFlags::UNSIGNED_FLAG);
+
+                    let v: $t = $v;
+                    v.to_mysql_bin(&mut data, &col).unwrap();
+                    assert_eq!(
+                        from_value::<$t>(
+                            value::read_bin_value(&mut &data[..], col.coltype, unsigned).unwrap()
+                        ),
+                        v
+                    );
+                }
+            };
+        }
+
+        rt!(u8_max, u8, u8::MAX);
+        rt!(i8_min, i8, i8::MIN);
+        rt!(u16_max, u16, u16::MAX);
+        rt!(i16_min, i16, i16::MIN);
+        rt!(u32_max, u32, u32::MAX);
+        rt!(i32_min, i32, i32::MIN);
+        rt!(u64_max, u64, u64::MAX);
+        rt!(i64_min, i64, i64::MIN);
+        rt!(usize_max, usize, usize::MAX);
+        rt!(isize_min, isize, isize::MIN);
+        rt!(f32_one, f32, 1.0);
+        rt!(f64_one, f64, 1.0);
+        rt!(string, String, "foobar".to_owned());
+        rt!(bytes, Vec<u8>, vec![0x42, 0x00, 0x1a]);
+        rt!(
+            date,
+            chrono::NaiveDate,
+            chrono::NaiveDate::from_ymd_opt(1989, 12, 7).unwrap()
+        );
+        rt!(
+            datetime,
+            chrono::NaiveDateTime,
+            chrono::NaiveDate::from_ymd_opt(1989, 12, 7)
+                .unwrap()
+                .and_hms_opt(8, 0, 4)
+                .unwrap()
+        );
+        rt!(dur, time::Duration, time::Duration::from_secs(1893));
+
+        #[test]
+        fn nullable() {
+            assert_eq!(<Option<u32>>::column_type(), ColumnType::MYSQL_TYPE_LONG);
+            assert_eq!(<Option<u32>>::column_flags(), ColumnFlags::UNSIGNED_FLAG);
+            assert_eq!(<Option<&str>>::column_flags(), ColumnFlags::empty());
+            assert_eq!(<&str>::column_flags(), ColumnFlags::NOT_NULL_FLAG);
+            assert_eq!(value::Value::column_flags(), ColumnFlags::empty());
+        }
+    }
 }
@@ -0,0 +1,507 @@
+//! System variables, as read by clients with `SELECT @@name`.
+//!
+//! Every connection gets its own [`SystemVariables`](struct.SystemVariables.html), seeded from
+//! [`MysqlShim::system_variables`](trait.MysqlShim.html#method.system_variables) when the client
+//! connects. Queries that consist of nothing but a list of system variables are answered from it
+//! directly, without involving the shim.
+
+use crate::compat;
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::sql::{Assignment, SelectedVariable, SetValue};
+use crate::{Column, ErrorKind, QueryResultWriter, ToMysqlValue};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// The scope a system variable is read from or written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableScope {
+    /// The server-wide value, as read by `@@global.name`.
+    Global,
+    /// The value for the current connection, as read by `@@session.name` or `@@name`.
+    Session,
+}
+
+/// How `SET` statements that assign system variables are dealt with.
+///
+/// Only statements that assign plain literals, like `SET NAMES utf8mb4`, `SET autocommit = 0` or
+/// `SET @@session.time_zone = '+00:00'`, are ever applied by the server. Anything else, including
+/// assignments to user variables, always goes to the shim. When the server applies them, a
+/// statement that assigns to a variable that the session does not have fails with
+/// `ER_UNKNOWN_SYSTEM_VARIABLE`, without reaching the shim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetStatements {
+    /// Give them to the shim like any other query.
+    Forward,
+    /// Apply them to the session's [`SystemVariables`](struct.SystemVariables.html), and reply
+    /// with OK without involving the shim, unless one turns `autocommit` on while a transaction
+    /// is open: that one goes to [`on_commit`](trait.MysqlShim.html#method.on_commit) instead.
+    Apply,
+    /// Apply them to the session's [`SystemVariables`](struct.SystemVariables.html), and then
+    /// give them to the shim, which replies to the client.
+    ApplyAndForward,
+}
+
+/// The value of a system variable.
+///
+/// The variant determines the type of the column the value is sent to the client in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariableValue {
+    /// `NULL`.
+    Null,
+    /// A signed integer, sent as a `BIGINT`.
+    Int(i64),
+    /// An unsigned integer, sent as a `BIGINT UNSIGNED`.
+    UInt(u64),
+    /// A floating-point number, sent as a `DOUBLE`.
+    Double(f64),
+    /// A string, sent as a `VARCHAR`.
+    String(String),
+}
+
+impl VariableValue {
+    fn column(&self, name: &str) -> Column {
+        let (coltype, colflags) = match *self {
+            VariableValue::Null => (ColumnType::MYSQL_TYPE_NULL, ColumnFlags::empty()),
+            VariableValue::Int(_) => (ColumnType::MYSQL_TYPE_LONGLONG, ColumnFlags::empty()),
+            VariableValue::UInt(_) => (ColumnType::MYSQL_TYPE_LONGLONG, ColumnFlags::UNSIGNED_FLAG),
+            VariableValue::Double(_) => (ColumnType::MYSQL_TYPE_DOUBLE, ColumnFlags::empty()),
+            VariableValue::String(_) => (ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::empty()),
+        };
+        Column {
+            table: String::new(),
+            column: name.to_owned(),
+            coltype,
+            colflags,
+        }
+    }
+}
+
+impl ToMysqlValue for VariableValue {
+    fn to_mysql_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match *self {
+            VariableValue::Null => None::<u8>.to_mysql_text(w),
+            VariableValue::Int(n) => n.to_mysql_text(w),
+            VariableValue::UInt(n) => n.to_mysql_text(w),
+            VariableValue::Double(f) => f.to_mysql_text(w),
+            VariableValue::String(ref s) => s.to_mysql_text(w),
+        }
+    }
+
+    fn to_mysql_bin<W: Write>(&self, w: &mut W, c: &Column) -> io::Result<()> {
+        match *self {
+            VariableValue::Null => unreachable!(),
+            VariableValue::Int(n) => n.to_mysql_bin(w, c),
+            VariableValue::UInt(n) => n.to_mysql_bin(w, c),
+            VariableValue::Double(f) => f.to_mysql_bin(w, c),
+            VariableValue::String(ref s) => s.to_mysql_bin(w, c),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        *self == VariableValue::Null
+    }
+}
+
+impl From<bool> for VariableValue {
+    fn from(v: bool) -> Self {
+        VariableValue::Int(v.into())
+    }
+}
+
+impl From<i32> for VariableValue {
+    fn from(v: i32) -> Self {
+        VariableValue::Int(v.into())
+    }
+}
+
+impl From<i64> for VariableValue {
+    fn from(v: i64) -> Self {
+        VariableValue::Int(v)
+    }
+}
+
+impl From<u32> for VariableValue {
+    fn from(v: u32) -> Self {
+        VariableValue::UInt(v.into())
+    }
+}
+
+impl From<u64> for VariableValue {
+    fn from(v: u64) -> Self {
+        VariableValue::UInt(v)
+    }
+}
+
+impl From<f64> for VariableValue {
+    fn from(v: f64) -> Self {
+        VariableValue::Double(v)
+    }
+}
+
+impl<'a> From<&'a str> for VariableValue {
+    fn from(v: &'a str) -> Self {
+        VariableValue::String(v.to_owned())
+    }
+}
+
+impl From<String> for VariableValue {
+    fn from(v: String) -> Self {
+        VariableValue::String(v)
+    }
+}
+
+impl<T: Into<VariableValue>> From<Option<T>> for VariableValue {
+    fn from(v: Option<T>) -> Self {
+        v.map(Into::into).unwrap_or(VariableValue::Null)
+    }
+}
+
+/// A store of system variables, with a global and a session scope.
+///
+/// Variable names are case-insensitive. Reading a variable in the session scope falls back to its
+/// global value if it has not been given a session value of its own, which is also how a plain
+/// `@@name` is resolved.
+///
+/// The `Default` store holds the global values of the variables that MySQL drivers commonly
+/// read when they connect, and no session values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemVariables {
+    global: BTreeMap<String, VariableValue>,
+    session: BTreeMap<String, VariableValue>,
+}
+
+impl Default for SystemVariables {
+    fn default() -> Self {
+        let mut vars = SystemVariables::empty();
+        let defaults: &[(&str, VariableValue)] = &[
+            ("auto_increment_increment", 1u64.into()),
+            ("auto_increment_offset", 1u64.into()),
+            ("autocommit", true.into()),
+            ("character_set_client", "utf8".into()),
+            ("character_set_connection", "utf8".into()),
+            ("character_set_database", "utf8".into()),
+            ("character_set_results", "utf8".into()),
+            ("character_set_server", "utf8".into()),
+            ("collation_connection", "utf8_general_ci".into()),
+            ("collation_database", "utf8_general_ci".into()),
+            ("collation_server", "utf8_general_ci".into()),
+            ("init_connect", "".into()),
+            ("interactive_timeout", 28800u64.into()),
+            ("license", "GPL".into()),
+            ("lower_case_table_names", 0u64.into()),
+            ("max_allowed_packet", 67_108_864u64.into()),
+            ("net_buffer_length", 16384u64.into()),
+            ("net_write_timeout", 60u64.into()),
+            ("query_cache_size", 0u64.into()),
+            ("query_cache_type", "OFF".into()),
+            ("sql_auto_is_null", false.into()),
+            (
+                "sql_mode",
+                "STRICT_TRANS_TABLES,NO_ENGINE_SUBSTITUTION".into(),
+            ),
+            ("sql_select_limit", u64::MAX.into()),
+            ("socket", "".into()),
+            ("system_time_zone", "UTC".into()),
+            ("time_zone", "SYSTEM".into()),
+            ("transaction_isolation", "REPEATABLE-READ".into()),
+            ("transaction_read_only", false.into()),
+            ("tx_isolation", "REPEATABLE-READ".into()),
+            ("tx_read_only", false.into()),
+            ("version", "5.1.10-alpha-msql-proxy".into()),
+            ("version_comment", "msql-srv".into()),
+            ("wait_timeout", 28800u64.into()),
+        ];
+        for (name, value) in defaults {
+            vars.set(VariableScope::Global, name, value.clone());
+        }
+        vars
+    }
+}
+
+impl SystemVariables {
+    /// Create a store that has no variables at all.
+    pub fn empty() -> Self {
+        SystemVariables {
+            global: BTreeMap::new(),
+            session: BTreeMap::new(),
+        }
+    }
+
+    /// Look up the value of the variable `name` in the given scope.
+    pub fn get(&self, scope: VariableScope, name: &str) -> Option<&VariableValue> {
+        let name = name.to_ascii_lowercase();
+        match scope {
+            VariableScope::Global => self.global.get(&name),
+            VariableScope::Session => self.session.get(&name).or_else(|| self.global.get(&name)),
+        }
+    }
+
+    /// The names and values of all the variables in the given scope, ordered by name.
+    pub fn iter(&self, scope: VariableScope) -> impl Iterator<Item = (&str, &VariableValue)> {
+        let mut vars: BTreeMap<_, _> = self.global.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        if scope == VariableScope::Session {
+            vars.extend(self.session.iter().map(|(k, v)| (k.as_str(), v)));
+        }
+        vars.into_iter()
+    }
+
+    /// Set the value of the variable `name` in the given scope.
+    ///
+    /// Setting a global value does not change a session value that has already been set.
+    pub fn set<V: Into<VariableValue>>(&mut self, scope: VariableScope, name: &str, value: V) {
+        let vars = match scope {
+            VariableScope::Global => &mut self.global,
+            VariableScope::Session => &mut self.session,
+        };
+        vars.insert(name.to_ascii_lowercase(), value.into());
+    }
+
+    /// Whether `autocommit` is on for the session.
+    pub(crate) fn autocommit(&self) -> bool {
+        match self.get(VariableScope::Session, "autocommit") {
+            Some(VariableValue::Int(n)) => *n != 0,
+            Some(VariableValue::UInt(n)) => *n != 0,
+            Some(VariableValue::String(s)) => s.eq_ignore_ascii_case("ON"),
+            _ => false,
+        }
+    }
+
+    /// The name of the first variable that a `SET` statement assigns to but that does not exist.
+    pub(crate) fn unknown<'a>(&self, assignments: &[Assignment<'a>]) -> Option<&'a str> {
+        assignments.iter().find_map(|assignment| match *assignment {
+            Assignment::Variable { scope, name, .. } if self.get(scope, name).is_none() => {
+                Some(name)
+            }
+            _ => None,
+        })
+    }
+
+    /// Apply the assignments of a `SET` statement.
+    pub(crate) fn apply(&mut self, assignments: Vec<Assignment<'_>>) {
+        const NAMES: [&str; 3] = [
+            "character_set_client",
+            "character_set_connection",
+            "character_set_results",
+        ];
+
+        for assignment in assignments {
+            match assignment {
+                Assignment::Variable { scope, name, value } => self.assign(scope, name, value),
+                Assignment::Names(Some((charset, collation))) => {
+                    for &name in &NAMES {
+                        self.set(VariableScope::Session, name, charset);
+                    }
+                    let collation = collation
+                        .or_else(|| compat::default_collation(charset))
+                        .map(str::to_owned)
+                        .unwrap_or_else(|| format!("{}_general_ci", charset));
+                    self.set(VariableScope::Session, "collation_connection", collation);
+                }
+                Assignment::Names(None) => {
+                    for &name in NAMES.iter().chain(&["collation_connection"]) {
+                        self.assign(VariableScope::Session, name, SetValue::Default);
+                    }
+                }
+                Assignment::CharacterSet(charset) => {
+                    for &name in &["character_set_client", "character_set_results"] {
+                        let value = charset.map_or(SetValue::Default, SetValue::Word);
+                        self.assign(VariableScope::Session, name, value);
+                    }
+                    // the connection goes back to the character set of the database
+                    for &(name, from) in &[
+                        ("character_set_connection", "character_set_database"),
+                        ("collation_connection", "collation_database"),
+                    ] {
+                        match self.get(VariableScope::Session, from).cloned() {
+                            Some(value) => self.set(VariableScope::Session, name, value),
+                            None => self.assign(VariableScope::Session, name, SetValue::Default),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Assign a literal to the variable `name`, converting it to the type of the variable's
+    /// current value where that makes sense.
+    fn assign(&mut self, scope: VariableScope, name: &str, value: SetValue<'_>) {
+        let current = self.get(scope, name);
+        let numeric = match current {
+            Some(VariableValue::Int(_)) => Some(false),
+            Some(VariableValue::UInt(_)) => Some(true),
+            _ => None,
+        };
+        let value = match value {
+            SetValue::Default => {
+                // a session value goes back to the global one
+                if scope == VariableScope::Session {
+                    self.session.remove(&name.to_ascii_lowercase());
+                }
+                return;
+            }
+            SetValue::Null => VariableValue::Null,
+            SetValue::Number(n) => match (numeric, n.parse::<u64>(), n.parse::<i64>()) {
+                (Some(true), Ok(n), _) | (None, Ok(n), _) => VariableValue::UInt(n),
+                (_, _, Ok(n)) => VariableValue::Int(n),
+                _ => n
+                    .parse()
+                    .map(VariableValue::Double)
+                    .unwrap_or_else(|_| n.into()),
+            },
+            SetValue::String(s) => s.into(),
+            SetValue::Word(s) => s.into(),
+        };
+
+        // ON and OFF, as well as TRUE and FALSE, are numbers for variables that hold numbers
+        let value = match (numeric, value) {
+            (Some(unsigned), VariableValue::String(s)) => {
+                let flag = if s.eq_ignore_ascii_case("ON") || s.eq_ignore_ascii_case("TRUE") {
+                    Some(1)
+                } else if s.eq_ignore_ascii_case("OFF") || s.eq_ignore_ascii_case("FALSE") {
+                    Some(0)
+                } else {
+                    None
+                };
+                match flag {
+                    Some(n) if unsigned => VariableValue::UInt(n as u64),
+                    Some(n) => VariableValue::Int(n),
+                    None => VariableValue::String(s),
+                }
+            }
+            (_, value) => value,
+        };
+        self.set(scope, name, value);
+    }
+
+    /// Look up the values of the variables in a `SELECT @@...` list, or return the name of the
+    /// first one that does not exist.
+    pub(crate) fn select<'a>(
+        &self,
+        vars: &[SelectedVariable<'a>],
+    ) -> Result<Vec<(&'a str, VariableValue)>, &'a str> {
+        vars.iter()
+            .map(|var| {
+                self.get(var.scope.unwrap_or(VariableScope::Session), var.name)
+                    .map(|value| (var.column, value.clone()))
+                    .ok_or(var.name)
+            })
+            .collect()
+    }
+}
+
+/// Answer a `SELECT @@...` query with a single row that holds the selected values (unless `row`
+/// is false), or with an error if one of the variables does not exist.
+pub(crate) fn write_selected<W: Write>(
+    selected: Result<Vec<(&str, VariableValue)>, &str>,
+    row: bool,
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    let selected = match selected {
+        Ok(selected) => selected,
+        Err(name) => return write_unknown(name, results),
+    };
+
+    let cols: Vec<_> = selected
+        .iter()
+        .map(|(name, value)| value.column(name))
+        .collect();
+    let mut w = results.start(&cols)?;
+    if row {
+        w.write_row(selected.into_iter().map(|(_, value)| value))?;
+    }
+    w.finish()
+}
+
+/// Reply to a statement that refers to the system variable `name`, which does not exist.
+pub(crate) fn write_unknown<W: Write>(
+    name: &str,
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    results.error(
+        ErrorKind::ER_UNKNOWN_SYSTEM_VARIABLE,
+        format!("Unknown system variable '{}'", name).as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_falls_back_to_global() {
+        let mut vars = SystemVariables::default();
+        assert_eq!(
+            vars.get(VariableScope::Session, "AUTOCOMMIT"),
+            Some(&VariableValue::Int(1))
+        );
+
+        vars.set(VariableScope::Session, "autocommit", false);
+        vars.set(VariableScope::Global, "Autocommit", 1);
+        assert_eq!(
+            vars.get(VariableScope::Session, "autocommit"),
+            Some(&VariableValue::Int(0))
+        );
+        assert_eq!(
+            vars.get(VariableScope::Global, "autocommit"),
+            Some(&VariableValue::Int(1))
+        );
+        assert_eq!(vars.get(VariableScope::Session, "no_such_variable"), None);
+    }
+
+    #[test]
+    fn empty_has_no_variables() {
+        let vars = SystemVariables::empty();
+        assert_eq!(vars.get(VariableScope::Global, "version"), None);
+    }
+
+    #[test]
+    fn it_applies_assignments() {
+        let mut vars = SystemVariables::default();
+        vars.apply(vec![
+            Assignment::Variable {
+                scope: VariableScope::Session,
+                name: "autocommit",
+                value: SetValue::Word("off"),
+            },
+            Assignment::Variable {
+                scope: VariableScope::Global,
+                name: "wait_timeout",
+                value: SetValue::Number("60"),
+            },
+            Assignment::Variable {
+                scope: VariableScope::Session,
+                name: "query_cache_type",
+                value: SetValue::Word("ON"),
+            },
+            Assignment::Variable {
+                scope: VariableScope::Session,
+                name: "my_setting",
+                value: SetValue::Number("-1"),
+            },
+            Assignment::Names(Some(("latin1", None))),
+        ]);
+        fn get(vars: &SystemVariables, name: &str) -> VariableValue {
+            vars.get(VariableScope::Session, name).cloned().unwrap()
+        }
+        assert_eq!(get(&vars, "autocommit"), VariableValue::Int(0));
+        assert_eq!(get(&vars, "wait_timeout"), VariableValue::UInt(60));
+        assert_eq!(get(&vars, "query_cache_type"), "ON".into());
+        assert_eq!(get(&vars, "my_setting"), VariableValue::Int(-1));
+        assert_eq!(get(&vars, "character_set_results"), "latin1".into());
+        assert_eq!(
+            get(&vars, "collation_connection"),
+            "latin1_swedish_ci".into()
+        );
+
+        vars.apply(vec![
+            Assignment::Variable {
+                scope: VariableScope::Session,
+                name: "autocommit",
+                value: SetValue::Default,
+            },
+            Assignment::Names(None),
+        ]);
+        assert_eq!(get(&vars, "autocommit"), VariableValue::Int(1));
+        assert_eq!(get(&vars, "character_set_results"), "utf8".into());
+    }
+}
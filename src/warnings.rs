@@ -0,0 +1,80 @@
+//! Warnings that statements leave behind, for `SHOW WARNINGS` to list.
+
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::sql::ShowWarnings;
+use crate::{Column, ErrorKind, QueryResultWriter};
+use std::io::{self, Write};
+
+/// How serious a condition reported with
+/// [`QueryResultWriter::add_warning`](struct.QueryResultWriter.html#method.add_warning) is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningLevel {
+    /// Something the client may want to know about, but that is usually harmless.
+    Note,
+    /// Something that probably did not go the way the client intended.
+    Warning,
+    /// An error.
+    Error,
+}
+
+impl WarningLevel {
+    fn name(self) -> &'static str {
+        match self {
+            WarningLevel::Note => "Note",
+            WarningLevel::Warning => "Warning",
+            WarningLevel::Error => "Error",
+        }
+    }
+}
+
+/// A note, warning or error raised by the last statement.
+#[derive(Debug, Clone)]
+pub(crate) struct Warning {
+    pub(crate) level: WarningLevel,
+    pub(crate) kind: ErrorKind,
+    pub(crate) message: String,
+}
+
+/// Answer a `SHOW WARNINGS` or `SHOW ERRORS` statement with the given `warnings`.
+pub(crate) fn write_warnings<W: Write>(
+    show: ShowWarnings,
+    warnings: &[Warning],
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    let column = |name: &str, coltype| Column {
+        table: String::new(),
+        column: name.to_owned(),
+        coltype,
+        colflags: ColumnFlags::empty(),
+    };
+    let shown = warnings
+        .iter()
+        .filter(|w| !show.errors || w.level == WarningLevel::Error);
+
+    if show.count {
+        let name = if show.errors {
+            "@@session.error_count"
+        } else {
+            "@@session.warning_count"
+        };
+        let cols = [column(name, ColumnType::MYSQL_TYPE_LONGLONG)];
+        let mut w = results.start(&cols)?;
+        w.write_row([shown.count() as u64])?;
+        return w.finish();
+    }
+
+    let cols = [
+        column("Level", ColumnType::MYSQL_TYPE_VAR_STRING),
+        column("Code", ColumnType::MYSQL_TYPE_LONG),
+        column("Message", ColumnType::MYSQL_TYPE_VAR_STRING),
+    ];
+    let (offset, limit) = show.limit;
+    let mut w = results.start(&cols)?;
+    for warning in shown.skip(offset as usize).take(limit as usize) {
+        w.write_col(warning.level.name())?;
+        w.write_col(warning.kind as u16 as u32)?;
+        w.write_col(warning.message.as_str())?;
+        w.end_row()?;
+    }
+    w.finish()
+}
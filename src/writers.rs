@@ -1,16 +1,23 @@
+use crate::commands::CLIENT_QUERY_ATTRIBUTES;
 use crate::myc::constants::StatusFlags;
 use crate::myc::io::WriteMysqlExt;
 use crate::packet::PacketWriter;
-use crate::{Column, ErrorKind};
+use crate::{Column, ColumnDefinition, ErrorKind};
 use byteorder::{LittleEndian, WriteBytesExt};
 use std::io::{self, Write};
 use myc::constants::{UTF8_GENERAL_CI, CapabilityFlags};
 
+/// MariaDB's extended capability flag for `COM_STMT_BULK_EXECUTE`. MariaDB servers send their
+/// extended capabilities in the last four bytes of the handshake filler.
+pub(crate) const MARIADB_CLIENT_STMT_BULK_OPERATIONS: u32 = 1 << 2;
+
 pub(crate) fn write_eof_packet<W: Write>(
     w: &mut PacketWriter<W>,
     s: StatusFlags,
+    warnings: u16,
 ) -> io::Result<()> {
-    w.write_all(&[0xFE, 0x00, 0x00])?;
+    w.write_u8(0xFE)?;
+    w.write_u16::<LittleEndian>(warnings)?;
     w.write_u16::<LittleEndian>(s.bits())?;
     w.end_packet()
 }
@@ -20,12 +27,16 @@ pub(crate) fn write_ok_packet<W: Write>(
     rows: u64,
     last_insert_id: u64,
     s: StatusFlags,
+    warnings: u16,
+    info: &[u8],
 ) -> io::Result<()> {
     w.write_u8(0x00)?; // OK packet type
     w.write_lenenc_int(rows)?;
     w.write_lenenc_int(last_insert_id)?;
     w.write_u16::<LittleEndian>(s.bits())?;
-    w.write_all(&[0x00, 0x00])?; // no warnings
+    w.write_u16::<LittleEndian>(warnings)?;
+    // without CLIENT_SESSION_TRACK, the info is the rest of the packet
+    w.write_all(info)?;
     w.end_packet()
 }
 
@@ -45,6 +56,7 @@ pub(crate) fn write_auth_switch_packet<W: Write>(
 pub(crate) fn write_handshake_packet<W: Write>(
     w: &mut PacketWriter<W>,
     connection_id: u32,
+    extra_capabilities: CapabilityFlags,
     auth_plugin: &[u8],
     nonce: &[u8],
 ) -> io::Result<()> {
@@ -53,18 +65,24 @@ pub(crate) fn write_handshake_packet<W: Write>(
     // 5.1.10 because that's what Ruby's ActiveRecord requires
     w.write_all(&b"5.1.10-alpha-msql-proxy\0"[..])?;
 
-    let capabilities = CapabilityFlags::CLIENT_PROTOCOL_41 | CapabilityFlags::CLIENT_PLUGIN_AUTH | CapabilityFlags::CLIENT_SECURE_CONNECTION | CapabilityFlags::CLIENT_CONNECT_WITH_DB;
+    let capabilities = CapabilityFlags::CLIENT_PROTOCOL_41
+        | CapabilityFlags::CLIENT_PLUGIN_AUTH
+        | CapabilityFlags::CLIENT_SECURE_CONNECTION
+        | CapabilityFlags::CLIENT_CONNECT_WITH_DB
+        | CapabilityFlags::CLIENT_LOCAL_FILES
+        | extra_capabilities;
+    let capabilities = capabilities.bits() | CLIENT_QUERY_ATTRIBUTES;
 
     w.write_u32::<LittleEndian>(connection_id)?;
     w.write_all(&nonce[0..8])?;
     w.write_u8(0)?;
-    w.write_u16::<LittleEndian>(capabilities.bits() as u16)?;
+    w.write_u16::<LittleEndian>(capabilities as u16)?;
     w.write_u8(UTF8_GENERAL_CI as u8)?; // UTF8_GENERAL_CI
     w.write_u16::<LittleEndian>(0)?; // status flags
-    w.write_u16::<LittleEndian>((capabilities.bits() >> 16) as u16)?; // extended capabilities
+    w.write_u16::<LittleEndian>((capabilities >> 16) as u16)?; // extended capabilities
     w.write_u8(nonce.len() as u8 + 1)?; // scramble length
     w.write_all(&[0x00; 6][..])?; // filler
-    w.write_all(&[0x00; 4][..])?; // filler
+    w.write_u32::<LittleEndian>(MARIADB_CLIENT_STMT_BULK_OPERATIONS)?; // MariaDB capabilities
     w.write_all(&nonce[8..])?; // 4.1+ servers must extend salt
     w.write_u8(0)?;
     w.write_all(auth_plugin)?;
@@ -81,17 +99,42 @@ pub fn write_err<W: Write>(err: ErrorKind, msg: &[u8], w: &mut PacketWriter<W>)
     w.end_packet()
 }
 
-use std::borrow::Borrow;
+/// Something that describes a column to the client: either a plain
+/// [`Column`](struct.Column.html) or its full [`ColumnDefinition`](struct.ColumnDefinition.html).
+pub(crate) trait Describe {
+    fn column(&self) -> &Column;
+    fn definition(&self) -> Option<&ColumnDefinition>;
+}
+
+impl Describe for Column {
+    fn column(&self) -> &Column {
+        self
+    }
+    fn definition(&self) -> Option<&ColumnDefinition> {
+        None
+    }
+}
 
-pub(crate) fn write_prepare_ok<'a, PI, CI, W>(
+impl Describe for ColumnDefinition {
+    fn column(&self) -> &Column {
+        &self.column
+    }
+    fn definition(&self) -> Option<&ColumnDefinition> {
+        Some(self)
+    }
+}
+
+pub(crate) fn write_prepare_ok<'a, P, C, PI, CI, W>(
     id: u32,
     params: PI,
     columns: CI,
     w: &mut PacketWriter<W>,
 ) -> io::Result<()>
 where
-    PI: IntoIterator<Item = &'a Column>,
-    CI: IntoIterator<Item = &'a Column>,
+    P: Describe + 'a,
+    C: Describe + 'a,
+    PI: IntoIterator<Item = &'a P>,
+    CI: IntoIterator<Item = &'a C>,
     <PI as IntoIterator>::IntoIter: ExactSizeIterator,
     <CI as IntoIterator>::IntoIter: ExactSizeIterator,
     W: Write,
@@ -112,31 +155,54 @@ where
     write_column_definitions(ci, w, true)
 }
 
-pub(crate) fn write_column_definitions<'a, I, W>(
+fn write_column_definition<D, W>(d: &D, w: &mut PacketWriter<W>) -> io::Result<()>
+where
+    D: Describe + ?Sized,
+    W: Write,
+{
+    let c = d.column();
+    let def = d.definition();
+    w.write_lenenc_str(b"def")?;
+    w.write_lenenc_str(def.map_or("", |d| d.schema.as_str()).as_bytes())?;
+    w.write_lenenc_str(c.table.as_bytes())?;
+    w.write_lenenc_str(def.map_or("", |d| d.org_table.as_str()).as_bytes())?;
+    w.write_lenenc_str(c.column.as_bytes())?;
+    w.write_lenenc_str(def.map_or("", |d| d.org_name.as_str()).as_bytes())?;
+    w.write_lenenc_int(0xC)?;
+    w.write_u16::<LittleEndian>(def.map_or(UTF8_GENERAL_CI, |d| d.character_set))?;
+    w.write_u32::<LittleEndian>(def.map_or(1024, |d| d.column_length))?;
+    w.write_u8(c.coltype as u8)?;
+    w.write_u16::<LittleEndian>(c.colflags.bits())?;
+    w.write_u8(def.map_or(0, |d| d.decimals))?;
+    w.write_all(&[0x00, 0x00]) // unused
+}
+
+/// Reply to a `COM_FIELD_LIST`, whose column definitions also carry the columns' default values.
+pub(crate) fn write_field_list<W: Write>(
+    cols: &[Column],
+    w: &mut PacketWriter<W>,
+) -> io::Result<()> {
+    for c in cols {
+        write_column_definition(c, w)?;
+        w.write_u8(0xFB)?; // no default value (NULL)
+        w.end_packet()?;
+    }
+    write_eof_packet(w, StatusFlags::empty(), 0)
+}
+
+pub(crate) fn write_column_definitions<'a, D, I, W>(
     i: I,
     w: &mut PacketWriter<W>,
     only_eof_on_nonempty: bool,
 ) -> io::Result<()>
 where
-    I: IntoIterator<Item = &'a Column>,
+    D: Describe + 'a,
+    I: IntoIterator<Item = &'a D>,
     W: Write,
 {
     let mut empty = true;
     for c in i {
-        let c = c.borrow();
-        w.write_lenenc_str(b"def")?;
-        w.write_lenenc_str(b"")?;
-        w.write_lenenc_str(c.table.as_bytes())?;
-        w.write_lenenc_str(b"")?;
-        w.write_lenenc_str(c.column.as_bytes())?;
-        w.write_lenenc_str(b"")?;
-        w.write_lenenc_int(0xC)?;
-        w.write_u16::<LittleEndian>(UTF8_GENERAL_CI)?;
-        w.write_u32::<LittleEndian>(1024)?;
-        w.write_u8(c.coltype as u8)?;
-        w.write_u16::<LittleEndian>(c.colflags.bits())?;
-        w.write_all(&[0x00])?; // decimals
-        w.write_all(&[0x00, 0x00])?; // unused
+        write_column_definition(c, w)?;
         w.end_packet()?;
         empty = false;
     }
@@ -144,13 +210,14 @@ where
     if empty && only_eof_on_nonempty {
         Ok(())
     } else {
-        write_eof_packet(w, StatusFlags::empty())
+        write_eof_packet(w, StatusFlags::empty(), 0)
     }
 }
 
-pub(crate) fn column_definitions<'a, I, W>(i: I, w: &mut PacketWriter<W>) -> io::Result<()>
+pub(crate) fn column_definitions<'a, D, I, W>(i: I, w: &mut PacketWriter<W>) -> io::Result<()>
 where
-    I: IntoIterator<Item = &'a Column>,
+    D: Describe + 'a,
+    I: IntoIterator<Item = &'a D>,
     <I as IntoIterator>::IntoIter: ExactSizeIterator,
     W: Write,
 {
@@ -0,0 +1,392 @@
+//! XA transactions, for shims that take part in distributed transactions as a resource manager.
+//!
+//! When a shim has an [`XaHandler`](trait.XaHandler.html), the `XA` statements that a transaction
+//! manager sends are parsed, checked against the state of the connection's transaction branch the
+//! way MySQL does, and handed to the handler.
+
+use crate::myc::constants::{ColumnFlags, ColumnType};
+use crate::sql::XaStatement;
+use crate::{Column, ConnectionState, ErrorKind, QueryResultWriter};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// The identifier of an XA transaction branch.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Xid {
+    /// The global transaction identifier.
+    pub gtrid: Vec<u8>,
+    /// The branch qualifier, which is empty if the client did not give one.
+    pub bqual: Vec<u8>,
+    /// The format of `gtrid` and `bqual`, which is 1 if the client did not give one.
+    pub format_id: i64,
+}
+
+/// Carries out the `XA` statements that a transaction manager sends.
+///
+/// Each method returns an error such as `ErrorKind::ER_XAER_NOTA` if the operation fails, and
+/// the client is sent MySQL's message for that error. The server keeps track of the transaction
+/// branch that each connection is working on, and rejects statements that do not fit its state
+/// (such as `XA PREPARE` for a branch that has not ended) before they reach the handler.
+///
+/// The statements in between `XA START` and `XA END` are given to the shim as usual.
+///
+/// See [`MysqlShim::xa_handler`](trait.MysqlShim.html#method.xa_handler).
+pub trait XaHandler {
+    /// Start work on a new transaction branch (`XA START`).
+    fn start(&mut self, xid: &Xid) -> Result<(), ErrorKind>;
+
+    /// End work on the transaction branch (`XA END`). By default, nothing happens.
+    fn end(&mut self, _xid: &Xid) -> Result<(), ErrorKind> {
+        Ok(())
+    }
+
+    /// Prepare the transaction branch to be committed (`XA PREPARE`).
+    fn prepare(&mut self, xid: &Xid) -> Result<(), ErrorKind>;
+
+    /// Commit the transaction branch (`XA COMMIT`).
+    ///
+    /// The branch has been prepared, possibly on another connection, unless `one_phase` is set,
+    /// in which case it is committed straight after `XA END`.
+    fn commit(&mut self, xid: &Xid, one_phase: bool) -> Result<(), ErrorKind>;
+
+    /// Roll back the transaction branch (`XA ROLLBACK`), which may have been prepared on another
+    /// connection.
+    fn rollback(&mut self, xid: &Xid) -> Result<(), ErrorKind>;
+
+    /// The transaction branches that have been prepared, but not yet committed or rolled back
+    /// (`XA RECOVER`).
+    fn recover(&mut self) -> Vec<Xid>;
+}
+
+/// The state of the transaction branch that a connection is working on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum XaState {
+    /// Between `XA START` and `XA END`.
+    Active,
+    /// Between `XA END` and `XA PREPARE`, `XA COMMIT` or `XA ROLLBACK`.
+    Idle,
+    /// Between `XA PREPARE` and `XA COMMIT` or `XA ROLLBACK`.
+    Prepared,
+}
+
+impl XaState {
+    fn name(self) -> &'static str {
+        match self {
+            XaState::Active => "ACTIVE",
+            XaState::Idle => "IDLE",
+            XaState::Prepared => "PREPARED",
+        }
+    }
+}
+
+/// What an `XA` statement results in.
+#[derive(Debug)]
+pub(crate) enum Outcome {
+    /// The statement succeeded.
+    Done,
+    /// The transaction branches listed by `XA RECOVER`, and whether they should be shown in
+    /// hexadecimal.
+    Recovered(Vec<Xid>, bool),
+    /// The statement failed.
+    Failed(ErrorKind, String),
+}
+
+/// Carry out `statement` with `handler`, and update the connection's transaction branch.
+pub(crate) fn execute(
+    statement: XaStatement,
+    handler: &mut dyn XaHandler,
+    state: &mut ConnectionState,
+) -> Outcome {
+    let current = state.xa.as_ref().map(|(xid, s)| (xid, *s));
+    let (result, next) = match statement {
+        XaStatement::Start(xid) => {
+            if let Some((_, s)) = current {
+                return rmfail(Some(s));
+            }
+            if state.in_transaction {
+                return failed(ErrorKind::ER_XAER_OUTSIDE, None);
+            }
+            (handler.start(&xid), Some((xid, XaState::Active)))
+        }
+        XaStatement::End(xid) => {
+            match current {
+                Some((current, XaState::Active)) if *current == xid => {}
+                Some((current, _)) if *current != xid => return nota(),
+                other => return rmfail(other.map(|(_, s)| s)),
+            }
+            (handler.end(&xid), Some((xid, XaState::Idle)))
+        }
+        XaStatement::Prepare(xid) => {
+            match current {
+                Some((current, XaState::Idle)) if *current == xid => {}
+                Some((current, _)) if *current != xid => return nota(),
+                other => return rmfail(other.map(|(_, s)| s)),
+            }
+            (handler.prepare(&xid), Some((xid, XaState::Prepared)))
+        }
+        XaStatement::Commit(xid, one_phase) => {
+            let expected = if one_phase {
+                XaState::Idle
+            } else {
+                XaState::Prepared
+            };
+            match current {
+                Some((current, s)) if *current == xid && s == expected => {}
+                Some((current, _)) if *current != xid => return nota(),
+                // a branch prepared on another connection can be committed from here
+                None if !one_phase => {}
+                None => return nota(),
+                other => return rmfail(other.map(|(_, s)| s)),
+            }
+            (handler.commit(&xid, one_phase), None)
+        }
+        XaStatement::Rollback(xid) => {
+            match current {
+                Some((current, XaState::Active)) if *current == xid => {
+                    return rmfail(Some(XaState::Active))
+                }
+                Some((current, _)) if *current != xid => return nota(),
+                _ => {}
+            }
+            (handler.rollback(&xid), None)
+        }
+        XaStatement::Recover(convert) => return Outcome::Recovered(handler.recover(), convert),
+    };
+
+    match result {
+        Ok(()) => {
+            state.in_transaction = next.is_some();
+            state.xa = next;
+            Outcome::Done
+        }
+        Err(kind) => failed(kind, current.map(|(_, s)| s)),
+    }
+}
+
+fn nota() -> Outcome {
+    failed(ErrorKind::ER_XAER_NOTA, None)
+}
+
+fn rmfail(state: Option<XaState>) -> Outcome {
+    failed(ErrorKind::ER_XAER_RMFAIL, state)
+}
+
+/// An error of the given kind, with MySQL's message for it. `state` is the state of the
+/// connection's transaction branch, if it has one.
+fn failed(kind: ErrorKind, state: Option<XaState>) -> Outcome {
+    let message = match kind {
+        ErrorKind::ER_XAER_NOTA => "XAER_NOTA: Unknown XID".to_owned(),
+        ErrorKind::ER_XAER_INVAL => {
+            "XAER_INVAL: Invalid arguments (or unsupported command)".to_owned()
+        }
+        ErrorKind::ER_XAER_RMFAIL => format!(
+            "XAER_RMFAIL: The command cannot be executed when global transaction is in the {} \
+             state",
+            state.map_or("NON-EXISTING", XaState::name)
+        ),
+        ErrorKind::ER_XAER_OUTSIDE => {
+            "XAER_OUTSIDE: Some work is done outside global transaction".to_owned()
+        }
+        ErrorKind::ER_XAER_RMERR => "XAER_RMERR: Fatal error occurred in the transaction branch \
+                                     - check your data for consistency"
+            .to_owned(),
+        ErrorKind::ER_XA_RBROLLBACK => {
+            "XA_RBROLLBACK: Transaction branch was rolled back".to_owned()
+        }
+        ErrorKind::ER_XAER_DUPID => "XAER_DUPID: The XID already exists".to_owned(),
+        ErrorKind::ER_XA_RBTIMEOUT => {
+            "XA_RBTIMEOUT: Transaction branch was rolled back: took too long".to_owned()
+        }
+        ErrorKind::ER_XA_RBDEADLOCK => {
+            "XA_RBDEADLOCK: Transaction branch was rolled back: deadlock was detected".to_owned()
+        }
+        _ => "XA command failed".to_owned(),
+    };
+    Outcome::Failed(kind, message)
+}
+
+/// Reply to an `XA` statement with its outcome.
+pub(crate) fn write_outcome<W: Write>(
+    outcome: Outcome,
+    results: QueryResultWriter<'_, W>,
+) -> io::Result<()> {
+    let (xids, convert) = match outcome {
+        Outcome::Done => return results.completed(0, 0),
+        Outcome::Failed(kind, message) => return results.error(kind, message.as_bytes()),
+        Outcome::Recovered(xids, convert) => (xids, convert),
+    };
+
+    let column = |name: &str, coltype| Column {
+        table: String::new(),
+        column: name.to_owned(),
+        coltype,
+        colflags: ColumnFlags::empty(),
+    };
+    let cols = [
+        column("formatID", ColumnType::MYSQL_TYPE_LONGLONG),
+        column("gtrid_length", ColumnType::MYSQL_TYPE_LONGLONG),
+        column("bqual_length", ColumnType::MYSQL_TYPE_LONGLONG),
+        column("data", ColumnType::MYSQL_TYPE_VAR_STRING),
+    ];
+    let mut w = results.start(&cols)?;
+    for xid in xids {
+        let mut data = xid.gtrid.clone();
+        data.extend_from_slice(&xid.bqual);
+        w.write_col(xid.format_id)?;
+        w.write_col(xid.gtrid.len() as i64)?;
+        w.write_col(xid.bqual.len() as i64)?;
+        if convert {
+            let mut hex = String::from("0x");
+            for byte in &data {
+                write!(hex, "{:02X}", byte).unwrap();
+            }
+            w.write_col(hex.as_str())?;
+        } else {
+            w.write_col(&data[..])?;
+        }
+        w.end_row()?;
+    }
+    w.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestHandler {
+        calls: Vec<String>,
+        prepared: Vec<Xid>,
+    }
+
+    impl XaHandler for TestHandler {
+        fn start(&mut self, _: &Xid) -> Result<(), ErrorKind> {
+            self.calls.push("start".to_owned());
+            Ok(())
+        }
+        fn prepare(&mut self, xid: &Xid) -> Result<(), ErrorKind> {
+            self.calls.push("prepare".to_owned());
+            self.prepared.push(xid.clone());
+            Ok(())
+        }
+        fn commit(&mut self, xid: &Xid, one_phase: bool) -> Result<(), ErrorKind> {
+            self.calls.push(format!("commit {}", one_phase));
+            match self.prepared.iter().position(|p| p == xid) {
+                Some(i) => {
+                    self.prepared.remove(i);
+                    Ok(())
+                }
+                None if one_phase => Ok(()),
+                None => Err(ErrorKind::ER_XAER_NOTA),
+            }
+        }
+        fn rollback(&mut self, _: &Xid) -> Result<(), ErrorKind> {
+            self.calls.push("rollback".to_owned());
+            Ok(())
+        }
+        fn recover(&mut self) -> Vec<Xid> {
+            self.prepared.clone()
+        }
+    }
+
+    fn xid(gtrid: &str) -> Xid {
+        Xid {
+            gtrid: gtrid.as_bytes().to_vec(),
+            bqual: Vec::new(),
+            format_id: 1,
+        }
+    }
+
+    fn error(outcome: Outcome) -> (ErrorKind, String) {
+        match outcome {
+            Outcome::Failed(kind, message) => (kind, message),
+            outcome => panic!("expected an error, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn it_follows_the_branch_through_its_states() {
+        let mut handler = TestHandler::default();
+        let mut state = ConnectionState::default();
+        let mut execute = |statement| execute(statement, &mut handler, &mut state);
+
+        assert!(matches!(
+            execute(XaStatement::Start(xid("a"))),
+            Outcome::Done
+        ));
+        let (kind, message) = error(execute(XaStatement::Prepare(xid("a"))));
+        assert_eq!(kind, ErrorKind::ER_XAER_RMFAIL);
+        assert!(message.contains("ACTIVE state"));
+        assert_eq!(
+            error(execute(XaStatement::End(xid("b")))).0,
+            ErrorKind::ER_XAER_NOTA
+        );
+        assert!(matches!(execute(XaStatement::End(xid("a"))), Outcome::Done));
+        assert!(matches!(
+            execute(XaStatement::Prepare(xid("a"))),
+            Outcome::Done
+        ));
+        assert_eq!(
+            error(execute(XaStatement::Start(xid("b")))).0,
+            ErrorKind::ER_XAER_RMFAIL
+        );
+        assert!(matches!(
+            execute(XaStatement::Commit(xid("a"), false)),
+            Outcome::Done
+        ));
+
+        assert_eq!(handler.calls, vec!["start", "prepare", "commit false"]);
+        assert_eq!(state.xa, None);
+        assert!(!state.in_transaction);
+    }
+
+    #[test]
+    fn it_commits_branches_prepared_elsewhere() {
+        let mut handler = TestHandler::default();
+        handler.prepared.push(xid("a"));
+        let mut state = ConnectionState::default();
+
+        match execute(XaStatement::Recover(false), &mut handler, &mut state) {
+            Outcome::Recovered(xids, false) => assert_eq!(xids, vec![xid("a")]),
+            outcome => panic!("expected recovered branches, got {:?}", outcome),
+        }
+        let outcome = execute(
+            XaStatement::Commit(xid("a"), false),
+            &mut handler,
+            &mut state,
+        );
+        assert!(matches!(outcome, Outcome::Done));
+        let outcome = execute(
+            XaStatement::Commit(xid("a"), false),
+            &mut handler,
+            &mut state,
+        );
+        assert_eq!(error(outcome).0, ErrorKind::ER_XAER_NOTA);
+        let outcome = execute(
+            XaStatement::Commit(xid("a"), true),
+            &mut handler,
+            &mut state,
+        );
+        assert_eq!(error(outcome).0, ErrorKind::ER_XAER_NOTA);
+        let (kind, message) = error(execute(
+            XaStatement::End(xid("a")),
+            &mut handler,
+            &mut state,
+        ));
+        assert_eq!(kind, ErrorKind::ER_XAER_RMFAIL);
+        assert!(message.contains("NON-EXISTING state"));
+    }
+
+    #[test]
+    fn it_does_not_start_branches_inside_transactions() {
+        let mut handler = TestHandler::default();
+        let mut state = ConnectionState {
+            in_transaction: true,
+            ..ConnectionState::default()
+        };
+
+        let outcome = execute(XaStatement::Start(xid("a")), &mut handler, &mut state);
+        assert_eq!(error(outcome).0, ErrorKind::ER_XAER_OUTSIDE);
+        assert!(handler.calls.is_empty());
+    }
+}
@@ -6,12 +6,18 @@ extern crate nom;
 
 use mysql::prelude::*;
 use std::io;
+use std::io::prelude::*;
 use std::net;
+use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
 
 use msql_srv::{
-    Column, ErrorKind, InitWriter, MysqlIntermediary, MysqlShim, ParamParser, QueryResultWriter,
-    StatementMetaWriter,
+    Catalog, Column, ColumnDefinition, Completion, DisconnectReason, EmulatedShim,
+    EmulatedStatements, ErrorKind, InitWriter, MysqlIntermediary, MysqlShim, ParamParser,
+    PingWriter, QueryResultWriter, SetStatements, StatementMetaWriter, StatusFlags,
+    SystemVariables, TransactionOptions, ValueInner, VariableScope, VariableValue, WarningLevel,
+    XaHandler, Xid,
 };
 
 struct TestingShim<Q, P, E, I> {
@@ -21,6 +27,16 @@ struct TestingShim<Q, P, E, I> {
     on_p: P,
     on_e: E,
     on_i: I,
+    multi_statements: bool,
+    backend_down: bool,
+    closed: Vec<u32>,
+    disconnected: Option<mpsc::Sender<(Vec<u32>, DisconnectReason)>>,
+    variables: SystemVariables,
+    set_statements: SetStatements,
+    driver_compatibility: bool,
+    emulated: Option<EmulatedStatements>,
+    catalog: Option<Box<dyn Catalog + Send>>,
+    xa: Option<Box<dyn XaHandler + Send>>,
 }
 
 impl<Q, P, E, I> MysqlShim<net::TcpStream> for TestingShim<Q, P, E, I>
@@ -37,6 +53,9 @@ where
         query: &str,
         info: StatementMetaWriter<net::TcpStream>,
     ) -> io::Result<()> {
+        if let Some(ref mut emulated) = self.emulated {
+            return emulated.prepare(query, info);
+        }
         let id = (self.on_p)(query);
         info.reply(id, &self.params, &self.columns)
     }
@@ -47,10 +66,25 @@ where
         params: ParamParser,
         results: QueryResultWriter<net::TcpStream>,
     ) -> io::Result<()> {
+        if let Some(ref emulated) = self.emulated {
+            let query = emulated.query(id, params).unwrap();
+            return (self.on_q)(&query, results);
+        }
         (self.on_e)(id, params.into_iter().collect(), results)
     }
 
-    fn on_close(&mut self, _: u32) {}
+    fn on_close(&mut self, stmt: u32) {
+        if let Some(ref mut emulated) = self.emulated {
+            emulated.close(stmt);
+        }
+        self.closed.push(stmt);
+    }
+
+    fn on_disconnect(&mut self, reason: DisconnectReason) {
+        if let Some(disconnected) = self.disconnected.take() {
+            disconnected.send((self.closed.clone(), reason)).unwrap();
+        }
+    }
 
     fn on_init(&mut self, schema: &str, writer: InitWriter<net::TcpStream>) -> io::Result<()> {
         (self.on_i)(schema, writer)
@@ -63,6 +97,101 @@ where
     ) -> io::Result<()> {
         (self.on_q)(query, results)
     }
+
+    fn on_begin(
+        &mut self,
+        _: &str,
+        options: TransactionOptions,
+        results: QueryResultWriter<net::TcpStream>,
+    ) -> io::Result<()> {
+        (self.on_q)(&format!("begin {:?}", options), results)
+    }
+
+    fn on_commit(&mut self, _: &str, results: QueryResultWriter<net::TcpStream>) -> io::Result<()> {
+        (self.on_q)("commit", results)
+    }
+
+    fn on_rollback(
+        &mut self,
+        _: &str,
+        results: QueryResultWriter<net::TcpStream>,
+    ) -> io::Result<()> {
+        (self.on_q)("rollback", results)
+    }
+
+    fn on_savepoint(
+        &mut self,
+        _: &str,
+        name: &str,
+        results: QueryResultWriter<net::TcpStream>,
+    ) -> io::Result<()> {
+        (self.on_q)(&format!("savepoint {}", name), results)
+    }
+
+    fn on_release_savepoint(
+        &mut self,
+        _: &str,
+        name: &str,
+        results: QueryResultWriter<net::TcpStream>,
+    ) -> io::Result<()> {
+        (self.on_q)(&format!("release {}", name), results)
+    }
+
+    fn on_rollback_to_savepoint(
+        &mut self,
+        _: &str,
+        name: &str,
+        results: QueryResultWriter<net::TcpStream>,
+    ) -> io::Result<()> {
+        (self.on_q)(&format!("rollback to {}", name), results)
+    }
+
+    fn on_ping(&mut self, w: PingWriter<net::TcpStream>) -> io::Result<()> {
+        if self.backend_down {
+            w.error(ErrorKind::ER_SERVER_SHUTDOWN, b"backend is down")
+        } else {
+            w.ok()
+        }
+    }
+
+    fn on_list_fields(&mut self, table: &str, _: &str) -> io::Result<Vec<Column>> {
+        Ok(self
+            .columns
+            .iter()
+            .filter(|c| c.table == table)
+            .cloned()
+            .collect())
+    }
+
+    fn split_multi_statements(&self) -> bool {
+        self.multi_statements
+    }
+
+    fn system_variables(&self) -> SystemVariables {
+        self.variables.clone()
+    }
+
+    fn set_statements(&self) -> SetStatements {
+        self.set_statements
+    }
+
+    fn driver_compatibility(&self) -> bool {
+        self.driver_compatibility
+    }
+
+    fn catalog(&self) -> Option<&dyn Catalog> {
+        match self.catalog {
+            Some(ref catalog) => Some(&**catalog),
+            None => None,
+        }
+    }
+
+    fn xa_handler(&mut self) -> Option<&mut dyn XaHandler> {
+        match self.xa {
+            Some(ref mut xa) => Some(&mut **xa),
+            None => None,
+        }
+    }
 }
 
 impl<Q, P, E, I> TestingShim<Q, P, E, I>
@@ -82,6 +211,16 @@ where
             on_p,
             on_e,
             on_i,
+            multi_statements: false,
+            backend_down: false,
+            closed: Vec::new(),
+            disconnected: None,
+            variables: SystemVariables::default(),
+            set_statements: SetStatements::Forward,
+            driver_compatibility: false,
+            emulated: None,
+            catalog: None,
+            xa: None,
         }
     }
 
@@ -95,6 +234,51 @@ where
         self
     }
 
+    fn with_multi_statements(mut self) -> Self {
+        self.multi_statements = true;
+        self
+    }
+
+    fn with_backend_down(mut self) -> Self {
+        self.backend_down = true;
+        self
+    }
+
+    fn with_variables(mut self, v: SystemVariables) -> Self {
+        self.variables = v;
+        self
+    }
+
+    fn with_set_statements(mut self, s: SetStatements) -> Self {
+        self.set_statements = s;
+        self
+    }
+
+    fn with_driver_compatibility(mut self) -> Self {
+        self.driver_compatibility = true;
+        self
+    }
+
+    fn with_emulated_statements(mut self) -> Self {
+        self.emulated = Some(EmulatedStatements::new());
+        self
+    }
+
+    fn with_catalog<C: Catalog + Send + 'static>(mut self, c: C) -> Self {
+        self.catalog = Some(Box::new(c));
+        self
+    }
+
+    fn with_xa_handler<X: XaHandler + Send + 'static>(mut self, x: X) -> Self {
+        self.xa = Some(Box::new(x));
+        self
+    }
+
+    fn with_disconnect(mut self, d: mpsc::Sender<(Vec<u32>, DisconnectReason)>) -> Self {
+        self.disconnected = Some(d);
+        self
+    }
+
     fn test<C>(self, c: C)
     where
         C: FnOnce(&mut mysql::Conn) -> (),
@@ -111,6 +295,83 @@ where
         drop(db);
         jh.join().unwrap().unwrap();
     }
+
+    fn test_raw<C>(self, c: C)
+    where
+        C: FnOnce(&mut RawConn),
+    {
+        self.test_raw_with_capabilities(0, c)
+    }
+
+    fn test_raw_with_capabilities<C>(self, capabilities: u32, c: C)
+    where
+        C: FnOnce(&mut RawConn),
+    {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let jh = thread::spawn(move || {
+            let (s, _) = listener.accept().unwrap();
+            MysqlIntermediary::run_on_tcp(self, s)
+        });
+
+        let mut conn = RawConn::connect(port, capabilities);
+        c(&mut conn);
+        drop(conn);
+        jh.join().unwrap().unwrap();
+    }
+}
+
+/// Speaks just enough of the protocol to issue commands that the client library does not support.
+struct RawConn(net::TcpStream);
+
+impl RawConn {
+    fn connect(port: u16, capabilities: u32) -> Self {
+        let mut conn = RawConn(net::TcpStream::connect(("127.0.0.1", port)).unwrap());
+        conn.recv(); // server handshake
+
+        let mut handshake = Vec::new();
+        let caps = myc::constants::CapabilityFlags::CLIENT_PROTOCOL_41
+            | myc::constants::CapabilityFlags::CLIENT_SECURE_CONNECTION;
+        handshake.extend(&(caps.bits() | capabilities).to_le_bytes());
+        handshake.extend(&16_777_216u32.to_le_bytes()); // max packet size
+        handshake.push(0x21); // UTF8_GENERAL_CI
+        handshake.extend(&[0; 23]);
+        handshake.extend(b"jon\0");
+        handshake.push(0); // no auth data
+        conn.send(1, &handshake);
+        assert_eq!(conn.recv()[0], 0x00);
+        conn
+    }
+
+    fn send(&mut self, seq: u8, payload: &[u8]) {
+        let len = (payload.len() as u32).to_le_bytes();
+        self.0.write_all(&[len[0], len[1], len[2], seq]).unwrap();
+        self.0.write_all(payload).unwrap();
+    }
+
+    fn recv(&mut self) -> Vec<u8> {
+        let mut header = [0; 4];
+        self.0.read_exact(&mut header).unwrap();
+        let len = u32::from_le_bytes([header[0], header[1], header[2], 0]);
+        let mut payload = vec![0; len as usize];
+        self.0.read_exact(&mut payload).unwrap();
+        payload
+    }
+
+    /// Receive the packets of a text resultset, up to and including the final EOF.
+    fn recv_resultset(&mut self) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let ncols = self.recv()[0] as usize;
+        let cols: Vec<_> = (0..ncols).map(|_| self.recv()).collect();
+        assert_eq!(self.recv()[0], 0xFE);
+        let mut rows = Vec::new();
+        loop {
+            let row = self.recv();
+            if row[0] == 0xFE && row.len() < 9 {
+                return (cols, rows);
+            }
+            rows.push(row);
+        }
+    }
 }
 
 #[test]
@@ -172,6 +433,201 @@ fn it_inits_on_use_query_ok() {
     });
 }
 
+#[test]
+fn it_inits_on_use_query_with_comments_and_quoting() {
+    let (tx, rx) = mpsc::channel();
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        move |schema, writer| {
+            tx.send(schema.to_owned()).unwrap();
+            writer.ok()
+        },
+    )
+    .test(|db| {
+        db.query_drop("Use test").unwrap();
+        db.query_drop("/* pick */ USE\t`my``db` -- the quoted one")
+            .unwrap();
+    });
+    assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["test", "my`db"]);
+}
+
+/// A catalog with a single `shop` schema that holds an `orders` table.
+struct ShopCatalog;
+
+impl Catalog for ShopCatalog {
+    fn schemas(&self) -> Vec<String> {
+        vec!["shop".to_owned()]
+    }
+
+    fn tables(&self, schema: &str) -> Option<Vec<String>> {
+        if schema == "shop" {
+            Some(vec!["orders".to_owned()])
+        } else {
+            None
+        }
+    }
+
+    fn columns(&self, schema: &str, table: &str) -> Option<Vec<Column>> {
+        if (schema, table) != ("shop", "orders") {
+            return None;
+        }
+        Some(vec![
+            Column {
+                table: "orders".to_owned(),
+                column: "id".to_owned(),
+                coltype: myc::constants::ColumnType::MYSQL_TYPE_LONG,
+                colflags: myc::constants::ColumnFlags::NOT_NULL_FLAG
+                    | myc::constants::ColumnFlags::PRI_KEY_FLAG,
+            },
+            Column {
+                table: "orders".to_owned(),
+                column: "note".to_owned(),
+                coltype: myc::constants::ColumnType::MYSQL_TYPE_BLOB,
+                colflags: myc::constants::ColumnFlags::empty(),
+            },
+        ])
+    }
+}
+
+#[test]
+fn it_answers_introspection_from_catalog() {
+    TestingShim::new(
+        |q, w| {
+            assert_eq!(q, "SELECT * FROM orders");
+            w.completed(0, 0)
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, w| w.ok(),
+    )
+    .with_catalog(ShopCatalog)
+    .test(|db| {
+        let schemas: Vec<String> = db.query("SHOW DATABASES").unwrap();
+        assert_eq!(schemas, vec!["shop"]);
+
+        let err = db.query_drop("SHOW TABLES").unwrap_err();
+        match err {
+            mysql::Error::MySqlError(e) => assert_eq!(e.code, ErrorKind::ER_NO_DB_ERROR as u16),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        db.query_drop("USE shop").unwrap();
+        let tables: Vec<String> = db.query("SHOW TABLES").unwrap();
+        assert_eq!(tables, vec!["orders"]);
+
+        let columns: Vec<(String, String, String, String, Option<String>, String)> =
+            db.query("DESCRIBE orders").unwrap();
+        let column = |field: &str, ty: &str, null: &str, key: &str| {
+            let owned = |s: &str| s.to_owned();
+            (
+                owned(field),
+                owned(ty),
+                owned(null),
+                owned(key),
+                None,
+                String::new(),
+            )
+        };
+        assert_eq!(
+            columns,
+            vec![
+                column("id", "int", "NO", "PRI"),
+                column("note", "text", "YES", ""),
+            ]
+        );
+
+        let columns: Vec<(String, u64, String)> = db
+            .query(
+                "SELECT column_name, ordinal_position, data_type FROM information_schema.columns \
+                 WHERE table_schema = 'shop' AND table_name = 'orders' \
+                 ORDER BY ordinal_position DESC",
+            )
+            .unwrap();
+        assert_eq!(
+            columns,
+            vec![
+                ("note".to_owned(), 2, "text".to_owned()),
+                ("id".to_owned(), 1, "int".to_owned()),
+            ]
+        );
+
+        db.query_drop("SELECT * FROM orders").unwrap();
+    })
+}
+
+/// An XA resource manager that only remembers which transaction branches are prepared.
+#[derive(Default)]
+struct PreparedBranches(Vec<Xid>);
+
+impl XaHandler for PreparedBranches {
+    fn start(&mut self, _: &Xid) -> Result<(), ErrorKind> {
+        Ok(())
+    }
+
+    fn prepare(&mut self, xid: &Xid) -> Result<(), ErrorKind> {
+        self.0.push(xid.clone());
+        Ok(())
+    }
+
+    fn commit(&mut self, xid: &Xid, _: bool) -> Result<(), ErrorKind> {
+        let before = self.0.len();
+        self.0.retain(|prepared| prepared != xid);
+        if self.0.len() == before {
+            Err(ErrorKind::ER_XAER_NOTA)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn rollback(&mut self, xid: &Xid) -> Result<(), ErrorKind> {
+        self.0.retain(|prepared| prepared != xid);
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Vec<Xid> {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn it_handles_xa_transactions() {
+    TestingShim::new(
+        |q, w| {
+            assert_eq!(q, "INSERT INTO t VALUES (1)");
+            w.completed(1, 0)
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_xa_handler(PreparedBranches::default())
+    .test(|db| {
+        let code = |e| match e {
+            mysql::Error::MySqlError(e) => e.code,
+            e => panic!("unexpected error: {:?}", e),
+        };
+
+        db.query_drop("XA START 'g1', 'b1'").unwrap();
+        db.query_drop("INSERT INTO t VALUES (1)").unwrap();
+        let e = db.query_drop("XA PREPARE 'g1', 'b1'").unwrap_err();
+        assert_eq!(code(e), ErrorKind::ER_XAER_RMFAIL as u16);
+        db.query_drop("XA END 'g1', 'b1'").unwrap();
+        db.query_drop("XA PREPARE 'g1', 'b1'").unwrap();
+
+        let recovered: Vec<(i64, i64, i64, Vec<u8>)> = db.query("XA RECOVER").unwrap();
+        assert_eq!(recovered, vec![(1, 2, 2, b"g1b1".to_vec())]);
+        let recovered: Vec<(i64, i64, i64, String)> = db.query("XA RECOVER CONVERT XID").unwrap();
+        assert_eq!(recovered, vec![(1, 2, 2, "0x67316231".to_owned())]);
+
+        db.query_drop("XA COMMIT 'g1', 'b1'").unwrap();
+        let recovered: Vec<(i64, i64, i64, Vec<u8>)> = db.query("XA RECOVER").unwrap();
+        assert!(recovered.is_empty());
+        let e = db.query_drop("XA COMMIT 'g1', 'b1'").unwrap_err();
+        assert_eq!(code(e), ErrorKind::ER_XAER_NOTA as u16);
+    })
+}
+
 #[test]
 fn it_pings() {
     TestingShim::new(
@@ -184,206 +640,1618 @@ fn it_pings() {
 }
 
 #[test]
-fn empty_response() {
+fn it_reports_unhealthy_pings() {
     TestingShim::new(
         |_, w| w.completed(0, 0),
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
+    .with_backend_down()
+    .test_raw(|conn| {
+        conn.send(0, &[myc::constants::Command::COM_PING as u8]);
+        let err = conn.recv();
+        assert_eq!(err[0], 0xFF);
+        assert_eq!(
+            u16::from_le_bytes([err[1], err[2]]),
+            ErrorKind::ER_SERVER_SHUTDOWN as u16
+        );
+
+        // the connection is still usable
+        conn.send(0, b"\x03SELECT 1");
+        assert_eq!(conn.recv()[0], 0x00);
+    })
+}
+
+#[test]
+fn it_selects_system_variables() {
+    let mut variables = SystemVariables::default();
+    variables.set(VariableScope::Global, "time_zone", "+00:00");
+    variables.set(VariableScope::Session, "time_zone", "Europe/Oslo");
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_variables(variables)
     .test(|db| {
-        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+        let row: (u64, String, String, String) = db
+            .query_first(
+                "SELECT @@auto_increment_increment, @@session.time_zone, \
+                 @@GLOBAL.time_zone AS tz, @@version_comment",
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                1,
+                "Europe/Oslo".to_owned(),
+                "+00:00".to_owned(),
+                "msql-srv".to_owned()
+            )
+        );
+
+        let res = db
+            .query_iter("select @@Session.max_allowed_packet, @@time_zone `zone`")
+            .unwrap();
+        let cols = res.columns();
+        let cols = cols.as_ref();
+        assert_eq!(cols[0].name_str(), "@@Session.max_allowed_packet");
+        assert_eq!(
+            cols[0].column_type(),
+            mysql::consts::ColumnType::MYSQL_TYPE_LONGLONG
+        );
+        assert!(cols[0]
+            .flags()
+            .contains(mysql::consts::ColumnFlags::UNSIGNED_FLAG));
+        assert_eq!(cols[1].name_str(), "zone");
+        assert_eq!(
+            cols[1].column_type(),
+            mysql::consts::ColumnType::MYSQL_TYPE_VAR_STRING
+        );
+        drop(res);
+
+        match db.query_iter("SELECT @@version, @@no_such_variable") {
+            Err(mysql::Error::MySqlError(e)) => {
+                assert_eq!(e.code, ErrorKind::ER_UNKNOWN_SYSTEM_VARIABLE as u16);
+                assert_eq!(e.message, "Unknown system variable 'no_such_variable'");
+            }
+            _ => unreachable!(),
+        }
     })
 }
 
 #[test]
-fn no_rows() {
-    let cols = [Column {
-        table: String::new(),
-        column: "a".to_owned(),
-        coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
-        colflags: myc::constants::ColumnFlags::empty(),
-    }];
+fn it_applies_set_statements() {
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_set_statements(SetStatements::Apply)
+    .test(|db| {
+        db.query_drop("SET NAMES utf8mb4").unwrap();
+        db.query_drop("SET autocommit=0, SESSION sql_mode='ANSI_QUOTES'")
+            .unwrap();
+        db.query_drop("SET character_set_results = NULL").unwrap();
+        db.query_drop("SET @@session.time_zone='+00:00'").unwrap();
+        match db.query_drop("SET time_zone = 'UTC', @@global.no_such_variable = 1") {
+            Err(mysql::Error::MySqlError(e)) => {
+                assert_eq!(e.code, ErrorKind::ER_UNKNOWN_SYSTEM_VARIABLE as u16);
+                assert_eq!(e.message, "Unknown system variable 'no_such_variable'");
+            }
+            _ => unreachable!(),
+        }
+
+        let row: (i64, String, String, Option<String>, String) = db
+            .query_first(
+                "SELECT @@autocommit, @@sql_mode, @@character_set_client, \
+                 @@character_set_results, @@time_zone",
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                0,
+                "ANSI_QUOTES".to_owned(),
+                "utf8mb4".to_owned(),
+                None,
+                "+00:00".to_owned()
+            )
+        );
+        let time_zone: String = db
+            .query_first("SELECT @@global.time_zone")
+            .unwrap()
+            .unwrap();
+        assert_eq!(time_zone, "SYSTEM");
+    })
+}
+
+#[test]
+fn it_reports_autocommit_in_status_flags() {
+    TestingShim::new(
+        |_, w| w.completed(0, 0),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_set_statements(SetStatements::Apply)
+    .test_raw(|conn| {
+        let autocommit = |ok: &[u8]| {
+            let status = u16::from_le_bytes([ok[3], ok[4]]);
+            status & myc::constants::StatusFlags::SERVER_STATUS_AUTOCOMMIT.bits() != 0
+        };
+
+        conn.send(0, b"\x03SET autocommit = OFF");
+        assert!(!autocommit(&conn.recv()));
+        conn.send(0, b"\x03DELETE FROM foo");
+        assert!(!autocommit(&conn.recv()));
+        conn.send(0, b"\x03SET @@autocommit = 1");
+        assert!(autocommit(&conn.recv()));
+    })
+}
+
+#[test]
+fn it_tracks_transactions() {
+    let (tx, rx) = mpsc::channel();
+    TestingShim::new(
+        move |q, w| {
+            tx.send(q.to_owned()).unwrap();
+            if q == "rollback to missing" {
+                w.error(
+                    ErrorKind::ER_SP_DOES_NOT_EXIST,
+                    b"SAVEPOINT missing does not exist",
+                )
+            } else {
+                w.completed(0, 0)
+            }
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_set_statements(SetStatements::Apply)
+    .test_raw(|conn| {
+        let in_trans = |ok: &[u8]| {
+            let status = u16::from_le_bytes([ok[3], ok[4]]);
+            status & myc::constants::StatusFlags::SERVER_STATUS_IN_TRANS.bits() != 0
+        };
+
+        conn.send(
+            0,
+            b"\x03START TRANSACTION READ ONLY, WITH CONSISTENT SNAPSHOT",
+        );
+        assert!(in_trans(&conn.recv()));
+        conn.send(0, b"\x03SAVEPOINT s1");
+        assert!(in_trans(&conn.recv()));
+        conn.send(0, b"\x03ROLLBACK TO SAVEPOINT s1");
+        assert!(in_trans(&conn.recv()));
+        conn.send(0, b"\x03RELEASE SAVEPOINT s1");
+        assert!(in_trans(&conn.recv()));
+        conn.send(0, b"\x03ROLLBACK TO missing");
+        assert_eq!(conn.recv()[0], 0xff);
+        conn.send(0, b"\x03COMMIT");
+        assert!(!in_trans(&conn.recv()));
+        conn.send(0, b"\x03DELETE FROM foo");
+        assert!(!in_trans(&conn.recv()));
+
+        // with autocommit off, any statement opens a transaction
+        conn.send(0, b"\x03SET autocommit = 0");
+        assert!(!in_trans(&conn.recv()));
+        conn.send(0, b"\x03DELETE FROM foo");
+        assert!(in_trans(&conn.recv()));
+        conn.send(0, b"\x03ROLLBACK");
+        assert!(!in_trans(&conn.recv()));
+        conn.send(0, b"\x03DELETE FROM foo");
+        assert!(in_trans(&conn.recv()));
+        conn.send(0, b"\x03SET autocommit = 1");
+        assert!(!in_trans(&conn.recv()));
+        conn.send(0, b"\x03BEGIN");
+        assert!(in_trans(&conn.recv()));
+    });
+
+    assert_eq!(
+        rx.iter().collect::<Vec<_>>(),
+        vec![
+            "begin TransactionOptions { read_only: true, consistent_snapshot: true }",
+            "savepoint s1",
+            "rollback to s1",
+            "release s1",
+            "rollback to missing",
+            "commit",
+            "DELETE FROM foo",
+            "DELETE FROM foo",
+            "rollback",
+            "DELETE FROM foo",
+            "commit",
+            "begin TransactionOptions { read_only: false, consistent_snapshot: false }",
+        ]
+    );
+}
+
+#[test]
+fn it_keeps_autocommit_off_if_turning_it_on_fails_to_commit() {
+    let mut commits = 0;
+    TestingShim::new(
+        move |q, w| {
+            if q == "commit" {
+                commits += 1;
+                if commits == 1 {
+                    return w.error(ErrorKind::ER_LOCK_DEADLOCK, b"Deadlock found");
+                }
+            }
+            w.completed(0, 0)
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_set_statements(SetStatements::Apply)
+    .test_raw(|conn| {
+        let status = |ok: &[u8]| {
+            let status = u16::from_le_bytes([ok[3], ok[4]]);
+            myc::constants::StatusFlags::from_bits_truncate(status)
+        };
+        let autocommit = myc::constants::StatusFlags::SERVER_STATUS_AUTOCOMMIT;
+        let in_trans = myc::constants::StatusFlags::SERVER_STATUS_IN_TRANS;
+
+        conn.send(0, b"\x03SET autocommit = 0");
+        assert!(!status(&conn.recv()).intersects(autocommit | in_trans));
+        conn.send(0, b"\x03DELETE FROM foo");
+        assert_eq!(status(&conn.recv()) & (autocommit | in_trans), in_trans);
+        conn.send(0, b"\x03SET autocommit = 1");
+        assert_eq!(conn.recv()[0], 0xff);
+        conn.send(0, b"\x03DELETE FROM foo");
+        assert_eq!(status(&conn.recv()) & (autocommit | in_trans), in_trans);
+        conn.send(0, b"\x03SET autocommit = 1");
+        assert_eq!(status(&conn.recv()) & (autocommit | in_trans), autocommit);
+    });
+}
+
+#[test]
+fn it_forwards_applied_set_statements() {
+    TestingShim::new(
+        |q, w| {
+            assert_eq!(q, "SET SESSION wait_timeout = 60");
+            assert_eq!(
+                w.variables().get(VariableScope::Session, "wait_timeout"),
+                Some(&VariableValue::UInt(60))
+            );
+            w.completed(0, 0)
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_set_statements(SetStatements::ApplyAndForward)
+    .test(|db| {
+        db.query_drop("SET SESSION wait_timeout = 60").unwrap();
+        let timeout: u64 = db.query_first("SELECT @@wait_timeout").unwrap().unwrap();
+        assert_eq!(timeout, 60);
+    })
+}
+
+#[test]
+fn it_answers_driver_bootstrap_queries() {
+    TestingShim::new(
+        |q, w| {
+            assert_eq!(q, "SHOW TABLES");
+            w.completed(0, 0)
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_driver_compatibility()
+    .test(|db| {
+        let comment: String = db
+            .query_first("select @@version_comment limit 1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(comment, "msql-srv");
+
+        let vars: Vec<(String, String)> = db.query("SHOW VARIABLES LIKE 'lower_case%'").unwrap();
+        assert_eq!(
+            vars,
+            vec![("lower_case_table_names".to_owned(), "0".to_owned())]
+        );
+        let vars: Vec<(String, String)> = db
+            .query(
+                "SHOW VARIABLES WHERE Variable_name = 'wait_timeout' \
+                 OR Variable_name = 'autocommit'",
+            )
+            .unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("autocommit".to_owned(), "1".to_owned()),
+                ("wait_timeout".to_owned(), "28800".to_owned())
+            ]
+        );
+
+        let collations: Vec<(String, String, u64, String, String, u64)> =
+            db.query("SHOW COLLATION LIKE 'utf8mb4%'").unwrap();
+        assert!(collations.len() > 1);
+        assert!(collations
+            .iter()
+            .any(|c| c.0 == "utf8mb4_general_ci" && c.2 == 45 && c.3 == "Yes"));
+
+        let charsets: Vec<(String, String, String, u64)> = db.query("SHOW CHARACTER SET").unwrap();
+        assert!(charsets
+            .iter()
+            .any(|c| c.0 == "utf8" && c.2 == "utf8_general_ci"));
+
+        // everything else still goes to the shim
+        db.query_drop("SHOW TABLES").unwrap();
+    })
+}
+
+#[test]
+fn it_reports_statistics() {
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test_raw(|conn| {
+        conn.send(0, &[myc::constants::Command::COM_STATISTICS as u8]);
+        let stats = String::from_utf8(conn.recv()).unwrap();
+        assert!(stats.starts_with("Uptime: "), "{}", stats);
+        assert!(stats.contains("  Threads: "), "{}", stats);
+        assert!(stats.contains("  Queries per second avg: "), "{}", stats);
+    })
+}
+
+#[test]
+fn it_passes_query_attributes() {
+    TestingShim::new(
+        |q, w| {
+            assert_eq!(q, "SELECT 1");
+            let attributes = w.attributes();
+            assert_eq!(attributes.len(), 1);
+            assert_eq!(attributes[0].name, "trace");
+            assert_eq!(attributes[0].value.into_inner(), ValueInner::Bytes(b"abc"));
+            w.completed(0, 0)
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test_raw_with_capabilities(1 << 27, |conn| {
+        let mut query = vec![0x03];
+        query.extend(&[0x01, 0x01, 0x00, 0x01]); // one attribute, with types
+        query.extend(&[myc::constants::ColumnType::MYSQL_TYPE_VAR_STRING as u8, 0]);
+        query.extend(b"\x05trace\x03abc");
+        query.extend(b"SELECT 1");
+        conn.send(0, &query);
+        assert_eq!(conn.recv()[0], 0x00);
+    })
+}
+
+#[test]
+fn it_lists_fields() {
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_columns(vec![Column {
+        table: "foo".to_owned(),
+        column: "a".to_owned(),
+        coltype: myc::constants::ColumnType::MYSQL_TYPE_LONGLONG,
+        colflags: myc::constants::ColumnFlags::empty(),
+    }])
+    .test_raw(|conn| {
+        conn.send(0, b"\x04foo\0");
+        let col = conn.recv();
+        assert!(col.starts_with(b"\x03def\x00\x03foo\x00\x01a\x00"));
+        // the default value is NULL
+        assert_eq!(col.last(), Some(&0xFB));
+        assert_eq!(conn.recv()[0], 0xFE);
+
+        conn.send(0, b"\x04bar\0");
+        assert_eq!(conn.recv()[0], 0xFE);
+    })
+}
+
+#[test]
+fn it_lists_processes() {
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test_raw(|conn| {
+        conn.send(0, &[myc::constants::Command::COM_PROCESS_INFO as u8]);
+        let (cols, rows) = conn.recv_resultset();
+        assert_eq!(cols.len(), 8);
+        // this connection is in there, busy listing the processes
+        assert!(rows
+            .iter()
+            .any(|row| row.windows(3).any(|w| w == b"jon")
+                && row.windows(11).any(|w| w == b"Processlist")));
+    })
+}
+
+#[test]
+fn empty_response() {
+    TestingShim::new(
+        |_, w| w.completed(0, 0),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+    })
+}
+
+#[test]
+fn no_rows() {
+    let cols = [Column {
+        table: String::new(),
+        column: "a".to_owned(),
+        coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
+        colflags: myc::constants::ColumnFlags::empty(),
+    }];
+    TestingShim::new(
+        move |_, w| w.start(&cols[..])?.finish(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+    })
+}
+
+#[test]
+fn no_columns() {
+    TestingShim::new(
+        move |_, w| w.start(&[])?.finish(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+    })
+}
+
+#[test]
+fn no_columns_but_rows() {
+    TestingShim::new(
+        move |_, w| w.start(&[])?.write_col(42).map(|_| ()),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+    })
+}
+
+#[test]
+fn error_response() {
+    let err = (ErrorKind::ER_NO, "clearly not");
+    TestingShim::new(
+        move |_, w| w.error(err.0, err.1.as_bytes()),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        if let mysql::Error::MySqlError(e) = db.query_iter("SELECT a, b FROM foo").unwrap_err() {
+            assert_eq!(
+                e,
+                mysql::error::MySqlError {
+                    state: String::from_utf8(err.0.sqlstate().to_vec()).unwrap(),
+                    message: err.1.to_owned(),
+                    code: err.0 as u16,
+                }
+            );
+        } else {
+            unreachable!();
+        }
+    })
+}
+
+#[test]
+fn empty_on_drop() {
+    let cols = [Column {
+        table: String::new(),
+        column: "a".to_owned(),
+        coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
+        colflags: myc::constants::ColumnFlags::empty(),
+    }];
+    TestingShim::new(
+        move |_, w| w.start(&cols[..]).map(|_| ()),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+    })
+}
+
+#[test]
+fn it_queries_nulls() {
+    TestingShim::new(
+        |_, w| {
+            let cols = &[Column {
+                table: String::new(),
+                column: "a".to_owned(),
+                coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
+                colflags: myc::constants::ColumnFlags::empty(),
+            }];
+            let mut w = w.start(cols)?;
+            w.write_col(None::<i16>)?;
+            w.finish()
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        let row = db
+            .query_iter("SELECT a, b FROM foo")
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.as_ref(0), Some(&mysql::Value::NULL));
+    })
+}
+
+#[test]
+fn it_queries() {
+    TestingShim::new(
+        |_, w| {
+            let cols = &[Column {
+                table: String::new(),
+                column: "a".to_owned(),
+                coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
+                colflags: myc::constants::ColumnFlags::empty(),
+            }];
+            let mut w = w.start(cols)?;
+            w.write_col(1024i16)?;
+            w.finish()
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        let row = db
+            .query_iter("SELECT a, b FROM foo")
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.get::<i16, _>(0), Some(1024));
+    })
+}
+
+#[test]
+#[cfg(feature = "sqlparser")]
+fn it_prepares_parsed_statements() {
+    use msql_srv::sqlparser::ast::Statement;
+
+    struct ParsingShim;
+
+    impl<W: io::Write> MysqlShim<W> for ParsingShim {
+        type Error = io::Error;
+
+        fn on_prepare(&mut self, query: &str, info: StatementMetaWriter<'_, W>) -> io::Result<()> {
+            // only queries that do not parse end up here
+            assert_eq!(query, "SELEKT a FROM t");
+            info.reply(2, &[], &[])
+        }
+
+        fn on_prepare_statement(
+            &mut self,
+            query: &str,
+            statement: Statement,
+            info: StatementMetaWriter<'_, W>,
+        ) -> io::Result<()> {
+            assert_eq!(query, "SELECT a FROM t");
+            assert!(matches!(statement, Statement::Query(_)));
+            info.reply(1, &[], &[])
+        }
+
+        fn on_execute(
+            &mut self,
+            id: u32,
+            _: ParamParser<'_>,
+            results: QueryResultWriter<'_, W>,
+        ) -> io::Result<()> {
+            results.completed(u64::from(id), 0)
+        }
+
+        fn on_close(&mut self, _: u32) {}
+
+        fn on_query(&mut self, _: &str, _: QueryResultWriter<'_, W>) -> io::Result<()> {
+            unreachable!()
+        }
+    }
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let jh = thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        MysqlIntermediary::run_on_tcp(ParsingShim, s)
+    });
+
+    let mut db = mysql::Conn::new(format!("mysql://127.0.0.1:{}", port)).unwrap();
+    let parsed = db.prep("SELECT a FROM t").unwrap();
+    assert_eq!(db.exec_iter(&parsed, ()).unwrap().affected_rows(), 1);
+    let unparsed = db.prep("SELEKT a FROM t").unwrap();
+    assert_eq!(db.exec_iter(&unparsed, ()).unwrap().affected_rows(), 2);
+    drop(db);
+    jh.join().unwrap().unwrap();
+}
+
+#[test]
+#[cfg(feature = "sqlparser")]
+fn it_routes_parsed_statements() {
+    use msql_srv::sqlparser::ast::{Query, Statement};
+
+    struct RoutingShim;
+
+    impl<W: io::Write> MysqlShim<W> for RoutingShim {
+        type Error = io::Error;
+
+        fn on_prepare(&mut self, _: &str, _: StatementMetaWriter<'_, W>) -> io::Result<()> {
+            unreachable!()
+        }
+
+        fn on_execute(
+            &mut self,
+            _: u32,
+            _: ParamParser<'_>,
+            _: QueryResultWriter<'_, W>,
+        ) -> io::Result<()> {
+            unreachable!()
+        }
+
+        fn on_close(&mut self, _: u32) {}
+
+        fn on_select(
+            &mut self,
+            query: &str,
+            _: Query,
+            results: QueryResultWriter<'_, W>,
+        ) -> io::Result<()> {
+            assert_eq!(query, "SELECT a FROM t");
+            results.completed(1, 0)
+        }
+
+        fn on_insert(
+            &mut self,
+            query: &str,
+            statement: Statement,
+            results: QueryResultWriter<'_, W>,
+        ) -> io::Result<()> {
+            assert_eq!(query, "INSERT INTO t VALUES (1)");
+            assert!(matches!(statement, Statement::Insert { .. }));
+            results.completed(2, 0)
+        }
+
+        fn on_query(&mut self, query: &str, results: QueryResultWriter<'_, W>) -> io::Result<()> {
+            // only queries that do not parse end up here
+            assert_eq!(query, "SELEKT a FROM t");
+            results.completed(3, 0)
+        }
+    }
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let jh = thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        MysqlIntermediary::run_on_tcp(RoutingShim, s)
+    });
+
+    let mut db = mysql::Conn::new(format!("mysql://127.0.0.1:{}", port)).unwrap();
+    let affected = |db: &mut mysql::Conn, q| db.query_iter(q).unwrap().affected_rows();
+    assert_eq!(affected(&mut db, "SELECT a FROM t"), 1);
+    assert_eq!(affected(&mut db, "INSERT INTO t VALUES (1)"), 2);
+    assert_eq!(affected(&mut db, "SELEKT a FROM t"), 3);
+    drop(db);
+    jh.join().unwrap().unwrap();
+}
+
+#[test]
+fn it_sends_full_column_definitions() {
+    fn definitions() -> Vec<ColumnDefinition> {
+        let price = Column {
+            table: "o".to_owned(),
+            column: "p".to_owned(),
+            coltype: myc::constants::ColumnType::MYSQL_TYPE_NEWDECIMAL,
+            colflags: myc::constants::ColumnFlags::empty(),
+        };
+        let photo = Column {
+            table: "o".to_owned(),
+            column: "photo".to_owned(),
+            coltype: myc::constants::ColumnType::MYSQL_TYPE_BLOB,
+            colflags: myc::constants::ColumnFlags::BINARY_FLAG
+                | myc::constants::ColumnFlags::BLOB_FLAG,
+        };
+        vec![
+            ColumnDefinition {
+                schema: "shop".to_owned(),
+                org_table: "orders".to_owned(),
+                org_name: "price".to_owned(),
+                character_set: ColumnDefinition::BINARY,
+                column_length: 12,
+                decimals: 2,
+                ..ColumnDefinition::from(price)
+            },
+            ColumnDefinition {
+                character_set: ColumnDefinition::BINARY,
+                column_length: 65535,
+                ..ColumnDefinition::from(photo)
+            },
+        ]
+    }
+
+    TestingShim::new(
+        |_, w| {
+            let cols = definitions();
+            let mut w = w.start_with_definitions(&cols)?;
+            w.write_row([&b"12.50"[..], &[0xff, 0x00]])?;
+            w.finish()
+        },
+        |_| 1,
+        |_, _, w| {
+            let cols = definitions();
+            let mut w = w.start_with_definitions(&cols)?;
+            w.write_row([&b"12.50"[..], &[0xff, 0x00]])?;
+            w.finish()
+        },
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        let check = |cols: &[mysql::Column]| {
+            assert_eq!(cols[0].schema_str(), "shop");
+            assert_eq!(cols[0].table_str(), "o");
+            assert_eq!(cols[0].org_table_str(), "orders");
+            assert_eq!(cols[0].name_str(), "p");
+            assert_eq!(cols[0].org_name_str(), "price");
+            assert_eq!(cols[0].character_set(), 63);
+            assert_eq!(cols[0].column_length(), 12);
+            assert_eq!(cols[0].decimals(), 2);
+            assert_eq!(cols[1].schema_str(), "");
+            assert_eq!(cols[1].character_set(), 63);
+            assert_eq!(cols[1].column_length(), 65535);
+            assert_eq!(cols[1].decimals(), 0);
+        };
+
+        let mut res = db
+            .query_iter("SELECT price AS p, photo FROM orders o")
+            .unwrap();
+        check(res.columns().as_ref());
+        let row: (String, Vec<u8>) = mysql::from_row(res.next().unwrap().unwrap());
+        assert_eq!(row, ("12.50".to_owned(), vec![0xff, 0x00]));
+        drop(res);
+
+        let mut res = db
+            .exec_iter("SELECT price AS p, photo FROM orders o", ())
+            .unwrap();
+        check(res.columns().as_ref());
+        let row: (String, Vec<u8>) = mysql::from_row(res.next().unwrap().unwrap());
+        assert_eq!(row, ("12.50".to_owned(), vec![0xff, 0x00]));
+    })
+}
+
+#[test]
+#[cfg(feature = "derive")]
+fn it_sends_derived_rows() {
+    use msql_srv::MysqlRow;
+
+    #[derive(MysqlRow)]
+    #[mysql(table = "users")]
+    struct User {
+        id: u64,
+        name: String,
+        #[mysql(rename = "email_address")]
+        email: Option<String>,
+        score: i16,
+    }
+
+    fn reply<W: Write>(w: QueryResultWriter<'_, W>) -> io::Result<()> {
+        let users = [
+            User {
+                id: 1,
+                name: "alice".to_owned(),
+                email: Some("alice@example.com".to_owned()),
+                score: -3,
+            },
+            User {
+                id: u64::MAX,
+                name: "bob".to_owned(),
+                email: None,
+                score: 7,
+            },
+        ];
+        let cols = User::columns();
+        let mut w = w.start(&cols)?;
+        for user in &users {
+            user.write_row(&mut w)?;
+        }
+        w.finish()
+    }
+
+    TestingShim::new(
+        |_, w| reply(w),
+        |_| 1,
+        |_, _, w| reply(w),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        let check = |cols: &[mysql::Column]| {
+            let names: Vec<_> = cols.iter().map(|c| c.name_str().into_owned()).collect();
+            assert_eq!(names, ["id", "name", "email_address", "score"]);
+            assert!(cols.iter().all(|c| c.table_str() == "users"));
+            assert_eq!(
+                cols[0].column_type(),
+                mysql::consts::ColumnType::MYSQL_TYPE_LONGLONG
+            );
+            assert_eq!(
+                cols[0].flags(),
+                mysql::consts::ColumnFlags::NOT_NULL_FLAG
+                    | mysql::consts::ColumnFlags::UNSIGNED_FLAG
+            );
+            assert_eq!(cols[2].flags(), mysql::consts::ColumnFlags::empty());
+            assert_eq!(
+                cols[3].column_type(),
+                mysql::consts::ColumnType::MYSQL_TYPE_SHORT
+            );
+            assert_eq!(cols[3].flags(), mysql::consts::ColumnFlags::NOT_NULL_FLAG);
+        };
+        let expected = vec![
+            (
+                1,
+                "alice".to_owned(),
+                Some("alice@example.com".to_owned()),
+                -3,
+            ),
+            (u64::MAX, "bob".to_owned(), None, 7),
+        ];
+
+        let res = db.query_iter("SELECT * FROM users").unwrap();
+        check(res.columns().as_ref());
+        let rows: Vec<(u64, String, Option<String>, i16)> =
+            res.map(|row| mysql::from_row(row.unwrap())).collect();
+        assert_eq!(rows, expected);
+
+        let res = db.exec_iter("SELECT * FROM users", ()).unwrap();
+        check(res.columns().as_ref());
+        let rows: Vec<(u64, String, Option<String>, i16)> =
+            res.map(|row| mysql::from_row(row.unwrap())).collect();
+        assert_eq!(rows, expected);
+    })
+}
+
+#[test]
+#[cfg(feature = "arrow")]
+fn it_sends_record_batches() {
+    use msql_srv::arrow_array::{
+        BinaryArray, BooleanArray, Date32Array, Decimal128Array, Float64Array, Int32Array,
+        RecordBatch, StringArray, TimestampMillisecondArray, UInt64Array,
+    };
+    use msql_srv::arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use std::sync::Arc;
+
+    fn batch() -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("big", DataType::UInt64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("score", DataType::Float64, false),
+            Field::new("born", DataType::Date32, false),
+            Field::new(
+                "seen",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("price", DataType::Decimal128(10, 2), false),
+            Field::new("active", DataType::Boolean, false),
+            Field::new("data", DataType::Binary, false),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(Int32Array::from(vec![1, -2])),
+                Arc::new(UInt64Array::from(vec![u64::MAX, 0])),
+                Arc::new(StringArray::from(vec![Some("alice"), None])),
+                Arc::new(Float64Array::from(vec![1.5, -0.25])),
+                Arc::new(Date32Array::from(vec![0, 19000])),
+                Arc::new(TimestampMillisecondArray::from(vec![1_500_000_000_123, 0])),
+                Arc::new(
+                    Decimal128Array::from(vec![12345, -5])
+                        .with_precision_and_scale(10, 2)
+                        .unwrap(),
+                ),
+                Arc::new(BooleanArray::from(vec![true, false])),
+                Arc::new(BinaryArray::from(vec![&[0x00, 0xff][..], &[][..]])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn reply<W: Write>(w: QueryResultWriter<'_, W>) -> io::Result<()> {
+        let batch = batch();
+        let cols = msql_srv::arrow_columns(&batch.schema(), "t")?;
+        let mut w = w.start_with_definitions(&cols)?;
+        w.write_batch(&batch)?;
+        w.finish()
+    }
+
+    TestingShim::new(
+        |_, w| reply(w),
+        |_| 1,
+        |_, _, w| reply(w),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        let check = |cols: &[mysql::Column]| {
+            assert_eq!(cols.len(), 9);
+            assert_eq!(
+                cols[1].flags(),
+                mysql::consts::ColumnFlags::NOT_NULL_FLAG
+                    | mysql::consts::ColumnFlags::UNSIGNED_FLAG
+            );
+            assert_eq!(cols[2].flags(), mysql::consts::ColumnFlags::empty());
+            assert_eq!(
+                cols[5].column_type(),
+                mysql::consts::ColumnType::MYSQL_TYPE_DATETIME
+            );
+            assert_eq!(cols[5].decimals(), 3);
+            assert_eq!(
+                cols[6].column_type(),
+                mysql::consts::ColumnType::MYSQL_TYPE_NEWDECIMAL
+            );
+            assert_eq!(cols[6].decimals(), 2);
+        };
+
+        let res = db.query_iter("SELECT * FROM t").unwrap();
+        check(res.columns().as_ref());
+        let rows: Vec<Vec<mysql::Value>> = res.map(|row| row.unwrap().unwrap()).collect();
+        let text = |v: &[u8]| mysql::Value::Bytes(v.to_vec());
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    text(b"1"),
+                    text(b"18446744073709551615"),
+                    text(b"alice"),
+                    text(b"1.5"),
+                    text(b"1970-01-01"),
+                    text(b"2017-07-14 02:40:00.123000"),
+                    text(b"123.45"),
+                    text(b"1"),
+                    text(&[0x00, 0xff]),
+                ],
+                vec![
+                    text(b"-2"),
+                    text(b"0"),
+                    mysql::Value::NULL,
+                    text(b"-0.25"),
+                    text(b"2022-01-08"),
+                    text(b"1970-01-01 00:00:00"),
+                    text(b"-0.05"),
+                    text(b"0"),
+                    text(b""),
+                ],
+            ]
+        );
+
+        let res = db.exec_iter("SELECT * FROM t", ()).unwrap();
+        check(res.columns().as_ref());
+        let rows: Vec<Vec<mysql::Value>> = res.map(|row| row.unwrap().unwrap()).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    mysql::Value::Int(1),
+                    mysql::Value::UInt(u64::MAX),
+                    text(b"alice"),
+                    mysql::Value::Float(1.5),
+                    mysql::Value::Date(1970, 1, 1, 0, 0, 0, 0),
+                    mysql::Value::Date(2017, 7, 14, 2, 40, 0, 123_000),
+                    text(b"123.45"),
+                    mysql::Value::Int(1),
+                    text(&[0x00, 0xff]),
+                ],
+                vec![
+                    mysql::Value::Int(-2),
+                    mysql::Value::UInt(0),
+                    mysql::Value::NULL,
+                    mysql::Value::Float(-0.25),
+                    mysql::Value::Date(2022, 1, 8, 0, 0, 0, 0),
+                    mysql::Value::Date(1970, 1, 1, 0, 0, 0, 0),
+                    text(b"-0.05"),
+                    mysql::Value::Int(0),
+                    text(b""),
+                ],
+            ]
+        );
+    })
+}
+#[test]
+#[cfg(feature = "serde")]
+fn it_sends_serialized_rows() {
+    use msql_srv::{ColumnFlags, ColumnType};
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Kind {
+        Admin,
+        Guest,
+    }
+
+    #[derive(Serialize)]
+    struct User {
+        id: u64,
+        name: String,
+        email: Option<String>,
+        active: bool,
+        kind: Kind,
+    }
+
+    #[derive(Serialize)]
+    struct Renamed {
+        #[serde(rename = "user_id")]
+        id: u64,
+        name: String,
+        email: Option<String>,
+        active: bool,
+        kind: Kind,
+    }
+
+    #[derive(Serialize)]
+    struct Misnamed {
+        id: u64,
+        name: String,
+        mail: Option<String>,
+        active: bool,
+        kind: Kind,
+    }
+
+    fn reply<W: Write>(w: QueryResultWriter<'_, W>) -> io::Result<()> {
+        let column = |name: &str, coltype, colflags| Column {
+            table: "users".to_owned(),
+            column: name.to_owned(),
+            coltype,
+            colflags,
+        };
+        let cols = [
+            column(
+                "id",
+                ColumnType::MYSQL_TYPE_LONGLONG,
+                ColumnFlags::NOT_NULL_FLAG | ColumnFlags::UNSIGNED_FLAG,
+            ),
+            column(
+                "name",
+                ColumnType::MYSQL_TYPE_VAR_STRING,
+                ColumnFlags::NOT_NULL_FLAG,
+            ),
+            column(
+                "email",
+                ColumnType::MYSQL_TYPE_VAR_STRING,
+                ColumnFlags::empty(),
+            ),
+            column(
+                "active",
+                ColumnType::MYSQL_TYPE_TINY,
+                ColumnFlags::NOT_NULL_FLAG | ColumnFlags::UNSIGNED_FLAG,
+            ),
+            column(
+                "kind",
+                ColumnType::MYSQL_TYPE_VAR_STRING,
+                ColumnFlags::NOT_NULL_FLAG,
+            ),
+        ];
+        let mut w = w.start(&cols)?;
+
+        // mismatched rows are rejected, and whatever part of them was already written is
+        // thrown away again
+        let e = w
+            .write_serialize(&Renamed {
+                id: 0,
+                name: String::new(),
+                email: None,
+                active: false,
+                kind: Kind::Guest,
+            })
+            .unwrap_err();
+        assert_eq!(e.to_string(), "field `user_id` does not match column `id`");
+        let e = w
+            .write_serialize(&Misnamed {
+                id: 2,
+                name: "mallory".to_owned(),
+                mail: None,
+                active: false,
+                kind: Kind::Guest,
+            })
+            .unwrap_err();
+        assert_eq!(e.to_string(), "field `mail` does not match column `email`");
+        let e = w
+            .write_serialize(&(2u64, "mallory", None::<&str>, false, ["guest"]))
+            .unwrap_err();
+        assert_eq!(e.to_string(), "a column value must serialize as a scalar");
+        let e = w.write_serialize(&(1u64, "alice")).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "row has 2 fields, but the resultset has 5 columns"
+        );
+
+        w.write_serialize(&User {
+            id: 1,
+            name: "alice".to_owned(),
+            email: Some("alice@example.com".to_owned()),
+            active: true,
+            kind: Kind::Admin,
+        })?;
+        w.write_serialize(&(u64::MAX, "bob", None::<&str>, false, "guest"))?;
+        w.finish()
+    }
+
+    TestingShim::new(
+        |_, w| reply(w),
+        |_| 1,
+        |_, _, w| reply(w),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        let expected = vec![
+            (
+                1,
+                "alice".to_owned(),
+                Some("alice@example.com".to_owned()),
+                1,
+                "admin".to_owned(),
+            ),
+            (u64::MAX, "bob".to_owned(), None, 0, "guest".to_owned()),
+        ];
+
+        let rows: Vec<(u64, String, Option<String>, u8, String)> =
+            db.query("SELECT * FROM users").unwrap();
+        assert_eq!(rows, expected);
+
+        let rows: Vec<(u64, String, Option<String>, u8, String)> =
+            db.exec("SELECT * FROM users", ()).unwrap();
+        assert_eq!(rows, expected);
+    })
+}
+
+#[test]
+fn multi_result() {
+    TestingShim::new(
+        |_, w| {
+            let cols = &[Column {
+                table: String::new(),
+                column: "a".to_owned(),
+                coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
+                colflags: myc::constants::ColumnFlags::empty(),
+            }];
+            let mut row = w.start(cols)?;
+            row.write_col(1024i16)?;
+            let w = row.finish_one()?;
+            let mut row = w.start(cols)?;
+            row.write_col(1025i16)?;
+            row.finish()
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        let mut result = db
+            .query_iter("SELECT a FROM foo; SELECT a FROM foo")
+            .unwrap();
+        let mut set = result.next_set().unwrap().unwrap();
+        let row1: Vec<_> = set
+            .by_ref()
+            .filter_map(|row| row.unwrap().get::<i16, _>(0))
+            .collect();
+        assert_eq!(row1, vec![1024]);
+        drop(set);
+        let mut set = result.next_set().unwrap().unwrap();
+        let row2: Vec<_> = set
+            .by_ref()
+            .filter_map(|row| row.unwrap().get::<i16, _>(0))
+            .collect();
+        assert_eq!(row2, vec![1025]);
+        drop(set);
+        assert!(result.next_set().is_none());
+    })
+}
+
+#[test]
+fn multi_statement() {
+    TestingShim::new(
+        |q, w| {
+            let cols = &[Column {
+                table: String::new(),
+                column: "a".to_owned(),
+                coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
+                colflags: myc::constants::ColumnFlags::empty(),
+            }];
+            match q {
+                "SELECT a FROM foo" => {
+                    let mut row = w.start(cols)?;
+                    row.write_col(1024i16)?;
+                    row.finish()
+                }
+                "INSERT INTO foo VALUES ('a;b')" => w.completed(1, 0),
+                _ => unreachable!(),
+            }
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |schema, writer| {
+            assert_eq!(schema, "test");
+            writer.ok()
+        },
+    )
+    .with_multi_statements()
+    .test(|db| {
+        let mut result = db
+            .query_iter("USE test; INSERT INTO foo VALUES ('a;b'); SELECT a FROM foo;")
+            .unwrap();
+        let set = result.next_set().unwrap().unwrap();
+        assert_eq!(set.affected_rows(), 0);
+        drop(set);
+        let set = result.next_set().unwrap().unwrap();
+        assert_eq!(set.affected_rows(), 1);
+        drop(set);
+        let mut set = result.next_set().unwrap().unwrap();
+        let rows: Vec<_> = set
+            .by_ref()
+            .filter_map(|row| row.unwrap().get::<i16, _>(0))
+            .collect();
+        assert_eq!(rows, vec![1024]);
+        drop(set);
+        assert!(result.next_set().is_none());
+    })
+}
+
+#[test]
+fn multi_statement_stops_at_error() {
+    TestingShim::new(
+        |q, w| match q {
+            "SELECT a FROM foo" => w.error(ErrorKind::ER_NO_SUCH_TABLE, b"no such table"),
+            "DELETE FROM foo" => w.completed(2, 0),
+            _ => unreachable!(),
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_multi_statements()
+    .test(|db| {
+        match db.query_iter("SELECT a FROM foo; DROP TABLE foo") {
+            Err(mysql::Error::MySqlError(e)) => {
+                assert_eq!(e.code, ErrorKind::ER_NO_SUCH_TABLE as u16)
+            }
+            _ => unreachable!(),
+        }
+        // the DROP TABLE was never executed, so the next query gets the next response
+        assert_eq!(db.query_iter("DELETE FROM foo").unwrap().affected_rows(), 2);
+    })
+}
+
+#[test]
+fn it_sets_multi_statement_option() {
+    // a shim that does not split queries cannot have splitting turned on
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test_raw(|conn| {
+        conn.send(0, b"\x1b\x00\x00"); // MYSQL_OPTION_MULTI_STATEMENTS_ON
+        assert_eq!(conn.recv()[0], 0xFF);
+        conn.send(0, b"\x1b\x01\x00"); // MYSQL_OPTION_MULTI_STATEMENTS_OFF
+        assert_eq!(conn.recv()[0], 0xFE);
+    });
+
     TestingShim::new(
-        move |_, w| w.start(&cols[..])?.finish(),
+        |_, _| unreachable!(),
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
-    .test(|db| {
-        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
-    })
+    .with_multi_statements()
+    .test_raw(|conn| {
+        conn.send(0, b"\x1b\x00\x00");
+        let eof = conn.recv();
+        assert_eq!(eof[0], 0xFE);
+        let status = u16::from_le_bytes([eof[3], eof[4]]);
+        assert_eq!(status, StatusFlags::SERVER_STATUS_AUTOCOMMIT.bits());
+    });
 }
 
 #[test]
-fn no_columns() {
+fn it_kills_queries() {
+    let (started, start) = mpsc::channel();
     TestingShim::new(
-        move |_, w| w.start(&[])?.finish(),
+        move |_, w| {
+            let token = w.cancellation_token();
+            started.send(()).unwrap();
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            w.completed(0, 0)
+        },
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
     .test(|db| {
-        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+        let id = db.connection_id();
+        let killer = thread::spawn(move || {
+            start.recv().unwrap();
+            TestingShim::new(
+                |_, _| unreachable!(),
+                |_| unreachable!(),
+                |_, _, _| unreachable!(),
+                |_, _| unreachable!(),
+            )
+            .test(|db| {
+                match db.query_drop("KILL QUERY 4000000000") {
+                    Err(mysql::Error::MySqlError(e)) => {
+                        assert_eq!(e.code, ErrorKind::ER_NO_SUCH_THREAD as u16)
+                    }
+                    _ => unreachable!(),
+                }
+                db.query_drop(format!("KILL QUERY {}", id)).unwrap();
+            })
+        });
+
+        match db.query_drop("SELECT SLEEP(100)") {
+            Err(mysql::Error::MySqlError(e)) => {
+                assert_eq!(e.code, ErrorKind::ER_QUERY_INTERRUPTED as u16)
+            }
+            _ => unreachable!(),
+        }
+        killer.join().unwrap();
     })
 }
 
 #[test]
-fn no_columns_but_rows() {
+fn it_only_kills_connections_of_the_same_user() {
     TestingShim::new(
-        move |_, w| w.start(&[])?.write_col(42).map(|_| ()),
+        |_, w| w.completed(0, 0),
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
     .test(|db| {
-        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
+        let id = db.connection_id();
+        thread::spawn(move || {
+            TestingShim::new(
+                |_, _| unreachable!(),
+                |_| unreachable!(),
+                |_, _, _| unreachable!(),
+                |_, _| unreachable!(),
+            )
+            .test_raw(|conn| {
+                // logged in as another user, this connection can neither see nor kill the first
+                conn.send(0, &[myc::constants::Command::COM_PROCESS_INFO as u8]);
+                let (_, rows) = conn.recv_resultset();
+                assert!(rows.iter().all(|row| row.windows(3).any(|w| w == b"jon")));
+
+                let mut kill = vec![myc::constants::Command::COM_PROCESS_KILL as u8];
+                kill.extend(&id.to_le_bytes());
+                conn.send(0, &kill);
+                let err = conn.recv();
+                assert_eq!(err[0], 0xFF);
+                assert_eq!(
+                    u16::from_le_bytes([err[1], err[2]]),
+                    ErrorKind::ER_KILL_DENIED_ERROR as u16
+                );
+            })
+        })
+        .join()
+        .unwrap();
+
+        match db.query_drop(format!("KILL {}", id + 1_000_000)) {
+            Err(mysql::Error::MySqlError(e)) => {
+                assert_eq!(e.code, ErrorKind::ER_NO_SUCH_THREAD as u16)
+            }
+            _ => unreachable!(),
+        }
+        db.query_drop("SELECT 1").unwrap();
     })
 }
 
 #[test]
-fn error_response() {
-    let err = (ErrorKind::ER_NO, "clearly not");
+fn it_loads_local_files() {
     TestingShim::new(
-        move |_, w| w.error(err.0, err.1.as_bytes()),
+        |q, mut w| {
+            if q.starts_with("LOAD DATA") {
+                let mut data = String::new();
+                w.request_local_file("rows.csv")?
+                    .unwrap()
+                    .read_to_string(&mut data)?;
+                assert_eq!(data, "1,a\n2,b\n3,c\n");
+                w.completed(data.lines().count() as u64, 0)
+            } else {
+                // replies before the whole file has been read, so the rest has to be skipped
+                let mut first = [0; 2];
+                w.request_local_file("rows.csv")?
+                    .unwrap()
+                    .read_exact(&mut first)?;
+                assert_eq!(&first, b"1,");
+                w.error(ErrorKind::ER_NOT_ALLOWED_COMMAND, b"not today")
+            }
+        },
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
     .test(|db| {
-        if let mysql::Error::MySqlError(e) = db.query_iter("SELECT a, b FROM foo").unwrap_err() {
-            assert_eq!(
-                e,
-                mysql::error::MySqlError {
-                    state: String::from_utf8(err.0.sqlstate().to_vec()).unwrap(),
-                    message: err.1.to_owned(),
-                    code: err.0 as u16,
-                }
-            );
-        } else {
-            unreachable!();
+        db.set_local_infile_handler(Some(mysql::LocalInfileHandler::new(|name, file| {
+            assert_eq!(name, b"rows.csv");
+            file.write_all(b"1,a\n2,b\n")?;
+            file.write_all(b"3,c\n")
+        })));
+        db.query_drop("LOAD DATA LOCAL INFILE 'rows.csv' INTO TABLE foo")
+            .unwrap();
+        assert_eq!(db.affected_rows(), 3);
+
+        match db.query_drop("ANOTHER LOAD") {
+            Err(mysql::Error::MySqlError(e)) => {
+                assert_eq!(e.code, ErrorKind::ER_NOT_ALLOWED_COMMAND as u16)
+            }
+            _ => unreachable!(),
         }
+        db.query_drop("LOAD DATA LOCAL INFILE 'rows.csv' INTO TABLE foo")
+            .unwrap();
+        assert_eq!(db.affected_rows(), 3);
     })
 }
 
 #[test]
-fn empty_on_drop() {
-    let cols = [Column {
-        table: String::new(),
-        column: "a".to_owned(),
-        coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
-        colflags: myc::constants::ColumnFlags::empty(),
-    }];
-    TestingShim::new(
-        move |_, w| w.start(&cols[..]).map(|_| ()),
+fn it_skips_local_files_when_the_client_disconnects() {
+    let shim = TestingShim::new(
+        |_, mut w| {
+            // gives up on the file without replying, and so leaves the rest of it to the server
+            let mut first = [0; 2];
+            w.request_local_file("rows.csv")?
+                .unwrap()
+                .read_exact(&mut first)?;
+            assert_eq!(&first, b"1,");
+            Ok(())
+        },
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
-    )
-    .test(|db| {
-        assert_eq!(db.query_iter("SELECT a, b FROM foo").unwrap().count(), 0);
-    })
+    );
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let jh = thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        MysqlIntermediary::run_on_tcp(shim, s)
+    });
+
+    let caps = myc::constants::CapabilityFlags::CLIENT_LOCAL_FILES;
+    let mut conn = RawConn::connect(port, caps.bits());
+    conn.send(0, b"\x03LOAD DATA LOCAL INFILE 'rows.csv' INTO TABLE foo");
+    assert_eq!(conn.recv(), b"\xFBrows.csv");
+    conn.send(2, b"1,a\n");
+    // disconnects in the middle of the next packet of the file
+    conn.0.write_all(&[0x10, 0x00, 0x00, 0x03, b'2']).unwrap();
+    drop(conn);
+
+    // the server gives up on the connection, without panicking
+    assert!(jh.join().unwrap().is_err());
 }
 
 #[test]
-fn it_queries_nulls() {
+fn it_only_requests_local_files_if_allowed() {
     TestingShim::new(
-        |_, w| {
-            let cols = &[Column {
-                table: String::new(),
-                column: "a".to_owned(),
-                coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
-                colflags: myc::constants::ColumnFlags::empty(),
-            }];
-            let mut w = w.start(cols)?;
-            w.write_col(None::<i16>)?;
-            w.finish()
+        |_, mut w| {
+            assert!(w.request_local_file("rows.csv")?.is_none());
+            w.error(ErrorKind::ER_NOT_ALLOWED_COMMAND, b"not allowed")
         },
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
-    .test(|db| {
-        let row = db
-            .query_iter("SELECT a, b FROM foo")
-            .unwrap()
-            .next()
-            .unwrap()
-            .unwrap();
-        assert_eq!(row.as_ref(0), Some(&mysql::Value::NULL));
+    .test_raw(|conn| {
+        conn.send(0, b"\x03LOAD DATA LOCAL INFILE 'rows.csv' INTO TABLE foo");
+        assert_eq!(conn.recv()[0], 0xFF);
     })
 }
 
 #[test]
-fn it_queries() {
+fn it_closes_statements_on_quit() {
+    let (disconnected, disconnect) = mpsc::channel();
+    let mut next_id = 0;
     TestingShim::new(
-        |_, w| {
-            let cols = &[Column {
-                table: String::new(),
-                column: "a".to_owned(),
-                coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
-                colflags: myc::constants::ColumnFlags::empty(),
-            }];
-            let mut w = w.start(cols)?;
-            w.write_col(1024i16)?;
-            w.finish()
+        |_, _| unreachable!(),
+        move |_| {
+            next_id += 1;
+            next_id
         },
-        |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
+    .with_disconnect(disconnected)
     .test(|db| {
-        let row = db
-            .query_iter("SELECT a, b FROM foo")
-            .unwrap()
-            .next()
-            .unwrap()
-            .unwrap();
-        assert_eq!(row.get::<i16, _>(0), Some(1024));
-    })
+        db.prep("SELECT 1").unwrap();
+        db.prep("SELECT 2").unwrap();
+    });
+
+    let (mut closed, reason) = disconnect.recv().unwrap();
+    closed.sort();
+    assert_eq!(closed, vec![1, 2]);
+    assert_eq!(reason, DisconnectReason::Quit);
 }
 
 #[test]
-fn multi_result() {
+fn it_closes_statements_when_the_client_goes_away() {
+    let (disconnected, disconnect) = mpsc::channel();
     TestingShim::new(
-        |_, w| {
-            let cols = &[Column {
-                table: String::new(),
-                column: "a".to_owned(),
-                coltype: myc::constants::ColumnType::MYSQL_TYPE_SHORT,
-                colflags: myc::constants::ColumnFlags::empty(),
-            }];
-            let mut row = w.start(cols)?;
-            row.write_col(1024i16)?;
-            let w = row.finish_one()?;
-            let mut row = w.start(cols)?;
-            row.write_col(1025i16)?;
-            row.finish()
-        },
+        |_, _| unreachable!(),
+        |_| 7,
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_disconnect(disconnected)
+    .test_raw(|conn| {
+        conn.send(0, b"\x16SELECT 1");
+        assert_eq!(conn.recv()[0], 0x00);
+    });
+
+    assert_eq!(
+        disconnect.recv().unwrap(),
+        (vec![7], DisconnectReason::Closed)
+    );
+}
+
+#[test]
+fn it_disconnects_when_the_handshake_fails() {
+    let (disconnected, disconnect) = mpsc::channel();
+    let shim = TestingShim::new(
+        |_, _| unreachable!(),
         |_| unreachable!(),
         |_, _, _| unreachable!(),
         |_, _| unreachable!(),
     )
-    .test(|db| {
-        let mut result = db
-            .query_iter("SELECT a FROM foo; SELECT a FROM foo")
-            .unwrap();
-        let mut set = result.next_set().unwrap().unwrap();
-        let row1: Vec<_> = set
-            .by_ref()
-            .filter_map(|row| row.unwrap().get::<i16, _>(0))
-            .collect();
-        assert_eq!(row1, vec![1024]);
-        drop(set);
-        let mut set = result.next_set().unwrap().unwrap();
-        let row2: Vec<_> = set
-            .by_ref()
-            .filter_map(|row| row.unwrap().get::<i16, _>(0))
-            .collect();
-        assert_eq!(row2, vec![1025]);
-        drop(set);
-        assert!(result.next_set().is_none());
-    })
+    .with_disconnect(disconnected);
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let jh = thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        MysqlIntermediary::run_on_tcp(shim, s)
+    });
+
+    // hang up as soon as the server has said hello
+    let mut s = net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    assert_ne!(s.read(&mut [0; 128]).unwrap(), 0);
+    drop(s);
+
+    assert!(jh.join().unwrap().is_err());
+    assert_eq!(
+        disconnect.recv().unwrap(),
+        (vec![], DisconnectReason::Error)
+    );
 }
 
 #[test]
@@ -600,6 +2468,50 @@ fn insert_exec() {
     })
 }
 
+#[test]
+fn it_bulk_executes() {
+    TestingShim::new(
+        |_, _| unreachable!(),
+        |_| 41,
+        |stmt, params, w| {
+            assert_eq!(stmt, 41);
+            let id = match params.into_iter().next().unwrap().value.into_inner() {
+                ValueInner::Int(id) => id as u64,
+                ValueInner::NULL => 0,
+                _ => unreachable!(),
+            };
+            w.completed(1, id)
+        },
+        |_, _| unreachable!(),
+    )
+    .with_params(vec![Column {
+        table: String::new(),
+        column: "a".to_owned(),
+        coltype: myc::constants::ColumnType::MYSQL_TYPE_LONGLONG,
+        colflags: myc::constants::ColumnFlags::empty(),
+    }])
+    .test_raw(|conn| {
+        conn.send(0, b"\x16INSERT INTO foo VALUES (?)");
+        let prepared = conn.recv();
+        assert_eq!(&prepared[..5], &[0x00, 41, 0, 0, 0]);
+        conn.recv(); // the parameter
+        assert_eq!(conn.recv()[0], 0xFE);
+
+        let mut bulk = vec![0xFA];
+        bulk.extend(&41u32.to_le_bytes());
+        bulk.extend(&128u16.to_le_bytes()); // parameter types are included
+        bulk.extend(&[myc::constants::ColumnType::MYSQL_TYPE_LONGLONG as u8, 0]);
+        bulk.push(1); // NULL
+        for id in &[7i64, 8] {
+            bulk.push(0);
+            bulk.extend(&id.to_le_bytes());
+        }
+        conn.send(0, &bulk);
+        // all three rows were inserted, and the first generated id was 7
+        assert_eq!(&conn.recv()[..3], &[0x00, 3, 7]);
+    })
+}
+
 #[test]
 fn send_long() {
     let cols = vec![Column {
@@ -702,6 +2614,231 @@ fn it_prepares_many() {
     })
 }
 
+#[test]
+fn it_emulates_prepared_statements() {
+    let cols = vec![Column {
+        table: String::new(),
+        column: "a".to_owned(),
+        coltype: myc::constants::ColumnType::MYSQL_TYPE_VAR_STRING,
+        colflags: myc::constants::ColumnFlags::empty(),
+    }];
+
+    TestingShim::new(
+        move |q, w| {
+            assert_eq!(
+                q,
+                "SELECT a FROM t WHERE b = 42 AND c = 'it''s' AND d IS NULL AND e = '?'"
+            );
+            let mut w = w.start(&cols)?;
+            w.write_row(["x"])?;
+            w.finish()
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .with_emulated_statements()
+    .test(|db| {
+        let query = "SELECT a FROM t WHERE b = ? AND c = ? AND d IS ? AND e = '?'";
+        let stmt = db.prep(query).unwrap();
+        assert_eq!(stmt.num_params(), 3);
+        let rows: Vec<String> = db.exec(&stmt, (42, "it's", None::<i32>)).unwrap();
+        assert_eq!(rows, vec!["x".to_owned()]);
+        db.close(stmt).unwrap();
+    })
+}
+
+#[test]
+fn it_wraps_text_only_shims() {
+    struct TextShim;
+
+    impl<W: io::Write> MysqlShim<W> for TextShim {
+        type Error = io::Error;
+
+        fn on_query(&mut self, query: &str, results: QueryResultWriter<'_, W>) -> io::Result<()> {
+            assert_eq!(query, r"DELETE FROM t WHERE a = 'it''s \\' OR b = 1");
+            results.completed(1, 0)
+        }
+    }
+
+    let serve = |wrap: bool| {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let jh = thread::spawn(move || {
+            let (s, _) = listener.accept().unwrap();
+            if wrap {
+                MysqlIntermediary::run_on_tcp(EmulatedShim::new(TextShim), s)
+            } else {
+                MysqlIntermediary::run_on_tcp(TextShim, s)
+            }
+        });
+        let db = mysql::Conn::new(format!("mysql://127.0.0.1:{}", port)).unwrap();
+        (db, jh)
+    };
+
+    let (mut db, jh) = serve(false);
+    match db.prep("DELETE FROM t WHERE a = ?").unwrap_err() {
+        mysql::Error::MySqlError(e) => assert_eq!(e.code, ErrorKind::ER_UNSUPPORTED_PS as u16),
+        e => panic!("unexpected error: {:?}", e),
+    }
+    drop(db);
+    jh.join().unwrap().unwrap();
+
+    let (mut db, jh) = serve(true);
+    let stmt = db.prep("DELETE FROM t WHERE a = ? OR b = ?").unwrap();
+    assert_eq!(stmt.num_params(), 2);
+    let deleted = db.exec_iter(&stmt, (r"it's \", 1)).unwrap().affected_rows();
+    assert_eq!(deleted, 1);
+    db.close(stmt).unwrap();
+    drop(db);
+    jh.join().unwrap().unwrap();
+}
+
+#[test]
+fn it_bulk_executes_emulated_statements() {
+    struct TextShim;
+
+    impl<W: io::Write> MysqlShim<W> for TextShim {
+        type Error = io::Error;
+
+        fn on_query(&mut self, query: &str, results: QueryResultWriter<'_, W>) -> io::Result<()> {
+            assert!(query == "INSERT INTO t VALUES (7)" || query == "INSERT INTO t VALUES (8)");
+            results.completed(1, 0)
+        }
+    }
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let jh = thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        MysqlIntermediary::run_on_tcp(EmulatedShim::new(TextShim), s)
+    });
+
+    let mut conn = RawConn::connect(port, 0);
+    conn.send(0, b"\x16INSERT INTO t VALUES (?)");
+    let prepared = conn.recv();
+    assert_eq!(&prepared[..5], &[0x00, 1, 0, 0, 0]);
+    conn.recv(); // the parameter
+    assert_eq!(conn.recv()[0], 0xFE);
+
+    let mut bulk = vec![0xFA];
+    bulk.extend(&1u32.to_le_bytes());
+    bulk.extend(&128u16.to_le_bytes()); // parameter types are included
+    bulk.extend(&[myc::constants::ColumnType::MYSQL_TYPE_LONGLONG as u8, 0]);
+    for id in &[7i64, 8] {
+        bulk.push(0);
+        bulk.extend(&id.to_le_bytes());
+    }
+    conn.send(0, &bulk);
+    assert_eq!(&conn.recv()[..2], &[0x00, 2]);
+    drop(conn);
+    jh.join().unwrap().unwrap();
+}
+
+#[test]
+fn it_reports_warnings() {
+    TestingShim::new(
+        |q, mut w| {
+            if q.starts_with("INSERT") {
+                w.add_warning(
+                    WarningLevel::Note,
+                    ErrorKind::ER_DUP_ENTRY,
+                    "Duplicate entry '1' for key 'PRIMARY'",
+                );
+                w.add_warning(
+                    WarningLevel::Warning,
+                    ErrorKind::ER_WARN_DATA_OUT_OF_RANGE,
+                    "Out of range value for column 'b' at row 1",
+                );
+                w.completed(1, 0)
+            } else {
+                w.error(ErrorKind::ER_NO_SUCH_TABLE, b"Table 'db.t' doesn't exist")
+            }
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        db.query_drop("INSERT INTO t VALUES (1, 1000)").unwrap();
+        assert_eq!(db.warnings(), 2);
+
+        let count: Option<u64> = db.query_first("SHOW COUNT(*) WARNINGS").unwrap();
+        assert_eq!(count, Some(2));
+        let warnings: Vec<(String, u32, String)> = db.query("SHOW WARNINGS").unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    "Note".to_owned(),
+                    1062,
+                    "Duplicate entry '1' for key 'PRIMARY'".to_owned()
+                ),
+                (
+                    "Warning".to_owned(),
+                    1264,
+                    "Out of range value for column 'b' at row 1".to_owned()
+                ),
+            ]
+        );
+        let warnings: Vec<(String, u32, String)> = db.query("SHOW WARNINGS LIMIT 1, 1").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].1, 1264);
+        let errors: Vec<(String, u32, String)> = db.query("SHOW ERRORS").unwrap();
+        assert!(errors.is_empty());
+
+        assert!(db.query_drop("SELECT a FROM t").is_err());
+        let errors: Vec<(String, u32, String)> = db.query("SHOW ERRORS").unwrap();
+        assert_eq!(
+            errors,
+            vec![(
+                "Error".to_owned(),
+                1146,
+                "Table 'db.t' doesn't exist".to_owned()
+            )]
+        );
+    })
+}
+
+#[test]
+fn it_sends_completion_info() {
+    TestingShim::new(
+        |q, mut w| {
+            if q.starts_with("UPDATE") {
+                w.add_warning(
+                    WarningLevel::Warning,
+                    ErrorKind::ER_TRUNCATED_WRONG_VALUE,
+                    "Truncated incorrect DOUBLE value: 'x'",
+                );
+                w.completed_with(Completion {
+                    rows: 2,
+                    info: "Rows matched: 3  Changed: 2  Warnings: 2".to_owned(),
+                    warnings: 1,
+                    status: StatusFlags::SERVER_STATUS_NO_INDEX_USED,
+                    ..Default::default()
+                })
+            } else {
+                w.completed(1, 42)
+            }
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!(),
+        |_, _| unreachable!(),
+    )
+    .test(|db| {
+        db.query_drop("UPDATE t SET a = 'x'").unwrap();
+        assert_eq!(db.affected_rows(), 2);
+        assert_eq!(db.warnings(), 2);
+        assert_eq!(db.info_str(), "Rows matched: 3  Changed: 2  Warnings: 2");
+
+        db.query_drop("INSERT INTO t VALUES (1)").unwrap();
+        assert_eq!(db.affected_rows(), 1);
+        assert_eq!(db.last_insert_id(), 42);
+        assert_eq!(db.warnings(), 0);
+        assert_eq!(db.info_str(), "");
+    })
+}
+
 #[test]
 fn prepared_empty() {
     let cols = vec![Column {